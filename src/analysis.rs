@@ -0,0 +1,400 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    Text,
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub line: usize,
+    pub segment: Segment,
+    pub labels: Vec<String>,
+    pub mnemonic: Option<String>,
+    pub operands: Vec<String>,
+    pub comment: Option<String>,
+}
+
+impl Statement {
+    pub fn is_directive(&self) -> bool {
+        matches!(&self.mnemonic, Some(m) if m.starts_with('.'))
+    }
+
+    pub fn is_instruction(&self) -> bool {
+        self.mnemonic.is_some() && !self.is_directive()
+    }
+
+    pub fn is_comment_only(&self) -> bool {
+        self.labels.is_empty() && self.mnemonic.is_none() && self.comment.is_some()
+    }
+}
+
+// Index of the `#` starting a comment, skipping any inside string or char literals
+pub fn comment_start(line: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return Some(i),
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+pub fn split_operands(operands: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut current = String::new();
+
+    for c in operands.chars() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ',') => {
+                parts.push(current.trim().to_string());
+                current = String::new();
+                continue;
+            }
+            (None, _) => {}
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() || !parts.is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+fn is_label_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '$')
+}
+
+fn take_label(code: &str) -> Option<(&str, &str)> {
+    let end = code.find(|c: char| !is_label_char(c))?;
+    if end > 0 && code[end..].starts_with(':') {
+        Some((&code[..end], code[(end + 1)..].trim_start()))
+    } else {
+        None
+    }
+}
+
+fn segment_of(directive: &str) -> Option<Segment> {
+    match directive {
+        ".text" | ".ktext" => Some(Segment::Text),
+        ".data" | ".kdata" => Some(Segment::Data),
+        _ => None,
+    }
+}
+
+/// Splits a file into statements: the labels, mnemonic (or directive),
+/// operands and comment of each non-blank line.
+pub fn parse(contents: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut segment = Segment::Text;
+
+    for (i, raw) in contents.lines().enumerate() {
+        let (mut code, comment) = match comment_start(raw) {
+            Some(hash) => (
+                raw[..hash].trim(),
+                Some(raw[(hash + 1)..].trim().to_string()),
+            ),
+            None => (raw.trim(), None),
+        };
+
+        let mut labels = Vec::new();
+        while let Some((label, rest)) = take_label(code) {
+            labels.push(label.to_string());
+            code = rest;
+        }
+
+        let (mnemonic, operands) = match code.split_once(char::is_whitespace) {
+            Some((mnemonic, rest)) => (Some(mnemonic.to_string()), split_operands(rest)),
+            None if code.is_empty() => (None, Vec::new()),
+            None => (Some(code.to_string()), Vec::new()),
+        };
+
+        if let Some(seg) = mnemonic.as_deref().and_then(segment_of) {
+            segment = seg;
+        }
+
+        if labels.is_empty() && mnemonic.is_none() && comment.is_none() {
+            continue;
+        }
+
+        statements.push(Statement {
+            line: i + 1,
+            segment,
+            labels,
+            mnemonic,
+            operands,
+            comment,
+        });
+    }
+
+    statements
+}
+
+/// Parses a decimal or `0x` hexadecimal integer literal.
+pub fn parse_int(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Splits a GAS relocation operand like `%hi(sym)` into `hi` or `lo` and the
+/// symbol it applies to.
+pub fn relocation(operand: &str) -> Option<(&str, &str)> {
+    let (operator, inner) = operand.strip_prefix('%')?.split_once('(')?;
+    let inner = inner.strip_suffix(')')?.trim();
+    match operator.trim() {
+        operator @ ("hi" | "lo") if !inner.is_empty() => Some((operator, inner)),
+        _ => None,
+    }
+}
+
+/// Size in bytes of each value a data directive lays out.
+pub fn element_size(directive: &str) -> Option<i64> {
+    match directive {
+        ".double" => Some(8),
+        ".word" | ".float" => Some(4),
+        ".half" => Some(2),
+        ".byte" => Some(1),
+        _ => None,
+    }
+}
+
+// Bytes in the string literals of an `.ascii`, each escape counting as one
+fn ascii_len(operands: &[String]) -> Option<i64> {
+    let mut len = 0;
+    for operand in operands {
+        let text = operand.strip_prefix('"')?.strip_suffix('"')?;
+        let mut escaped = false;
+        for c in text.chars() {
+            match (escaped, c) {
+                (false, '\\') => escaped = true,
+                _ => {
+                    escaped = false;
+                    len += c.len_utf8() as i64;
+                }
+            }
+        }
+    }
+    Some(len)
+}
+
+/// The alignment and size in bytes of a data directive with its operands.
+pub fn data_layout(directive: &str, operands: &[String]) -> Option<(i64, i64)> {
+    if let Some(size) = element_size(directive) {
+        let count: Option<i64> = operands
+            .iter()
+            .map(|op| match op.split_once(':') {
+                Some((_, count)) => parse_int(count.trim()),
+                None => Some(1),
+            })
+            .sum();
+        return Some((size, size * count?));
+    }
+    match (directive, operands) {
+        (".space", [bytes]) => Some((1, parse_int(bytes)?)),
+        (".ascii", _) => Some((1, ascii_len(operands)?)),
+        (".asciiz", _) => Some((1, ascii_len(operands)? + operands.len() as i64)),
+        _ => None,
+    }
+}
+
+/// The `.eqv` constants with integer values.
+pub fn eqvs(statements: &[Statement]) -> Vec<(String, i64)> {
+    statements
+        .iter()
+        .filter(|s| s.mnemonic.as_deref() == Some(".eqv"))
+        .filter_map(|s| {
+            let definition = s.operands.join(",");
+            let (name, value) = definition.split_once(char::is_whitespace)?;
+            Some((name.to_string(), parse_int(value.trim())?))
+        })
+        .collect()
+}
+
+/// Every label-like word in a statement's operands.
+pub fn references(statement: &Statement) -> Vec<&str> {
+    statement
+        .operands
+        .iter()
+        .flat_map(|op| op.split(|c| !is_label_char(c)))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Labels that start a procedure rather than a loop or branch target: `main`
+/// and anything called with `jal` or declared `.globl`.
+pub fn procedure_labels(statements: &[Statement]) -> Vec<String> {
+    let mut labels = vec![String::from("main")];
+    for statement in statements {
+        if let Some("jal" | ".globl" | ".global") = statement.mnemonic.as_deref() {
+            labels.extend(references(statement).into_iter().map(String::from));
+        }
+    }
+    labels
+}
+
+/// Percentage of text-segment instructions that have a trailing comment or
+/// directly follow a comment line.
+pub fn comment_coverage(statements: &[Statement]) -> usize {
+    let mut total = 0;
+    let mut commented = 0;
+    let mut prev: Option<&Statement> = None;
+
+    for statement in statements {
+        if statement.segment == Segment::Text && statement.is_instruction() {
+            total += 1;
+            let after_comment =
+                matches!(prev, Some(p) if p.is_comment_only() && p.line + 1 == statement.line);
+            if statement.comment.is_some() || after_comment {
+                commented += 1;
+            }
+        }
+        prev = Some(statement);
+    }
+
+    match total {
+        0 => 100,
+        _ => commented * 100 / total,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub line: usize,
+    pub params: Vec<String>,
+    // Indices of the body's statements, excluding `.macro` and `.end_macro`
+    pub body: std::ops::Range<usize>,
+}
+
+/// Finds the `%name` macro parameters referenced in some text, skipping the
+/// `%hi(...)` and `%lo(...)` relocation operators.
+pub fn macro_params(text: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('%') {
+        let after = &rest[(start + 1)..];
+        let len = after
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..len];
+        let relocation = matches!(name, "hi" | "lo") && after[len..].starts_with('(');
+
+        if len > 0 && !relocation {
+            params.push(format!("%{}", name));
+        }
+        rest = &after[len..];
+    }
+
+    params
+}
+
+pub fn macros(statements: &[Statement]) -> Vec<Macro> {
+    let mut macros = Vec::new();
+    let mut open: Option<Macro> = None;
+
+    for (i, statement) in statements.iter().enumerate() {
+        match statement.mnemonic.as_deref() {
+            Some(".macro") => {
+                let header = statement.operands.join(", ");
+                let name = header
+                    .split(|c: char| c.is_whitespace() || c == '(' || c == ',')
+                    .next()
+                    .unwrap_or("")
+                    .to_string();
+                open = Some(Macro {
+                    name,
+                    line: statement.line,
+                    params: macro_params(&header),
+                    body: (i + 1)..(i + 1),
+                });
+            }
+            Some(".end_macro") => {
+                if let Some(mut m) = open.take() {
+                    m.body.end = i;
+                    macros.push(m);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    macros
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Indent {
+    #[default]
+    None,
+    Tabs,
+    Spaces,
+    Mixed,
+}
+
+pub fn indent_of(line: &str) -> Indent {
+    let leading = &line[..(line.len() - line.trim_start().len())];
+    match (leading.contains('\t'), leading.contains(' ')) {
+        _ if line.trim().is_empty() => Indent::None,
+        (true, true) => Indent::Mixed,
+        (true, false) => Indent::Tabs,
+        (false, true) => Indent::Spaces,
+        (false, false) => Indent::None,
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct IndentHealth {
+    pub tabs: usize,
+    pub spaces: usize,
+    pub mixed: usize,
+}
+
+impl IndentHealth {
+    pub fn of(contents: &str) -> Self {
+        let mut health = IndentHealth::default();
+        for line in contents.lines() {
+            match indent_of(line) {
+                Indent::Tabs => health.tabs += 1,
+                Indent::Spaces => health.spaces += 1,
+                Indent::Mixed => health.mixed += 1,
+                Indent::None => {}
+            }
+        }
+        health
+    }
+}
+
+impl std::fmt::Display for IndentHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} tab-indented, {} space-indented, {} mixed",
+            self.tabs, self.spaces, self.mixed
+        )
+    }
+}
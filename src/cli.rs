@@ -0,0 +1,113 @@
+/// An option the command line accepts, under one or more names.
+pub struct Flag {
+    pub names: &'static [&'static str],
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+impl Flag {
+    /// The name the flag is reported under, its first long name.
+    pub fn name(&self) -> &'static str {
+        self.names
+            .iter()
+            .find(|n| n.starts_with("--"))
+            .unwrap_or(&self.names[0])
+    }
+}
+
+/// Which outcomes of a run make it exit with status 1. Each also fails on
+/// everything the ones before it do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailOn {
+    Never,
+    Error,
+    Warning,
+    Change,
+}
+
+impl FailOn {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "never" => Some(FailOn::Never),
+            "error" => Some(FailOn::Error),
+            "warning" => Some(FailOn::Warning),
+            "change" => Some(FailOn::Change),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Arg<'a> {
+    Flag(&'static str, Option<&'a str>),
+    Positional(&'a str),
+}
+
+fn valid_flags(flags: &[Flag]) -> String {
+    flags
+        .iter()
+        .flat_map(|f| f.names.iter().copied())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+/// Splits `args` into flags, named by `Flag::name`, and positional arguments.
+/// Values can follow a flag or be attached with `=`, and `--` ends the flags.
+pub fn parse<'a>(args: &'a [String], flags: &[Flag]) -> Result<Vec<Arg<'a>>, String> {
+    let mut parsed = Vec::new();
+    let mut args = args.iter().map(|a| a.as_str());
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.extend(args.by_ref().map(Arg::Positional));
+            break;
+        }
+        if arg == "-" || !arg.starts_with('-') {
+            parsed.push(Arg::Positional(arg));
+            continue;
+        }
+
+        let (name, attached) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        let flag = flags
+            .iter()
+            .find(|f| f.names.contains(&name))
+            .ok_or(format!(
+                "Unknown option '{}'\n       Valid options are {}",
+                name,
+                valid_flags(flags)
+            ))?;
+
+        let value = match (flag.value, attached) {
+            (None, None) => None,
+            (None, Some(_)) => return Err(format!("Option '{}' doesn't take a value", name)),
+            (Some(_), Some(value)) => Some(value),
+            (Some(metavar), None) => Some(args.next().ok_or(format!(
+                "Option '{}' expects a value, e.g. {} <{}>",
+                name, name, metavar
+            ))?),
+        };
+        parsed.push(Arg::Flag(flag.name(), value));
+    }
+
+    Ok(parsed)
+}
+
+/// Lists the flags with their help, one or two lines each.
+pub fn usage(flags: &[Flag]) -> String {
+    flags
+        .iter()
+        .map(|flag| {
+            let mut names = flag.names.join(", ");
+            if let Some(metavar) = flag.value {
+                names += &format!(" <{}>", metavar);
+            }
+            match names.len() < 24 {
+                true => format!("  {:24}{}\n", names, flag.help),
+                false => format!("  {}\n  {:24}{}\n", names, "", flag.help),
+            }
+        })
+        .collect()
+}
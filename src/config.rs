@@ -0,0 +1,1184 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::isa::{InstructionSet, Isa};
+
+pub static CONFIG_PATH_VAR: &str = "MACMIPS_CONFIG";
+static CONFIG_FILE_NAMES: &[&str] = &["macmips.toml", ".macmips.toml"];
+static OPTION_VAR_PREFIX: &str = "MACMIPS_";
+
+pub static CONFIG_VERSION: i64 = 2;
+
+struct Rename {
+    old: &'static str,
+    new: &'static str,
+    version: i64,
+}
+
+// Options renamed since `config_version = 1`, oldest first
+static RENAMED_OPTIONS: &[Rename] = &[Rename {
+    // It covers `.extern` declarations too
+    old: "blank_lines_around_globl",
+    new: "blank_lines_around_globals",
+    version: 2,
+}];
+
+pub static OPTIONS: &[&str] = &[
+    "address_literals",
+    "align_constants",
+    "align_matrices",
+    "annotate_data_offsets",
+    "annotate_offsets",
+    "annotate_syscalls",
+    "blank_comments",
+    "blank_line_runs",
+    "blank_lines_after_directive",
+    "blank_lines_around_globals",
+    "blank_lines_between_chunks",
+    "comment_column",
+    "comment_column_max",
+    "comment_disparity",
+    "comment_gap",
+    "comment_overflow",
+    "data_list_continuation",
+    "data_list_width",
+    "forbidden_instructions",
+    "globl_style",
+    "hex_case",
+    "indent",
+    "instruction_tables",
+    "isa",
+    "keep_together",
+    "label_case",
+    "line_endings",
+    "lint_ignore",
+    "max_file_size",
+    "max_line_length",
+    "max_lines",
+    "max_procedure_instructions",
+    "max_width",
+    "operator_spacing",
+    "preserve_tables",
+    "procedure_comments",
+    "protected_region",
+    "sort_data",
+    "trailing_comment_max",
+    "version_header",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim();
+
+        match raw {
+            "" => Err(String::from("missing value")),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ if raw.starts_with('"') || raw.starts_with('\'') => parse_string(raw).map(Value::Str),
+            _ if raw.starts_with('[') => {
+                if !raw.ends_with(']') {
+                    return Err(format!("unterminated array `{}`", raw));
+                }
+                split_top_level(&raw[1..(raw.len() - 1)])
+                    .into_iter()
+                    .filter(|item| !item.trim().is_empty())
+                    .map(Value::parse)
+                    .collect::<Result<Vec<Value>, String>>()
+                    .map(Value::Array)
+            }
+            _ => raw
+                .replace('_', "")
+                .parse::<i64>()
+                .map(Value::Int)
+                .map_err(|_| format!("invalid value `{}`", raw)),
+        }
+    }
+
+    pub fn as_bool(&self, key: &str) -> Result<bool, String> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(format!("`{}` expects true or false", key)),
+        }
+    }
+
+    pub fn as_usize(&self, key: &str) -> Result<usize, String> {
+        match self {
+            Value::Int(n) if *n >= 0 => Ok(*n as usize),
+            _ => Err(format!("`{}` expects a non-negative integer", key)),
+        }
+    }
+
+    pub fn as_str_list(&self, key: &str) -> Result<Vec<String>, String> {
+        let invalid = || format!("`{}` expects an array of strings", key);
+        match self {
+            Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Value::Str(s) => Ok(s.clone()),
+                    _ => Err(invalid()),
+                })
+                .collect(),
+            Value::Str(s) => Ok(s
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|i| i.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+fn parse_string(raw: &str) -> Result<String, String> {
+    let quote = raw.chars().next().unwrap();
+    if raw.len() < 2 || !raw.ends_with(quote) {
+        return Err(format!("unterminated string {}", raw));
+    }
+
+    let inner = &raw[1..(raw.len() - 1)];
+    if quote == '\'' {
+        return Ok(inner.into());
+    }
+
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some(other) => return Err(format!("invalid escape `\\{}`", other)),
+            None => return Err(String::from("string ends with `\\`")),
+        }
+    }
+    Ok(out)
+}
+
+// Splits on commas that are not inside quotes or nested brackets
+fn split_top_level(raw: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+
+    for (i, c) in raw.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(&raw[start..i]);
+                start = i + 1;
+            }
+            (None, _) => {}
+        }
+    }
+    parts.push(&raw[start..]);
+    parts
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            (None, _) => {}
+        }
+    }
+    line
+}
+
+/// Parses the subset of TOML used by config files: `key = value` pairs,
+/// `[table]` headers (flattened into dotted keys) and single-line arrays.
+pub fn parse_toml(contents: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut pairs = Vec::new();
+    let mut table = String::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            table = line[1..(line.len() - 1)].trim().to_string();
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => (key.trim(), value),
+            _ => return Err(format!("line {}: expected `key = value`", i + 1)),
+        };
+        let value = Value::parse(value).map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+        if table.is_empty() {
+            pairs.push((key.to_string(), value));
+        } else {
+            pairs.push((format!("{}.{}", table, key), value));
+        }
+    }
+
+    Ok(pairs)
+}
+
+fn renamed(key: &str, renames: &[Rename]) -> Option<&'static str> {
+    renames.iter().find(|r| r.old == key).map(|r| r.new)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(cur)
+            };
+            prev = cur;
+        }
+    }
+
+    row[b.len()]
+}
+
+fn suggest_option(key: &str) -> Option<&'static str> {
+    OPTIONS
+        .iter()
+        .map(|option| (edit_distance(key, option), *option))
+        .filter(|(distance, _)| *distance <= (key.len() / 3).max(1))
+        .min()
+        .map(|(_, option)| option)
+}
+
+fn unknown_option(key: &str) -> String {
+    match suggest_option(key) {
+        Some(option) => format!("unknown option `{}`, did you mean `{}`?", key, option),
+        None => format!("unknown option `{}`", key),
+    }
+}
+
+/// Rewrites a config file to use the current option names, recording each
+/// rename in a comment, and stamps it with the current `config_version`.
+pub fn migrate(contents: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut table = String::new();
+    let mut has_version = false;
+
+    for line in contents.lines() {
+        let code = strip_comment(line).trim();
+        if code.starts_with('[') && code.ends_with(']') {
+            table = code[1..(code.len() - 1)].trim().to_string();
+        }
+
+        let key = match code.split_once('=') {
+            Some((key, _)) => key.trim(),
+            None => {
+                lines.push(line.into());
+                continue;
+            }
+        };
+
+        if table.is_empty() && key == "config_version" {
+            has_version = true;
+            lines.push(format!("config_version = {}", CONFIG_VERSION));
+            continue;
+        }
+
+        let full_key = match table.as_str() {
+            "" => key.to_string(),
+            table => format!("{}.{}", table, key),
+        };
+        match RENAMED_OPTIONS.iter().find(|r| r.old == full_key) {
+            Some(rename) => {
+                let new_key = rename.new.strip_prefix(&format!("{}.", table));
+                let indent = &line[..(line.len() - line.trim_start().len())];
+                lines.push(format!(
+                    "{}# renamed from `{}` in config_version {}",
+                    indent, rename.old, rename.version
+                ));
+                lines.push(line.replacen(key, new_key.unwrap_or(rename.new), 1));
+            }
+            None => lines.push(line.into()),
+        }
+    }
+
+    if !has_version {
+        lines.insert(0, format!("config_version = {}", CONFIG_VERSION));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// What happens to lines holding only `#`, used as spacers in comment blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlankComments {
+    Keep,
+    Collapse,
+    Blank,
+}
+
+impl BlankComments {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "keep" => Some(BlankComments::Keep),
+            "collapse" => Some(BlankComments::Collapse),
+            "blank" => Some(BlankComments::Blank),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlankComments::Keep => "keep",
+            BlankComments::Collapse => "collapse",
+            BlankComments::Blank => "blank",
+        }
+    }
+}
+
+/// What happens to several blank lines in a row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlankLineRuns {
+    Collapse,
+    Preserve,
+}
+
+impl BlankLineRuns {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "collapse" => Some(BlankLineRuns::Collapse),
+            "preserve" => Some(BlankLineRuns::Preserve),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlankLineRuns::Collapse => "collapse",
+            BlankLineRuns::Preserve => "preserve",
+        }
+    }
+}
+
+/// How a text section's `.globl` declarations are laid out. Except with
+/// `Preserve`, they're gathered above its first code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GloblStyle {
+    Preserve,
+    Joined,
+    Split,
+}
+
+impl GloblStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(GloblStyle::Preserve),
+            "joined" => Some(GloblStyle::Joined),
+            "split" => Some(GloblStyle::Split),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GloblStyle::Preserve => "preserve",
+            GloblStyle::Joined => "joined",
+            GloblStyle::Split => "split",
+        }
+    }
+}
+
+/// The case the digits of hex literals are written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HexCase {
+    Preserve,
+    Lower,
+    Upper,
+}
+
+impl HexCase {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(HexCase::Preserve),
+            "lower" => Some(HexCase::Lower),
+            "upper" => Some(HexCase::Upper),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HexCase::Preserve => "preserve",
+            HexCase::Lower => "lower",
+            HexCase::Upper => "upper",
+        }
+    }
+}
+
+/// How literal addresses, like a section's start or the operand of `la`, are
+/// written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressLiterals {
+    Preserve,
+    Hex,
+    Decimal,
+}
+
+impl AddressLiterals {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(AddressLiterals::Preserve),
+            "hex" => Some(AddressLiterals::Hex),
+            "decimal" => Some(AddressLiterals::Decimal),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddressLiterals::Preserve => "preserve",
+            AddressLiterals::Hex => "hex",
+            AddressLiterals::Decimal => "decimal",
+        }
+    }
+}
+
+/// The line endings formatted files are written with. `Auto` keeps whichever
+/// a file mostly uses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEndings {
+    Auto,
+    Lf,
+    Crlf,
+}
+
+impl LineEndings {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(LineEndings::Auto),
+            "lf" => Some(LineEndings::Lf),
+            "crlf" => Some(LineEndings::Crlf),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEndings::Auto => "auto",
+            LineEndings::Lf => "lf",
+            LineEndings::Crlf => "crlf",
+        }
+    }
+}
+
+/// The style labels are renamed into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LabelCase {
+    Preserve,
+    SnakeCase,
+    Lowercase,
+}
+
+impl LabelCase {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(LabelCase::Preserve),
+            "snake_case" => Some(LabelCase::SnakeCase),
+            "lowercase" => Some(LabelCase::Lowercase),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LabelCase::Preserve => "preserve",
+            LabelCase::SnakeCase => "snake_case",
+            LabelCase::Lowercase => "lowercase",
+        }
+    }
+}
+
+/// Where a trailing comment goes when its code runs past `comment_column_max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentOverflow {
+    Inline,
+    Above,
+    Wrap,
+}
+
+impl CommentOverflow {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "inline" => Some(CommentOverflow::Inline),
+            "above" => Some(CommentOverflow::Above),
+            "wrap" => Some(CommentOverflow::Wrap),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommentOverflow::Inline => "inline",
+            CommentOverflow::Above => "above",
+            CommentOverflow::Wrap => "wrap",
+        }
+    }
+}
+
+/// How a wrapped `.word`, `.half` or `.byte` list continues onto its next
+/// lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataListContinuation {
+    Directive,
+    Aligned,
+}
+
+impl DataListContinuation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "directive" => Some(DataListContinuation::Directive),
+            "aligned" => Some(DataListContinuation::Aligned),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataListContinuation::Directive => "directive",
+            DataListContinuation::Aligned => "aligned",
+        }
+    }
+}
+
+/// Where the comment naming the formatter version and style goes, if anywhere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VersionHeader {
+    Off,
+    Top,
+    Bottom,
+}
+
+impl VersionHeader {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(VersionHeader::Off),
+            "top" => Some(VersionHeader::Top),
+            "bottom" => Some(VersionHeader::Bottom),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VersionHeader::Off => "off",
+            VersionHeader::Top => "top",
+            VersionHeader::Bottom => "bottom",
+        }
+    }
+}
+
+/// How `+`, `-`, `*` and `/` between operands like `label+4` are spaced.
+/// Signs, as in `-4($sp)`, always sit against their operand unless left as
+/// written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperatorSpacing {
+    Preserve,
+    Tight,
+    Spaced,
+}
+
+impl OperatorSpacing {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(OperatorSpacing::Preserve),
+            "tight" => Some(OperatorSpacing::Tight),
+            "spaced" => Some(OperatorSpacing::Spaced),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperatorSpacing::Preserve => "preserve",
+            OperatorSpacing::Tight => "tight",
+            OperatorSpacing::Spaced => "spaced",
+        }
+    }
+}
+
+/// What each level of indentation is written as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    /// Parses `tab` or a number of spaces.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "tab" | "tabs" => Some(IndentStyle::Tabs),
+            _ => name.parse().ok().map(IndentStyle::Spaces),
+        }
+    }
+
+    /// The columns one level takes up, counting a tab as 8.
+    pub fn width(&self) -> usize {
+        match self {
+            IndentStyle::Tabs => 8,
+            IndentStyle::Spaces(n) => *n,
+        }
+    }
+
+    pub fn unit(&self) -> String {
+        match self {
+            IndentStyle::Tabs => String::from("\t"),
+            IndentStyle::Spaces(n) => " ".repeat(*n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub address_literals: AddressLiterals,
+    pub align_constants: bool,
+    pub align_matrices: bool,
+    pub annotate_data_offsets: bool,
+    pub annotate_offsets: bool,
+    pub annotate_syscalls: bool,
+    pub blank_comments: BlankComments,
+    pub blank_line_runs: BlankLineRuns,
+    pub blank_lines_after_directive: usize,
+    pub blank_lines_around_globals: usize,
+    pub blank_lines_between_chunks: usize,
+    pub comment_column: usize,
+    pub comment_column_max: usize,
+    pub comment_disparity: usize,
+    pub comment_gap: usize,
+    pub comment_overflow: CommentOverflow,
+    pub data_list_continuation: DataListContinuation,
+    pub data_list_width: usize,
+    pub forbidden_instructions: Vec<String>,
+    pub globl_style: GloblStyle,
+    pub hex_case: HexCase,
+    pub indent: IndentStyle,
+    pub instruction_tables: Vec<String>,
+    // The standard instructions plus those from `instruction_tables`
+    pub instructions: InstructionSet,
+    pub isa: Isa,
+    // Patterns like `li $v0 + syscall` for lines never split by blank lines
+    pub keep_together: Vec<String>,
+    pub label_case: LabelCase,
+    pub line_endings: LineEndings,
+    pub lint_ignore: Vec<String>,
+    pub max_file_size: usize,
+    pub max_line_length: usize,
+    pub max_lines: usize,
+    pub max_procedure_instructions: usize,
+    pub max_width: usize,
+    pub operator_spacing: OperatorSpacing,
+    pub preserve_tables: bool,
+    pub procedure_comments: Vec<String>,
+    pub protected_region: Vec<String>,
+    pub sort_data: bool,
+    pub trailing_comment_max: usize,
+    pub version_header: VersionHeader,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address_literals: AddressLiterals::Preserve,
+            align_constants: true,
+            align_matrices: false,
+            annotate_data_offsets: false,
+            annotate_offsets: false,
+            annotate_syscalls: false,
+            blank_comments: BlankComments::Keep,
+            blank_line_runs: BlankLineRuns::Collapse,
+            blank_lines_after_directive: 1,
+            blank_lines_around_globals: 1,
+            blank_lines_between_chunks: 1,
+            comment_column: 0,
+            comment_column_max: 0,
+            comment_disparity: 10,
+            comment_gap: 2,
+            comment_overflow: CommentOverflow::Inline,
+            data_list_continuation: DataListContinuation::Directive,
+            data_list_width: 0,
+            forbidden_instructions: Vec::new(),
+            globl_style: GloblStyle::Preserve,
+            hex_case: HexCase::Preserve,
+            indent: IndentStyle::Tabs,
+            instruction_tables: Vec::new(),
+            instructions: InstructionSet::standard(),
+            isa: Isa::Mips32r2,
+            keep_together: Vec::new(),
+            label_case: LabelCase::Preserve,
+            line_endings: LineEndings::Auto,
+            lint_ignore: Vec::new(),
+            max_file_size: 1 << 20,
+            max_line_length: 1000,
+            max_lines: 50_000,
+            max_procedure_instructions: 0,
+            max_width: 0,
+            operator_spacing: OperatorSpacing::Preserve,
+            preserve_tables: true,
+            procedure_comments: Vec::new(),
+            protected_region: Vec::new(),
+            sort_data: false,
+            trailing_comment_max: 0,
+            version_header: VersionHeader::Off,
+        }
+    }
+}
+
+impl Config {
+    /// Describes the first size limit `contents` is over, if any. A limit of
+    /// 0 is unlimited.
+    pub fn exceeded_limit(&self, contents: &str) -> Option<String> {
+        let over = |limit: usize, value: usize| limit > 0 && value > limit;
+
+        if over(self.max_file_size, contents.len()) {
+            return Some(format!(
+                "{} bytes is over `max_file_size` ({})",
+                contents.len(),
+                self.max_file_size
+            ));
+        }
+        let lines = contents.lines().count();
+        if over(self.max_lines, lines) {
+            return Some(format!(
+                "{} lines is over `max_lines` ({})",
+                lines, self.max_lines
+            ));
+        }
+        contents
+            .lines()
+            .enumerate()
+            .find(|(_, line)| over(self.max_line_length, line.chars().count()))
+            .map(|(i, line)| {
+                format!(
+                    "line {} is {} characters, over `max_line_length` ({})",
+                    i + 1,
+                    line.chars().count(),
+                    self.max_line_length
+                )
+            })
+    }
+
+    /// Turns on the structural cleanups `--organize` runs on top of plain
+    /// formatting: gathering `.globl`s (one per line unless `globl_style`
+    /// says otherwise), collapsing runs of blank lines and sorting `.data`.
+    pub fn organize(&mut self) {
+        if self.globl_style == GloblStyle::Preserve {
+            self.globl_style = GloblStyle::Split;
+        }
+        self.blank_line_runs = BlankLineRuns::Collapse;
+        self.sort_data = true;
+    }
+
+    pub fn set(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "address_literals" => {
+                self.address_literals = match value {
+                    Value::Str(name) => AddressLiterals::parse(name),
+                    _ => None,
+                }
+                .ok_or("`address_literals` expects one of \"preserve\", \"hex\" or \"decimal\"")?
+            }
+            "align_constants" => self.align_constants = value.as_bool(key)?,
+            "align_matrices" => self.align_matrices = value.as_bool(key)?,
+            "annotate_data_offsets" => self.annotate_data_offsets = value.as_bool(key)?,
+            "annotate_offsets" => self.annotate_offsets = value.as_bool(key)?,
+            "annotate_syscalls" => self.annotate_syscalls = value.as_bool(key)?,
+            "blank_comments" => {
+                self.blank_comments = match value {
+                    Value::Str(name) => BlankComments::parse(name),
+                    _ => None,
+                }
+                .ok_or("`blank_comments` expects one of \"keep\", \"collapse\" or \"blank\"")?
+            }
+            "blank_line_runs" => {
+                self.blank_line_runs = match value {
+                    Value::Str(name) => BlankLineRuns::parse(name),
+                    _ => None,
+                }
+                .ok_or("`blank_line_runs` expects either \"collapse\" or \"preserve\"")?
+            }
+            "blank_lines_after_directive" => {
+                self.blank_lines_after_directive = value.as_usize(key)?
+            }
+            "blank_lines_around_globals" => {
+                self.blank_lines_around_globals = value.as_usize(key)?
+            }
+            "blank_lines_between_chunks" => {
+                self.blank_lines_between_chunks = value.as_usize(key)?
+            }
+            "comment_column" => self.comment_column = value.as_usize(key)?,
+            "comment_column_max" => self.comment_column_max = value.as_usize(key)?,
+            "comment_disparity" => self.comment_disparity = value.as_usize(key)?,
+            "comment_gap" => self.comment_gap = value.as_usize(key)?,
+            "comment_overflow" => {
+                self.comment_overflow = match value {
+                    Value::Str(name) => CommentOverflow::parse(name),
+                    _ => None,
+                }
+                .ok_or("`comment_overflow` expects one of \"inline\", \"above\" or \"wrap\"")?
+            }
+            "data_list_continuation" => {
+                self.data_list_continuation = match value {
+                    Value::Str(name) => DataListContinuation::parse(name),
+                    _ => None,
+                }
+                .ok_or("`data_list_continuation` expects one of \"directive\" or \"aligned\"")?
+            }
+            "data_list_width" => self.data_list_width = value.as_usize(key)?,
+            "forbidden_instructions" => self.forbidden_instructions = value.as_str_list(key)?,
+            "globl_style" => {
+                self.globl_style = match value {
+                    Value::Str(name) => GloblStyle::parse(name),
+                    _ => None,
+                }
+                .ok_or("`globl_style` expects one of \"preserve\", \"joined\" or \"split\"")?
+            }
+            "hex_case" => {
+                self.hex_case = match value {
+                    Value::Str(name) => HexCase::parse(name),
+                    _ => None,
+                }
+                .ok_or("`hex_case` expects one of \"preserve\", \"lower\" or \"upper\"")?
+            }
+            "indent" => {
+                self.indent = match value {
+                    Value::Str(name) => IndentStyle::parse(name),
+                    Value::Int(n) if *n >= 0 => Some(IndentStyle::Spaces(*n as usize)),
+                    _ => None,
+                }
+                .ok_or("`indent` expects \"tab\" or a number of spaces")?
+            }
+            "instruction_tables" => self.instruction_tables = value.as_str_list(key)?,
+            "isa" => {
+                self.isa = match value {
+                    Value::Str(name) => Isa::parse(name),
+                    _ => None,
+                }
+                .ok_or("`isa` expects one of \"mips1\", \"mips32\" or \"mips32r2\"")?
+            }
+            "keep_together" => {
+                let patterns = value.as_str_list(key)?;
+                if let Some(bad) = patterns.iter().find(|p| p.matches('+').count() != 1) {
+                    return Err(format!(
+                        "`keep_together` pattern \"{}\" should look like \"li $v0 + syscall\"",
+                        bad
+                    ));
+                }
+                self.keep_together = patterns;
+            }
+            "label_case" => {
+                self.label_case = match value {
+                    Value::Str(name) => LabelCase::parse(name),
+                    _ => None,
+                }
+                .ok_or(
+                    "`label_case` expects one of \"preserve\", \"snake_case\" or \"lowercase\"",
+                )?
+            }
+            "line_endings" => {
+                self.line_endings = match value {
+                    Value::Str(name) => LineEndings::parse(name),
+                    _ => None,
+                }
+                .ok_or("`line_endings` expects one of \"auto\", \"lf\" or \"crlf\"")?
+            }
+            "lint_ignore" => self.lint_ignore = value.as_str_list(key)?,
+            "max_file_size" => self.max_file_size = value.as_usize(key)?,
+            "max_line_length" => self.max_line_length = value.as_usize(key)?,
+            "max_lines" => self.max_lines = value.as_usize(key)?,
+            "max_procedure_instructions" => {
+                self.max_procedure_instructions = value.as_usize(key)?
+            }
+            "max_width" => self.max_width = value.as_usize(key)?,
+            "operator_spacing" => {
+                self.operator_spacing = match value {
+                    Value::Str(name) => OperatorSpacing::parse(name),
+                    _ => None,
+                }
+                .ok_or("`operator_spacing` expects one of \"preserve\", \"tight\" or \"spaced\"")?
+            }
+            "preserve_tables" => self.preserve_tables = value.as_bool(key)?,
+            "procedure_comments" => self.procedure_comments = value.as_str_list(key)?,
+            "protected_region" => {
+                let markers = value.as_str_list(key)?;
+                if !markers.is_empty() && markers.len() != 2 {
+                    return Err(String::from(
+                        "`protected_region` expects a start and end marker, e.g. [\"#! begin\", \"#! end\"]",
+                    ));
+                }
+                self.protected_region = markers;
+            }
+            "sort_data" => self.sort_data = value.as_bool(key)?,
+            "trailing_comment_max" => self.trailing_comment_max = value.as_usize(key)?,
+            "version_header" => {
+                self.version_header = match value {
+                    Value::Str(name) => VersionHeader::parse(name),
+                    _ => None,
+                }
+                .ok_or("`version_header` expects one of \"off\", \"top\" or \"bottom\"")?
+            }
+            _ => return Err(unknown_option(key)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let strings =
+            |list: &[String]| Value::Array(list.iter().cloned().map(Value::Str).collect());
+        Some(match key {
+            "address_literals" => Value::Str(self.address_literals.name().to_string()),
+            "align_constants" => Value::Bool(self.align_constants),
+            "align_matrices" => Value::Bool(self.align_matrices),
+            "annotate_data_offsets" => Value::Bool(self.annotate_data_offsets),
+            "annotate_offsets" => Value::Bool(self.annotate_offsets),
+            "annotate_syscalls" => Value::Bool(self.annotate_syscalls),
+            "blank_comments" => Value::Str(self.blank_comments.name().to_string()),
+            "blank_line_runs" => Value::Str(self.blank_line_runs.name().to_string()),
+            "blank_lines_after_directive" => Value::Int(self.blank_lines_after_directive as i64),
+            "blank_lines_around_globals" => Value::Int(self.blank_lines_around_globals as i64),
+            "blank_lines_between_chunks" => Value::Int(self.blank_lines_between_chunks as i64),
+            "comment_column" => Value::Int(self.comment_column as i64),
+            "comment_column_max" => Value::Int(self.comment_column_max as i64),
+            "comment_disparity" => Value::Int(self.comment_disparity as i64),
+            "comment_gap" => Value::Int(self.comment_gap as i64),
+            "comment_overflow" => Value::Str(self.comment_overflow.name().to_string()),
+            "data_list_continuation" => Value::Str(self.data_list_continuation.name().to_string()),
+            "data_list_width" => Value::Int(self.data_list_width as i64),
+            "forbidden_instructions" => strings(&self.forbidden_instructions),
+            "globl_style" => Value::Str(self.globl_style.name().to_string()),
+            "hex_case" => Value::Str(self.hex_case.name().to_string()),
+            "indent" => match self.indent {
+                IndentStyle::Tabs => Value::Str(String::from("tab")),
+                IndentStyle::Spaces(n) => Value::Int(n as i64),
+            },
+            "instruction_tables" => strings(&self.instruction_tables),
+            "isa" => Value::Str(self.isa.name().to_string()),
+            "keep_together" => strings(&self.keep_together),
+            "label_case" => Value::Str(self.label_case.name().to_string()),
+            "line_endings" => Value::Str(self.line_endings.name().to_string()),
+            "lint_ignore" => strings(&self.lint_ignore),
+            "max_file_size" => Value::Int(self.max_file_size as i64),
+            "max_line_length" => Value::Int(self.max_line_length as i64),
+            "max_lines" => Value::Int(self.max_lines as i64),
+            "max_procedure_instructions" => Value::Int(self.max_procedure_instructions as i64),
+            "max_width" => Value::Int(self.max_width as i64),
+            "operator_spacing" => Value::Str(self.operator_spacing.name().to_string()),
+            "preserve_tables" => Value::Bool(self.preserve_tables),
+            "procedure_comments" => strings(&self.procedure_comments),
+            "protected_region" => strings(&self.protected_region),
+            "sort_data" => Value::Bool(self.sort_data),
+            "trailing_comment_max" => Value::Int(self.trailing_comment_max as i64),
+            "version_header" => Value::Str(self.version_header.name().to_string()),
+            _ => return None,
+        })
+    }
+
+    /// Applies a config file's options, returning any warnings about it.
+    pub fn apply_toml(&mut self, contents: &str) -> Result<Vec<String>, String> {
+        self.apply_pairs(parse_toml(contents)?)
+    }
+
+    fn apply_pairs(&mut self, pairs: Vec<(String, Value)>) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+
+        for (key, value) in pairs {
+            if key == "config_version" {
+                match value {
+                    Value::Int(v) if v > CONFIG_VERSION => {
+                        return Err(format!(
+                        "config_version {} is newer than this version of mac-mips supports ({})",
+                        v, CONFIG_VERSION
+                    ))
+                    }
+                    Value::Int(_) => continue,
+                    _ => return Err(String::from("`config_version` expects an integer")),
+                }
+            }
+
+            let key = match renamed(&key, RENAMED_OPTIONS) {
+                Some(new_key) => {
+                    warnings.push(format!(
+                        "`{}` has been renamed to `{}`, run 'mac-mips config migrate' to update",
+                        key, new_key
+                    ));
+                    new_key.to_string()
+                }
+                None => key,
+            };
+
+            if !OPTIONS.contains(&key.as_str()) {
+                warnings.push(unknown_option(&key));
+                continue;
+            }
+
+            self.set(&key, &value)?;
+        }
+
+        Ok(warnings)
+    }
+
+    /// Fails if both `comment_column` and `comment_column_max` are set, the
+    /// one pair of options that can't be used together, checked once every
+    /// source has been applied. Every other combination is allowed, the
+    /// options applying one after another.
+    pub fn check_comment_columns(&self) -> Result<(), String> {
+        match (self.comment_column, self.comment_column_max) {
+            (0, _) | (_, 0) => Ok(()),
+            _ => Err(String::from(
+                "`comment_column` and `comment_column_max` can't be used together, a fixed comment column is already the furthest comments go",
+            )),
+        }
+    }
+
+    pub fn apply_file(&mut self, path: &Path) -> Result<Vec<String>, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        self.apply_toml(&contents)
+            .map(|warnings| {
+                warnings
+                    .into_iter()
+                    .map(|w| format!("{}: {}", path.display(), w))
+                    .collect()
+            })
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Applies every `MACMIPS_<OPTION>` variable, e.g. `MACMIPS_COMMENT_GAP=4`.
+    /// Values that aren't valid TOML are taken as plain strings.
+    pub fn apply_env<I>(&mut self, vars: I) -> Result<Vec<String>, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut renames = Vec::new();
+        let pairs = vars
+            .into_iter()
+            .filter(|(name, _)| name != CONFIG_PATH_VAR)
+            .filter_map(|(name, raw)| {
+                let key = name.strip_prefix(OPTION_VAR_PREFIX)?.to_lowercase();
+                let value = Value::parse(&raw).unwrap_or(Value::Str(raw));
+                match renamed(&key, RENAMED_OPTIONS) {
+                    Some(new_key) => {
+                        renames.push(format!(
+                            "`{}` has been renamed to `{}{}`",
+                            name,
+                            OPTION_VAR_PREFIX,
+                            new_key.to_uppercase()
+                        ));
+                        Some((new_key.to_string(), value))
+                    }
+                    None => Some((key, value)),
+                }
+            })
+            .collect();
+
+        self.apply_pairs(pairs)
+            .map(|warnings| {
+                renames
+                    .into_iter()
+                    .chain(warnings)
+                    .map(|w| format!("environment: {}", w))
+                    .collect()
+            })
+            .map_err(|e| format!("environment: {}", e))
+    }
+
+    /// Adds the instructions from each of `instruction_tables`, resolving
+    /// relative paths against `dir`.
+    pub fn load_instruction_tables(&mut self, dir: &Path) -> Result<(), String> {
+        for table in &self.instruction_tables {
+            let path = dir.join(table);
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+            self.instructions
+                .extend_from_toml(&contents)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Builds the effective config: defaults, then the config file at `path`
+    /// if there is one, then `MACMIPS_*` variables, which together mustn't
+    /// set both comment columns. Instruction tables are found relative to the
+    /// config file.
+    pub fn load(path: Option<&Path>) -> Result<(Self, Vec<String>), String> {
+        let mut config = Config::default();
+        let mut warnings = Vec::new();
+
+        if let Some(path) = path {
+            warnings = config.apply_file(path)?;
+        }
+
+        warnings.extend(config.apply_env(env::vars())?);
+        config.check_comment_columns()?;
+
+        let dir = path.and_then(Path::parent).unwrap_or(Path::new("."));
+        config.load_instruction_tables(dir)?;
+        Ok((config, warnings))
+    }
+}
+
+/// The config file in `dir`, if it has one.
+pub fn discover(dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// The config file for sources in `dir`: an explicit path, then
+/// `$MACMIPS_CONFIG`, then the nearest config file in `dir` or its parents.
+pub fn config_path(explicit_path: Option<&Path>, dir: &Path) -> Option<PathBuf> {
+    match explicit_path {
+        Some(path) => Some(path.to_path_buf()),
+        None => env::var_os(CONFIG_PATH_VAR)
+            .map(PathBuf::from)
+            .or_else(|| dir.ancestors().find_map(discover)),
+    }
+}
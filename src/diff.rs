@@ -0,0 +1,221 @@
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+// Lengths of the longest common subsequence of `old` and each prefix of
+// `new`, or each suffix when `backwards`, keeping only one row at a time
+fn lcs_lengths<T: PartialEq>(old: &[T], new: &[T], backwards: bool) -> Vec<usize> {
+    let at = |items: &[T], i: usize| match backwards {
+        true => items.len() - 1 - i,
+        false => i,
+    };
+    let mut row = vec![0; new.len() + 1];
+    let mut prev = row.clone();
+    for i in 0..old.len() {
+        std::mem::swap(&mut row, &mut prev);
+        for j in 0..new.len() {
+            row[j + 1] = match old[at(old, i)] == new[at(new, j)] {
+                true => prev[j] + 1,
+                false => prev[j + 1].max(row[j]),
+            };
+        }
+    }
+    row
+}
+
+// Marks the lines of a longest common subsequence of `old` and `new` with
+// Hirschberg's algorithm, which needs space only linear in their lengths
+fn mark_common<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    old_common: &mut [bool],
+    new_common: &mut [bool],
+) {
+    if old.is_empty() || new.is_empty() {
+        return;
+    }
+    if old.len() == 1 {
+        if let Some(j) = new.iter().position(|line| *line == old[0]) {
+            old_common[0] = true;
+            new_common[j] = true;
+        }
+        return;
+    }
+
+    // Split `new` where the halves of `old` share the most with it
+    let mid = old.len() / 2;
+    let front = lcs_lengths(&old[..mid], new, false);
+    let back = lcs_lengths(&old[mid..], new, true);
+    let split = (0..=new.len())
+        .max_by_key(|&j| (front[j] + back[new.len() - j], std::cmp::Reverse(j)))
+        .unwrap();
+
+    let (old_front, old_back) = old_common.split_at_mut(mid);
+    let (new_front, new_back) = new_common.split_at_mut(split);
+    mark_common(&old[..mid], &new[..split], old_front, new_front);
+    mark_common(&old[mid..], &new[split..], old_back, new_back);
+}
+
+/// Finds the runs of lines that differ between `old` and `new`, using the
+/// longest common subsequence of lines.
+pub fn hunks<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..(old.len() - suffix)];
+    let new_mid = &new[prefix..(new.len() - suffix)];
+    let mut old_common = vec![false; old_mid.len()];
+    let mut new_common = vec![false; new_mid.len()];
+    mark_common(old_mid, new_mid, &mut old_common, &mut new_common);
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_common[i] && new_common[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let deleting = i < old_mid.len() && !old_common[i];
+
+        let hunk = match hunks.last_mut() {
+            Some(h)
+                if h.old_start + h.old_len == prefix + i
+                    && h.new_start + h.new_len == prefix + j =>
+            {
+                h
+            }
+            _ => {
+                hunks.push(Hunk {
+                    old_start: prefix + i,
+                    old_len: 0,
+                    new_start: prefix + j,
+                    new_len: 0,
+                });
+                hunks.last_mut().unwrap()
+            }
+        };
+
+        if deleting {
+            hunk.old_len += 1;
+            i += 1;
+        } else {
+            hunk.new_len += 1;
+            j += 1;
+        }
+    }
+
+    hunks
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        if self.start == self.end {
+            start <= self.start && self.start <= end
+        } else {
+            self.start < end && start < self.end
+        }
+    }
+}
+
+/// Describes how to turn `old` into `new` as byte-offset replacements of
+/// whole lines in `old`.
+pub fn text_edits(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let mut offsets = vec![0];
+    for line in &old_lines {
+        offsets.push(offsets.last().unwrap() + line.len());
+    }
+
+    hunks(&old_lines, &new_lines)
+        .into_iter()
+        .map(|h| TextEdit {
+            start: offsets[h.old_start],
+            end: offsets[h.old_start + h.old_len],
+            text: new_lines[h.new_start..(h.new_start + h.new_len)].concat(),
+        })
+        .collect()
+}
+
+/// Renders the changes from `old` to `new` as a unified diff with `context`
+/// unchanged lines around each change.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    // Hunks close enough that their context would overlap are printed together
+    let mut groups: Vec<Vec<Hunk>> = Vec::new();
+    for hunk in hunks(&old_lines, &new_lines) {
+        let joins = groups
+            .last()
+            .and_then(|group| group.last())
+            .is_some_and(|last| hunk.old_start <= last.old_start + last.old_len + 2 * context);
+        match joins {
+            true => groups.last_mut().unwrap().push(hunk),
+            false => groups.push(vec![hunk]),
+        }
+    }
+
+    if groups.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for group in groups {
+        let first = &group[0];
+        let last = group.last().unwrap();
+        let lead = first.old_start.min(context);
+        let old_start = first.old_start - lead;
+        let new_start = first.new_start - lead;
+        let old_end = (last.old_start + last.old_len + context).min(old_lines.len());
+        let new_end =
+            new_start + (old_end - old_start) + group.iter().map(|h| h.new_len).sum::<usize>()
+                - group.iter().map(|h| h.old_len).sum::<usize>();
+
+        out += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        );
+
+        let mut i = old_start;
+        for hunk in &group {
+            for line in &old_lines[i..hunk.old_start] {
+                out += &format!(" {}\n", line);
+            }
+            for line in &old_lines[hunk.old_start..(hunk.old_start + hunk.old_len)] {
+                out += &format!("-{}\n", line);
+            }
+            for line in &new_lines[hunk.new_start..(hunk.new_start + hunk.new_len)] {
+                out += &format!("+{}\n", line);
+            }
+            i = hunk.old_start + hunk.old_len;
+        }
+        for line in &old_lines[i..old_end] {
+            out += &format!(" {}\n", line);
+        }
+    }
+
+    out
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+use crate::analysis::Statement;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Mars,
+    Gnu,
+    Generic,
+}
+
+impl Dialect {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "mars" => Some(Dialect::Mars),
+            "gnu" | "gas" => Some(Dialect::Gnu),
+            "generic" | "spim" => Some(Dialect::Generic),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Dialect::Mars => write!(f, "MARS"),
+            Dialect::Gnu => write!(f, "GNU as"),
+            Dialect::Generic => write!(f, "generic MIPS (SPIM or MARS)"),
+        }
+    }
+}
+
+static MARS_DIRECTIVES: &[&str] = &[".eqv", ".macro", ".end_macro", ".include"];
+static GNU_DIRECTIVES: &[&str] = &[
+    ".section", ".set", ".ent", ".end", ".frame", ".mask", ".fmask", ".type", ".size", ".rodata",
+    ".bss", ".sdata",
+];
+
+/// Guesses which assembler a file was written for, from the directives and
+/// operators only one of them supports. Also returns what gave it away.
+pub fn detect_dialect(statements: &[Statement]) -> (Dialect, Vec<String>) {
+    let mut mars: Vec<String> = Vec::new();
+    let mut gnu: Vec<String> = Vec::new();
+
+    for statement in statements {
+        if let Some(mnemonic) = &statement.mnemonic {
+            let evidence = format!("`{}` on line {}", mnemonic, statement.line);
+            if MARS_DIRECTIVES.contains(&mnemonic.as_str()) {
+                mars.push(evidence);
+            } else if GNU_DIRECTIVES.contains(&mnemonic.as_str()) {
+                gnu.push(evidence);
+            }
+        }
+        if statement
+            .operands
+            .iter()
+            .any(|op| op.contains("%hi(") || op.contains("%lo("))
+        {
+            gnu.push(format!("`%hi`/`%lo` on line {}", statement.line));
+        }
+    }
+
+    match (mars.is_empty(), gnu.is_empty()) {
+        (true, true) => (Dialect::Generic, Vec::new()),
+        _ if gnu.len() > mars.len() => (Dialect::Gnu, gnu),
+        _ => (Dialect::Mars, mars),
+    }
+}
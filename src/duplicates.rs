@@ -0,0 +1,113 @@
+use crate::analysis::{procedure_labels, Segment, Statement};
+
+#[derive(Debug, PartialEq)]
+pub struct Occurrence {
+    pub procedure: String,
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Duplicate {
+    pub len: usize,
+    pub first: Occurrence,
+    pub second: Occurrence,
+}
+
+struct Procedure {
+    name: String,
+    // Source line and register-insensitive text of each instruction
+    code: Vec<(usize, String)>,
+}
+
+// An instruction with every register replaced, so sequences that only differ
+// in which registers they use still match
+fn normalise(statement: &Statement) -> String {
+    let operands: Vec<String> = statement
+        .operands
+        .iter()
+        .map(|op| {
+            let mut out = String::new();
+            let mut in_register = false;
+            for c in op.chars() {
+                if c == '$' {
+                    in_register = true;
+                    out.push_str("$r");
+                } else if in_register && c.is_alphanumeric() {
+                    continue;
+                } else {
+                    in_register = false;
+                    out.push(c);
+                }
+            }
+            out
+        })
+        .collect();
+    format!(
+        "{} {}",
+        statement.mnemonic.as_deref().unwrap_or(""),
+        operands.join(", ")
+    )
+}
+
+fn procedures(statements: &[Statement]) -> Vec<Procedure> {
+    let boundaries = procedure_labels(statements);
+    let mut procedures: Vec<Procedure> = Vec::new();
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Text) {
+        if let Some(label) = statement.labels.iter().find(|l| boundaries.contains(l)) {
+            procedures.push(Procedure {
+                name: label.clone(),
+                code: Vec::new(),
+            });
+        }
+        if let (Some(procedure), true) = (procedures.last_mut(), statement.is_instruction()) {
+            procedure.code.push((statement.line, normalise(statement)));
+        }
+    }
+
+    procedures
+}
+
+fn occurrence(procedure: &Procedure, start: usize, len: usize) -> Occurrence {
+    Occurrence {
+        procedure: procedure.name.clone(),
+        first_line: procedure.code[start].0,
+        last_line: procedure.code[start + len - 1].0,
+    }
+}
+
+/// Finds runs of at least `min_len` instructions that appear in two different
+/// procedures, ignoring register names. Only the longest run at each pair of
+/// positions is reported.
+pub fn find_duplicates(statements: &[Statement], min_len: usize) -> Vec<Duplicate> {
+    let procedures = procedures(statements);
+    let mut duplicates = Vec::new();
+
+    for (a, first) in procedures.iter().enumerate() {
+        for second in &procedures[(a + 1)..] {
+            for i in 0..first.code.len() {
+                for j in 0..second.code.len() {
+                    if i > 0 && j > 0 && first.code[i - 1].1 == second.code[j - 1].1 {
+                        continue;
+                    }
+
+                    let len = first.code[i..]
+                        .iter()
+                        .zip(&second.code[j..])
+                        .take_while(|(x, y)| x.1 == y.1)
+                        .count();
+                    if len >= min_len.max(1) {
+                        duplicates.push(Duplicate {
+                            len,
+                            first: occurrence(first, i, len),
+                            second: occurrence(second, j, len),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    duplicates
+}
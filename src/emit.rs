@@ -0,0 +1,57 @@
+use crate::config::Config;
+use crate::formatter;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcedureOptions {
+    /// Registers the procedure saves on entry and restores before returning,
+    /// such as `$s0`.
+    pub saved: Vec<String>,
+    /// Whether `$ra` is saved too, for procedures that call others.
+    pub save_ra: bool,
+    /// Bytes of stack reserved for locals, below the saved registers.
+    pub locals: usize,
+}
+
+/// A formatted procedure called `name` running `body`, wrapped in a prologue
+/// that makes a stack frame and saves the registers `options` asks for, and
+/// an epilogue that restores them and returns.
+pub fn procedure(
+    name: &str,
+    body: &[&str],
+    options: &ProcedureOptions,
+    config: &Config,
+) -> Result<String, String> {
+    let mut saved: Vec<&str> = options.saved.iter().map(String::as_str).collect();
+    if options.save_ra {
+        saved.insert(0, "$ra");
+    }
+    let frame = (4 * saved.len() + options.locals).div_ceil(4) * 4;
+    let slots: Vec<(&str, usize)> = saved
+        .iter()
+        .enumerate()
+        .map(|(i, reg)| (*reg, frame - 4 * (i + 1)))
+        .collect();
+
+    let mut lines = vec![format!("{}:", name)];
+    if frame > 0 {
+        lines.push(format!("addi $sp, $sp, -{}", frame));
+    }
+    lines.extend(
+        slots
+            .iter()
+            .map(|(reg, at)| format!("sw {}, {}($sp)", reg, at)),
+    );
+    lines.extend(body.iter().map(|line| line.to_string()));
+    lines.extend(
+        slots
+            .iter()
+            .map(|(reg, at)| format!("lw {}, {}($sp)", reg, at)),
+    );
+    if frame > 0 {
+        lines.push(format!("addi $sp, $sp, {}", frame));
+    }
+    lines.push(String::from("jr $ra"));
+
+    formatter::format_with_config(lines.join("\n"), config)
+        .map_err(|_| format!("couldn't format procedure `{}`", name))
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+const BOM: char = '\u{feff}';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf8bom" | "utf8sig" => Some(Encoding::Utf8Bom),
+            "latin1" | "iso88591" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn decode(&self, bytes: Vec<u8>) -> Result<String, String> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8(bytes).map_err(|e| {
+                format!(
+                    "invalid {} at byte {}",
+                    Encoding::Utf8,
+                    e.utf8_error().valid_up_to()
+                )
+            }),
+            Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+        }
+    }
+
+    /// Encodes `text` for writing, or describes each line holding characters
+    /// the encoding can't represent.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, Vec<String>> {
+        let text = text.strip_prefix(BOM).unwrap_or(text);
+
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf8Bom => Ok(format!("{}{}", BOM, text).into_bytes()),
+            Encoding::Latin1 => {
+                let errors: Vec<String> = text
+                    .lines()
+                    .enumerate()
+                    .filter_map(|(i, line)| {
+                        let bad: Vec<String> = line
+                            .chars()
+                            .filter(|c| *c as u32 > 0xff)
+                            .map(|c| format!("`{}` (U+{:04X})", c, c as u32))
+                            .collect();
+                        match bad.is_empty() {
+                            true => None,
+                            false => Some(format!(
+                                "line {}: {} can't be encoded in {}",
+                                i + 1,
+                                bad.join(", "),
+                                self
+                            )),
+                        }
+                    })
+                    .collect();
+
+                match errors.is_empty() {
+                    true => Ok(text.chars().map(|c| c as u8).collect()),
+                    false => Err(errors),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Utf8Bom => write!(f, "UTF-8 with BOM"),
+            Encoding::Latin1 => write!(f, "Latin-1"),
+        }
+    }
+}
+
+/// How many lines of `text` end in a bare `\n`, and how many in `\r\n`.
+pub fn line_endings(text: &str) -> (usize, usize) {
+    let crlf = text.matches("\r\n").count();
+    (text.matches('\n').count() - crlf, crlf)
+}
+
+/// `text`, which ends its lines with `\n`, with `\r\n` endings instead.
+pub fn to_crlf(text: &str) -> String {
+    text.replace('\n', "\r\n")
+}
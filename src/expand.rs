@@ -0,0 +1,228 @@
+use crate::analysis::{comment_start, macro_params, split_operands};
+
+static MAX_DEPTH: usize = 16;
+
+struct MacroDef {
+    name: String,
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '%' | '$')
+}
+
+// Replaces whole words in the code part of a line, leaving strings and comments alone
+fn replace_words<F>(line: &str, replace: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let (code, comment) = match comment_start(line) {
+        Some(hash) => line.split_at(hash),
+        None => (line, ""),
+    };
+
+    let mut out = String::new();
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+
+    let flush = |word: &mut String, out: &mut String| {
+        match replace(word) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(word),
+        }
+        word.clear();
+    };
+
+    for c in code.chars() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == q && !out.ends_with(&format!("\\{}", q)) {
+                    quote = None;
+                }
+            }
+            None if is_word_char(c) => word.push(c),
+            None => {
+                flush(&mut word, &mut out);
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                out.push(c);
+            }
+        }
+    }
+    flush(&mut word, &mut out);
+
+    out + comment
+}
+
+fn collect_eqvs(lines: &[&str]) -> Vec<(String, String)> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let code = match comment_start(line) {
+                Some(hash) => &line[..hash],
+                None => line,
+            };
+            let rest = code.trim().strip_prefix(".eqv")?;
+            let (name, value) = rest.trim().split_once(char::is_whitespace)?;
+            Some((name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn collect_macros(lines: &[&str]) -> Vec<MacroDef> {
+    let mut macros: Vec<MacroDef> = Vec::new();
+    let mut open: Option<MacroDef> = None;
+
+    for line in lines {
+        let code = line.trim();
+        if let Some(header) = code.strip_prefix(".macro") {
+            let header = header.trim();
+            let name_end = header
+                .find(|c: char| c.is_whitespace() || c == '(')
+                .unwrap_or(header.len());
+            open = Some(MacroDef {
+                name: header[..name_end].to_string(),
+                params: macro_params(&header[name_end..]),
+                body: Vec::new(),
+            });
+        } else if code.starts_with(".end_macro") {
+            macros.extend(open.take());
+        } else if let Some(m) = &mut open {
+            m.body.push(line.to_string());
+        }
+    }
+
+    macros
+}
+
+fn invocation<'a>(line: &str, macros: &'a [MacroDef]) -> Option<(&'a MacroDef, Vec<String>)> {
+    let code = match comment_start(line) {
+        Some(hash) => &line[..hash],
+        None => line,
+    }
+    .trim();
+
+    let name_end = code
+        .find(|c: char| c.is_whitespace() || c == '(')
+        .unwrap_or(code.len());
+    let (name, args) = code.split_at(name_end);
+
+    let args = args.trim();
+    let args = match args.strip_prefix('(') {
+        Some(inner) => inner.strip_suffix(')')?,
+        None => args,
+    };
+    let args = split_operands(args);
+
+    macros
+        .iter()
+        .find(|m| m.name == name && m.params.len() == args.len())
+        .map(|m| (m, args))
+}
+
+struct Expander {
+    eqvs: Vec<(String, String)>,
+    macros: Vec<MacroDef>,
+    expansions: usize,
+}
+
+impl Expander {
+    fn substitute_eqvs(&self, line: &str) -> String {
+        replace_words(line, |word| {
+            self.eqvs
+                .iter()
+                .find(|(name, _)| name == word)
+                .map(|(_, value)| value.clone())
+        })
+    }
+
+    fn expand_line(&mut self, line: &str, depth: usize, out: &mut Vec<String>) {
+        let (m, args) = match invocation(line, &self.macros) {
+            Some(found) if depth < MAX_DEPTH => found,
+            _ => {
+                out.push(self.substitute_eqvs(line));
+                return;
+            }
+        };
+
+        let expansion = self.expansions + 1;
+        let suffix = format!("_M{}", expansion);
+        let indent = &line[..(line.len() - line.trim_start().len())];
+
+        // Labels inside a macro are made unique per expansion, like MARS does
+        let local_labels: Vec<String> = m
+            .body
+            .iter()
+            .filter_map(|l| {
+                let label = l.trim().split_once(':')?.0;
+                label.chars().all(is_word_char).then(|| label.to_string())
+            })
+            .collect();
+
+        let bindings: Vec<String> = m
+            .params
+            .iter()
+            .zip(&args)
+            .map(|(p, a)| format!("{} = {}", p, a))
+            .collect();
+        let name = m.name.clone();
+        let body: Vec<String> = m
+            .body
+            .iter()
+            .map(|l| {
+                replace_words(l, |word| {
+                    if let Some(i) = m.params.iter().position(|p| p == word) {
+                        return Some(args[i].clone());
+                    }
+                    local_labels
+                        .iter()
+                        .find(|label| *label == word)
+                        .map(|label| format!("{}{}", label, suffix))
+                })
+            })
+            .collect();
+
+        self.expansions = expansion;
+        out.push(format!("{}# >>> {}({})", indent, name, bindings.join(", ")));
+        for body_line in body {
+            self.expand_line(&body_line, depth + 1, out);
+        }
+        out.push(format!("{}# <<< {}", indent, name));
+    }
+}
+
+/// Shows a file with `.eqv` constants substituted and macro invocations
+/// replaced by their bodies, wrapped in `# >>>` / `# <<<` marker comments.
+pub fn expand(contents: &str) -> String {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut expander = Expander {
+        eqvs: collect_eqvs(&lines),
+        macros: collect_macros(&lines),
+        expansions: 0,
+    };
+
+    let mut out = Vec::new();
+    let mut in_macro = false;
+
+    for line in lines {
+        let code = line.trim();
+        if code.starts_with(".macro") {
+            in_macro = true;
+        }
+
+        if in_macro || code.starts_with(".eqv") {
+            out.push(line.to_string());
+        } else {
+            expander.expand_line(line, 0, &mut out);
+        }
+
+        if code.starts_with(".end_macro") {
+            in_macro = false;
+        }
+    }
+
+    out.join("\n") + "\n"
+}
@@ -0,0 +1,71 @@
+use crate::analysis::{self, references, Segment};
+
+// Index of the first line in the comment block directly above `line`
+fn comment_block_start(lines: &[&str], line: usize) -> usize {
+    let mut start = line;
+    while start > 0 && lines[start - 1].trim_start().starts_with('#') {
+        start -= 1;
+    }
+    start
+}
+
+/// Pulls a procedure out of a file, along with its comment block and the
+/// `.data` items only it references, as a standalone source file.
+pub fn extract(contents: &str, name: &str) -> Option<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let statements = analysis::parse(contents);
+
+    let start = statements
+        .iter()
+        .position(|s| s.segment == Segment::Text && s.labels.iter().any(|l| l == name))?;
+
+    let mut boundaries = analysis::procedure_labels(&statements);
+    boundaries.retain(|l| l != name);
+    let end = statements[(start + 1)..]
+        .iter()
+        .position(|s| {
+            s.segment != Segment::Text
+                || matches!(s.mnemonic.as_deref(), Some(".text" | ".ktext"))
+                || s.labels.iter().any(|l| boundaries.contains(l))
+        })
+        .map_or(statements.len(), |i| start + 1 + i);
+
+    let first_line = comment_block_start(&lines, statements[start].line - 1);
+    let last_line = match statements.get(end) {
+        Some(next) => comment_block_start(&lines, next.line - 1),
+        None => lines.len(),
+    };
+    let body = &statements[start..end];
+
+    let used: Vec<&str> = body.iter().flat_map(references).collect();
+    let used_elsewhere: Vec<&str> = statements
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| (*i < start || *i >= end) && s.segment == Segment::Text)
+        .flat_map(|(_, s)| references(s))
+        .collect();
+
+    let mut data = Vec::new();
+    let mut taking = false;
+    for statement in statements.iter().filter(|s| s.segment == Segment::Data) {
+        if !statement.labels.is_empty() {
+            taking = statement
+                .labels
+                .iter()
+                .any(|l| used.contains(&l.as_str()) && !used_elsewhere.contains(&l.as_str()));
+        }
+        if taking && !statement.is_comment_only() {
+            data.push(lines[statement.line - 1]);
+        }
+    }
+
+    let mut out = Vec::new();
+    if !data.is_empty() {
+        out.push(".data");
+        out.extend(data);
+        out.push(".text");
+    }
+    out.extend(&lines[first_line..last_line]);
+
+    Some(out.join("\n"))
+}
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{comment_start, split_operands};
+
+fn code_of(line: &str) -> &str {
+    match comment_start(line) {
+        Some(hash) => line[..hash].trim(),
+        None => line.trim(),
+    }
+}
+
+// The path named by an `.include "file"` line
+fn include_path(line: &str) -> Option<&str> {
+    let rest = code_of(line).strip_prefix(".include")?.trim();
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+// Finds an included file next to the file including it, or failing that in
+// one of the include paths
+fn resolve<F>(
+    read: &F,
+    include_paths: &[PathBuf],
+    dir: &Path,
+    included: &str,
+) -> Result<(PathBuf, String), String>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    let path = dir.join(included);
+    match read(&path) {
+        Ok(contents) => Ok((path, contents)),
+        Err(e) => include_paths
+            .iter()
+            .map(|p| p.join(included))
+            .find_map(|path| Some((path.clone(), read(&path).ok()?)))
+            .ok_or(e),
+    }
+}
+
+struct Flattener<'a, F> {
+    read: &'a F,
+    include_paths: &'a [PathBuf],
+    stack: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    out: Vec<String>,
+}
+
+impl<F> Flattener<'_, F>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    fn resolve(&self, dir: &Path, included: &str) -> Result<(PathBuf, String), String> {
+        resolve(self.read, self.include_paths, dir, included)
+    }
+
+    fn include(&mut self, path: &Path, contents: &str) -> Result<(), String> {
+        if self.stack.iter().any(|p| p == path) {
+            let chain: Vec<String> = self.stack.iter().map(|p| p.display().to_string()).collect();
+            return Err(format!(
+                "include cycle: {} -> {}",
+                chain.join(" -> "),
+                path.display()
+            ));
+        }
+
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.stack.push(path.to_path_buf());
+        if !self.files.iter().any(|p| p == path) {
+            self.files.push(path.to_path_buf());
+        }
+
+        for line in contents.lines() {
+            match include_path(line) {
+                Some(included) => {
+                    let indent = &line[..(line.len() - line.trim_start().len())];
+                    self.out
+                        .push(format!("{}# >>> include \"{}\"", indent, included));
+                    let (path, contents) = self.resolve(&dir, included)?;
+                    self.include(&path, &contents)?;
+                    self.out
+                        .push(format!("{}# <<< include \"{}\"", indent, included));
+                }
+                None => self.out.push(line.to_string()),
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+fn flattener<'a, F>(
+    path: &Path,
+    include_paths: &'a [PathBuf],
+    read: &'a F,
+) -> Result<Flattener<'a, F>, String>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    let mut flattener = Flattener {
+        read,
+        include_paths,
+        stack: Vec::new(),
+        files: Vec::new(),
+        out: Vec::new(),
+    };
+    flattener.include(path, &read(path)?)?;
+    Ok(flattener)
+}
+
+// Drops symbols from `.globl` lines that an earlier line already declared
+fn dedup_globls(lines: Vec<String>) -> Vec<String> {
+    let mut seen: Vec<String> = Vec::new();
+    let mut out = Vec::new();
+
+    for line in lines {
+        let operands = match code_of(&line)
+            .strip_prefix(".globl")
+            .or_else(|| code_of(&line).strip_prefix(".global"))
+        {
+            Some(rest) if rest.starts_with(char::is_whitespace) => split_operands(rest),
+            _ => {
+                out.push(line);
+                continue;
+            }
+        };
+
+        let declared = operands.len();
+        let new: Vec<String> = operands
+            .into_iter()
+            .filter(|symbol| !seen.contains(symbol))
+            .collect();
+        if new.is_empty() {
+            continue;
+        }
+
+        if new.len() == declared {
+            out.push(line);
+        } else {
+            let indent = &line[..(line.len() - line.trim_start().len())];
+            out.push(format!("{}.globl {}", indent, new.join(", ")));
+        }
+        seen.extend(new);
+    }
+
+    out
+}
+
+/// Inlines every `.include` reachable from `path` into a single file, with
+/// marker comments showing where each file starts and ends. Includes not found
+/// next to the including file are looked for in `include_paths`, and `read`
+/// loads a file's contents.
+pub fn flatten<F>(path: &Path, include_paths: &[PathBuf], read: &F) -> Result<String, String>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    let flattener = flattener(path, include_paths, read)?;
+    Ok(dedup_globls(flattener.out).join("\n") + "\n")
+}
+
+/// `path` and every file it includes, directly or not, in the order they're
+/// first included.
+pub fn included_files<F>(
+    path: &Path,
+    include_paths: &[PathBuf],
+    read: &F,
+) -> Result<Vec<PathBuf>, String>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    Ok(flattener(path, include_paths, read)?.files)
+}
+
+/// `path` and every file it includes, directly or not, along with a message
+/// for each include that couldn't be found. Unlike `included_files`, a missing
+/// file doesn't stop the search.
+pub fn follow_includes<F>(
+    path: &Path,
+    include_paths: &[PathBuf],
+    read: &F,
+) -> (Vec<PathBuf>, Vec<String>)
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    let mut files = vec![path.to_path_buf()];
+    let mut missing = Vec::new();
+    let mut pending = vec![(path.to_path_buf(), read(path).unwrap_or_default())];
+
+    while let Some((path, contents)) = pending.pop() {
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut found = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let included = match include_path(line) {
+                Some(included) => included,
+                None => continue,
+            };
+            match resolve(read, include_paths, &dir, included) {
+                Ok((path, _)) if files.contains(&path) => {}
+                Ok((path, contents)) => {
+                    files.push(path.clone());
+                    found.push((path, contents));
+                }
+                Err(_) => missing.push(format!(
+                    "{}:{}: couldn't find included file \"{}\"",
+                    path.display(),
+                    i + 1,
+                    included
+                )),
+            }
+        }
+        pending.extend(found.into_iter().rev());
+    }
+
+    (files, missing)
+}
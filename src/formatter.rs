@@ -1,35 +1,54 @@
 use std::fmt::Error;
-
+use std::ops::Range;
+
+use crate::analysis::{self, split_operands, Segment, Statement};
+use crate::config::{
+    self, AddressLiterals, BlankComments, BlankLineRuns, CommentOverflow, Config,
+    DataListContinuation, GloblStyle, HexCase, OperatorSpacing, VersionHeader,
+};
+use crate::isa;
+use crate::json::Json;
+use crate::labels;
+
+use self::line::split_label;
 use self::line::CodeLine;
-use self::line::SplitLine;
-
-static MAX_COMMENT_DISPARITY: usize = 10;
 
 mod line {
-    #[derive(Debug)]
+    use std::fmt;
+
+    use crate::config::Config;
+
+    #[derive(Debug, Clone)]
     enum CodeToken {
         Space,
         Item(String),
+        Number(String),
+        Operator(char),
         Comma,
         Colon,
         ParenOpen,
         ParenClose,
         Literal(String),
+        CharLiteral(String),
     }
 
-    impl CodeToken {
-        pub fn to_string(&self) -> String {
-            return match self {
-                CodeToken::Space => String::new(),
-                CodeToken::Comma => String::from(","),
-                CodeToken::Colon => String::from(":"),
-                CodeToken::ParenOpen => String::from("("),
-                CodeToken::ParenClose => String::from(")"),
-                CodeToken::Item(item) => String::from(item),
-                CodeToken::Literal(string) => format!("\"{}\"", string),
-            };
+    impl fmt::Display for CodeToken {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                CodeToken::Space => Ok(()),
+                CodeToken::Comma => write!(f, ","),
+                CodeToken::Colon => write!(f, ":"),
+                CodeToken::ParenOpen => write!(f, "("),
+                CodeToken::ParenClose => write!(f, ")"),
+                CodeToken::Item(item) | CodeToken::Number(item) => write!(f, "{}", item),
+                CodeToken::Operator(op) => write!(f, "{}", op),
+                CodeToken::Literal(string) => write!(f, "\"{}\"", string),
+                CodeToken::CharLiteral(c) => write!(f, "'{}'", c),
+            }
         }
+    }
 
+    impl CodeToken {
         pub fn from(c: char) -> Self {
             match c {
                 ',' => CodeToken::Comma,
@@ -41,6 +60,22 @@ mod line {
         }
     }
 
+    // Whether the next character is escaped by the backslashes ending `text`
+    fn ends_escaped(text: &str) -> bool {
+        text.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+    }
+
+    // Whether `item` is a decimal, `0x` hex or `0b` binary literal
+    fn is_number(item: &str) -> bool {
+        let digits = item.trim_start_matches(['-', '+']);
+        let (radix, digits) = match digits.get(..2) {
+            Some("0x" | "0X") => (16, &digits[2..]),
+            Some("0b" | "0B") => (2, &digits[2..]),
+            _ => (10, digits),
+        };
+        !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix))
+    }
+
     fn tokenise_line(code: &str) -> Vec<CodeToken> {
         let mut tokens = vec![CodeToken::Space];
 
@@ -51,25 +86,110 @@ mod line {
                     tokens.push(CodeToken::Space)
                 }
                 (CodeToken::Literal(cur), c) => *cur += &c.to_string(),
+                (CodeToken::CharLiteral(cur), '\'') if !ends_escaped(cur) => {
+                    tokens.push(CodeToken::Space)
+                }
+                (CodeToken::CharLiteral(cur), c) => cur.push(c),
 
                 (CodeToken::Space, c) if c.is_whitespace() => {}
                 (_, c) if c.is_whitespace() => tokens.push(CodeToken::Space),
 
                 (_, ',' | ':' | '(' | ')') => tokens.push(CodeToken::from(c)),
                 (_, '"') => tokens.push(CodeToken::Literal(String::new())),
+                (_, '\'') => tokens.push(CodeToken::CharLiteral(String::new())),
 
                 (CodeToken::Item(cur), c) => *cur += &c.to_string(),
                 (_, c) => tokens.push(CodeToken::Item(c.into())),
             }
         }
 
-        return tokens
+        tokens
             .into_iter()
-            .filter(|t| match t {
-                CodeToken::Space => false,
-                _ => true,
+            .filter(|t| !matches!(t, CodeToken::Space))
+            .map(|t| match t {
+                CodeToken::Item(item) if is_number(&item) => CodeToken::Number(item),
+                t => t,
             })
-            .collect();
+            .collect()
+    }
+
+    // A token, or an arithmetic operator split out of an operand expression
+    enum Atom {
+        Token(CodeToken),
+        Operator(char),
+    }
+
+    fn item_token(item: String) -> CodeToken {
+        match is_number(&item) {
+            true => CodeToken::Number(item),
+            false => CodeToken::Item(item),
+        }
+    }
+
+    // Splits items like `label+4` or `-8` around their operators, keeping the
+    // sign of a float's exponent like `1.5e-3`
+    fn split_operators(tokens: Vec<CodeToken>) -> Vec<Atom> {
+        let mut atoms = Vec::new();
+        for token in tokens {
+            let item = match token {
+                CodeToken::Item(item) | CodeToken::Number(item) => item,
+                token => {
+                    atoms.push(Atom::Token(token));
+                    continue;
+                }
+            };
+            let mut piece = String::new();
+            for c in item.chars() {
+                let is_exponent = matches!(c, '+' | '-')
+                    && piece.starts_with(|c: char| c.is_ascii_digit())
+                    && !piece.starts_with("0x")
+                    && piece.ends_with(['e', 'E']);
+                match c {
+                    '+' | '-' | '*' | '/' if !is_exponent => {
+                        if !piece.is_empty() {
+                            atoms.push(Atom::Token(item_token(std::mem::take(&mut piece))));
+                        }
+                        atoms.push(Atom::Operator(c));
+                    }
+                    c => piece.push(c),
+                }
+            }
+            if !piece.is_empty() {
+                atoms.push(Atom::Token(item_token(piece)));
+            }
+        }
+        atoms
+    }
+
+    // Whether the operator at `i` is a sign rather than between two operands,
+    // as it is at the start of an operand or straight after the mnemonic
+    fn is_unary(atoms: &[Atom], i: usize) -> bool {
+        if !matches!(atoms[i], Atom::Operator('+' | '-')) {
+            return false;
+        }
+        let before = |j: usize| j.checked_sub(1).map(|j| &atoms[j]);
+        match before(i) {
+            None
+            | Some(Atom::Operator(_))
+            | Some(Atom::Token(CodeToken::Comma | CodeToken::ParenOpen | CodeToken::Colon)) => true,
+            Some(Atom::Token(CodeToken::Item(_))) => match before(i - 1) {
+                None | Some(Atom::Token(CodeToken::Colon)) => true,
+                Some(Atom::Token(CodeToken::Item(item))) => item == ".eqv",
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn join_tokens(tokens: &[CodeToken]) -> String {
+        let mut code = tokens[0].to_string();
+        for pair in tokens.windows(2) {
+            if should_be_spaced(&pair[0], &pair[1]) {
+                code += " ";
+            }
+            code += &pair[1].to_string();
+        }
+        code
     }
 
     #[derive(Debug, Clone)]
@@ -78,6 +198,8 @@ mod line {
         pub comment: Option<String>,
         pub com_gap: Option<usize>,
         indent: usize,
+        // The line as written, for lines left unformatted
+        raw: Option<String>,
     }
 
     impl Default for CodeLine {
@@ -93,6 +215,21 @@ mod line {
                 comment,
                 com_gap: None,
                 indent: 0,
+                raw: None,
+            }
+        }
+
+        // A line that is kept exactly as written
+        pub fn verbatim(line: &str) -> Self {
+            CodeLine::new((!line.is_empty()).then(|| line.to_string()), None)
+        }
+
+        // A line inside a section that is left exactly as written, which
+        // has no code or comment for the formatter to change
+        pub fn raw(line: &str) -> Self {
+            CodeLine {
+                raw: Some(line.to_string()),
+                ..CodeLine::new(None, None)
             }
         }
 
@@ -101,38 +238,90 @@ mod line {
                 return CodeLine::new(None, None);
             }
 
-            if let Some(comment_index) = line.find('#') {
+            if let Some(comment_index) = crate::analysis::comment_start(line) {
                 let code = line[..comment_index].trim().to_string();
 
                 if code.is_empty() {
                     return CodeLine::new(None, Some(line[(comment_index + 1)..].trim().into()));
                 }
 
-                return CodeLine::new(Some(code), Some(line[(comment_index + 1)..].trim().into()));
+                CodeLine::new(Some(code), Some(line[(comment_index + 1)..].trim().into()))
             } else {
-                return CodeLine::new(Some(line.trim().into()), None);
+                CodeLine::new(Some(line.trim().into()), None)
             }
         }
 
         pub fn format(&mut self) {
             if let Some(code) = &mut self.code {
-                let tokens = tokenise_line(&code);
-                *code = tokens[0].to_string();
+                *code = join_tokens(&tokenise_line(code));
+            }
+        }
+
+        // Writes signs against their operand, and other operators with a
+        // space either side when `spaced`, or none when not
+        pub fn space_operators(&mut self, spaced: bool) {
+            let Some(code) = &mut self.code else {
+                return;
+            };
+            let atoms = split_operators(tokenise_line(code));
+            let mut tokens: Vec<CodeToken> = Vec::new();
+            let mut glue = false;
+
+            for (i, atom) in atoms.iter().enumerate() {
+                match atom {
+                    Atom::Operator(op) if spaced && !is_unary(&atoms, i) => {
+                        tokens.push(CodeToken::Operator(*op));
+                        glue = false;
+                    }
+                    Atom::Operator(op) => {
+                        match (is_unary(&atoms, i), tokens.last_mut()) {
+                            (false, Some(CodeToken::Item(prev) | CodeToken::Number(prev))) => {
+                                prev.push(*op)
+                            }
+                            _ => tokens.push(CodeToken::Item(op.to_string())),
+                        }
+                        glue = true;
+                    }
+                    Atom::Token(CodeToken::Item(item) | CodeToken::Number(item)) if glue => {
+                        match tokens.last_mut() {
+                            Some(CodeToken::Item(prev) | CodeToken::Number(prev)) => *prev += item,
+                            _ => tokens.push(CodeToken::Item(item.clone())),
+                        }
+                        glue = false;
+                    }
+                    Atom::Token(token) => {
+                        tokens.push(token.clone());
+                        glue = false;
+                    }
+                }
+            }
+            *code = join_tokens(&tokens);
+        }
 
-                for pair in tokens.windows(2) {
-                    if should_be_spaced(&pair[0], &pair[1]) {
-                        *code += " ";
+        // Rewrites each numeric literal in the code with `f`, which is also
+        // told whether the literal is the whole of the last operand, rather
+        // than an offset like the `4` in `arr + 4` or `4($sp)`
+        pub fn map_numbers(&mut self, f: impl Fn(&str, bool) -> String) {
+            if let Some(code) = &mut self.code {
+                let mut tokens = tokenise_line(code);
+                let last = tokens.len() - 1;
+                let bare_last = match tokens[..last] {
+                    [.., CodeToken::Comma] => true,
+                    // The only operand, after the mnemonic and any label
+                    [CodeToken::Item(_)] | [.., CodeToken::Colon, CodeToken::Item(_)] => true,
+                    _ => false,
+                };
+                for (i, token) in tokens.iter_mut().enumerate() {
+                    if let CodeToken::Number(number) = token {
+                        *number = f(number, i == last && bare_last);
                     }
-                    *code += &pair[1].to_string();
                 }
+                *code = join_tokens(&tokens);
             }
         }
 
         pub fn is_comment_only(&self) -> bool {
-            match (&self.code, &self.comment) {
-                (None, Some(_)) => true,
-                _ => false,
-            }
+            matches!((&self.code, &self.comment), (None, Some(_)))
         }
 
         pub fn code_w(&self) -> usize {
@@ -144,7 +333,7 @@ mod line {
 
         pub fn is_empty(&self) -> bool {
             match (&self.code, &self.comment) {
-                (None, None) => true,
+                (None, None) => self.raw.is_none(),
                 (_, _) => false,
             }
         }
@@ -167,6 +356,18 @@ mod line {
             self.indent += 1;
         }
 
+        // A comment-only line at the same indentation as this one
+        pub fn standalone_comment(&self, comment: String) -> Self {
+            CodeLine {
+                indent: self.indent,
+                ..CodeLine::new(None, Some(comment))
+            }
+        }
+
+        pub fn indent_width(&self, config: &Config) -> usize {
+            self.indent * config.indent.width()
+        }
+
         pub fn set_hash_index(&mut self, h_index: usize) {
             self.com_gap = if h_index >= self.code_w() {
                 Some(h_index - self.code_w())
@@ -175,57 +376,67 @@ mod line {
             };
         }
 
-        fn to_string_without_indent(&self) -> String {
+        pub fn render_without_indent(&self, config: &Config) -> String {
+            if let Some(raw) = &self.raw {
+                return raw.trim_start().to_string();
+            }
             match (&self.code, &self.comment) {
                 (None, None) => String::new(),
                 (Some(code), None) => code.into(),
-                (None, Some(comment)) => format!("# {}", comment),
+                (None, Some(comment)) => format!("# {}", comment).trim_end().to_string(),
                 (Some(code), Some(comment)) => {
-                    let comment_gap = (0..self.com_gap.unwrap_or(2))
-                        .map(|_| " ")
-                        .collect::<String>();
+                    let comment_gap = " ".repeat(self.com_gap.unwrap_or(config.comment_gap));
                     format!("{}{}# {}", code, comment_gap, comment)
+                        .trim_end()
+                        .to_string()
                 }
             }
         }
 
-        pub fn to_string(&self) -> String {
-            let indents: String = (0..self.indent).map(|_| "\t").collect();
-            return indents + &self.to_string_without_indent();
+        pub fn render(&self, config: &Config) -> String {
+            if let Some(raw) = &self.raw {
+                return raw.clone();
+            }
+            let indents = config.indent.unit().repeat(self.indent);
+            indents + &self.render_without_indent(config)
         }
     }
 
     fn should_be_spaced(left: &CodeToken, right: &CodeToken) -> bool {
         match (left, right) {
+            (CodeToken::Operator(_), _) | (_, CodeToken::Operator(_)) => true,
             (
-                CodeToken::Item(_) | CodeToken::Literal(_) | CodeToken::Comma | CodeToken::Colon,
-                CodeToken::Item(_) | CodeToken::Literal(_),
+                CodeToken::Item(_)
+                | CodeToken::Number(_)
+                | CodeToken::Literal(_)
+                | CodeToken::CharLiteral(_)
+                | CodeToken::Comma
+                | CodeToken::Colon,
+                CodeToken::Item(_)
+                | CodeToken::Number(_)
+                | CodeToken::Literal(_)
+                | CodeToken::CharLiteral(_),
             ) => true,
             (CodeToken::Comma, CodeToken::ParenOpen) => true,
             (_, _) => false,
         }
     }
 
-    #[derive(Debug)]
-    pub enum SplitLine<'a> {
-        One(&'a str),
-        Two((&'a str, &'a str)),
-    }
-
-    impl<'a> SplitLine<'a> {
-        pub fn parse(line: &'a str) -> SplitLine<'a> {
-            if let Some(colon_i) = line.find(':') {
-                if let Some(hash_i) = line.find('#') {
-                    if colon_i < hash_i {
-                        if !&line[(colon_i + 1)..hash_i].trim().is_empty() {
-                            return SplitLine::Two((&line[..=colon_i], &line[(colon_i + 1)..]));
-                        }
-                    }
-                } else {
-                    return SplitLine::Two((&line[..=colon_i], &line[(colon_i + 1)..]));
-                }
+    // Splits a leading label off a line, leaving any later ones for the
+    // caller to split off in turn
+    pub fn split_label(line: &str) -> Option<(&str, &str)> {
+        let is_label = |text: &str| {
+            !text.is_empty()
+                && text
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '$' | '%'))
+        };
+        let colon_i = line.find(':').filter(|i| is_label(line[..*i].trim()))?;
+        match crate::analysis::comment_start(line) {
+            Some(hash_i) if colon_i > hash_i || line[(colon_i + 1)..hash_i].trim().is_empty() => {
+                None
             }
-            return SplitLine::One(line);
+            _ => Some((&line[..=colon_i], &line[(colon_i + 1)..])),
         }
     }
 }
@@ -234,15 +445,41 @@ mod line {
 enum Directive {
     Text,
     Data,
+    Verbatim,
+}
+
+// Sections the formatter doesn't understand, which are passed through as written
+static VERBATIM_SECTIONS: &[&str] = &[
+    ".bss", ".lit4", ".lit8", ".rdata", ".rodata", ".sbss", ".sdata", ".section",
+];
+
+fn section_of(line: &str) -> Option<Directive> {
+    let directive = line.split(|c: char| c.is_whitespace() || c == '#').next()?;
+    match directive {
+        ".text" | ".ktext" => Some(Directive::Text),
+        ".data" | ".kdata" => Some(Directive::Data),
+        _ if VERBATIM_SECTIONS.contains(&directive) => Some(Directive::Verbatim),
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
 enum Chunk {
-    Space,
-    GlobDec(CodeLine),
+    // The number of blank lines in a row
+    Space(usize),
+    GlobDec(Vec<CodeLine>),
+    // A run of `.extern` declarations, set apart like `.globl` lines
+    Extern(Vec<CodeLine>),
     Modifier(CodeLine),
     Code(Vec<CodeLine>),
     Comment(Vec<CodeLine>),
+    Verbatim(Vec<CodeLine>),
+    // A run of `.eqv` constants, kept flush
+    Eqv(Vec<CodeLine>),
+    // A run of `.include` lines, kept flush
+    Include(Vec<CodeLine>),
+    // From `.macro` to `.end_macro`, with the body indented
+    Macro(Vec<CodeLine>),
 }
 
 #[derive(Debug)]
@@ -257,7 +494,9 @@ impl Section {
         let dir_line = if line.is_empty() {
             None
         } else {
-            Some(CodeLine::parse(line))
+            let mut dir_line = CodeLine::parse(line);
+            dir_line.format();
+            Some(dir_line)
         };
 
         Section {
@@ -268,40 +507,191 @@ impl Section {
     }
 }
 
-fn parse_sections(lines: &Vec<&str>) -> Vec<Section> {
+/// The first and last line index of each region between the
+/// `protected_region` markers, which is kept exactly as written. Fails if the
+/// markers are set but no region is found, or one is never closed.
+pub fn protected_regions(contents: &str, config: &Config) -> Result<Vec<(usize, usize)>, String> {
+    let (start, end) = match config.protected_region.as_slice() {
+        [start, end] => (start.as_str(), end.as_str()),
+        _ => return Ok(Vec::new()),
+    };
+    let mut regions = Vec::new();
+    let mut open = None;
+
+    for (i, line) in contents.lines().enumerate() {
+        match open {
+            None if line.trim().starts_with(start) => open = Some(i),
+            Some(from) if line.trim().starts_with(end) => {
+                regions.push((from, i));
+                open = None;
+            }
+            _ => {}
+        }
+    }
+
+    match open {
+        Some(from) => Err(format!(
+            "The protected region opened on line {} is never closed with `{}`",
+            from + 1,
+            end
+        )),
+        None if regions.is_empty() => Err(format!(
+            "There is no protected region between `{}` and `{}`",
+            start, end
+        )),
+        None => Ok(regions),
+    }
+}
+
+/// The first and last line index of each region kept exactly as written:
+/// protected regions, `# macmips: off` up to `# macmips: on` (or the end of
+/// the file), and lines marked `# macmips: skip` or following a line that is
+/// only that comment.
+pub fn unformatted_regions(contents: &str, config: &Config) -> Vec<(usize, usize)> {
+    let mut regions = protected_regions(contents, config).unwrap_or_default();
+    let mut off = None;
+    let mut last = 0;
+
+    for (i, line) in contents.lines().enumerate() {
+        last = i;
+        let directive = match crate::analysis::comment_start(line) {
+            Some(hash) => line[(hash + 1)..].trim(),
+            None => continue,
+        };
+        match (directive, off) {
+            ("macmips: off", None) => off = Some(i),
+            ("macmips: on", Some(from)) => {
+                regions.push((from, i));
+                off = None;
+            }
+            ("macmips: skip", None) => match line.trim_start().starts_with('#') {
+                true => regions.push((i, i + 1)),
+                false => regions.push((i, i)),
+            },
+            _ => {}
+        }
+    }
+    regions.extend(off.map(|from| (from, last)));
+
+    regions
+}
+
+fn parse_sections(lines: &[&str], unformatted: &[(usize, usize)]) -> Vec<Section> {
     let mut sections: Vec<Section> = vec![Section::new("", Directive::Text)];
 
-    for line in lines {
+    for (i, raw) in lines.iter().enumerate() {
+        let line = raw.trim();
         let cur_section = sections.last_mut().unwrap();
-        match (&cur_section.dir, line) {
-            (_, line) if line.starts_with(".text") => {
-                sections.push(Section::new(line, Directive::Text));
-            }
-            (_, line) if line.starts_with(".data") => {
-                sections.push(Section::new(line, Directive::Data));
-            }
-            (Directive::Data, line) => cur_section.lines.push(CodeLine::parse(line)),
-            (Directive::Text, line) => match SplitLine::parse(line) {
-                SplitLine::One(line) => cur_section.lines.push(CodeLine::parse(line)),
-                SplitLine::Two((part1, part2)) => cur_section
-                    .lines
-                    .extend([CodeLine::parse(part1), CodeLine::parse(part2)]),
-            },
+        let is_raw = unformatted.iter().any(|(s, e)| (*s..=*e).contains(&i));
+
+        match (&cur_section.dir, section_of(line)) {
+            (_, Some(Directive::Verbatim)) => sections.push(Section {
+                dir: Directive::Verbatim,
+                dir_line: None,
+                lines: vec![CodeLine::verbatim(raw)],
+            }),
+            (_, Some(dir)) if is_raw => sections.push(Section {
+                dir,
+                dir_line: Some(CodeLine::raw(raw)),
+                lines: Vec::new(),
+            }),
+            (_, Some(dir)) => sections.push(Section::new(line, dir)),
+            (Directive::Verbatim, None) => cur_section.lines.push(CodeLine::verbatim(raw)),
+            (_, None) if is_raw => cur_section.lines.push(CodeLine::raw(raw)),
+            (Directive::Data, None) => cur_section.lines.push(CodeLine::parse(line)),
+            (Directive::Text, None) => {
+                let mut rest = line;
+                while let Some((label, after)) = split_label(rest) {
+                    cur_section.lines.push(CodeLine::parse(label));
+                    rest = after;
+                }
+                cur_section.lines.push(CodeLine::parse(rest));
+            }
+        }
+    }
+
+    sections
+}
+
+// Matches text against a pattern where `*` stands for any run of characters
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let text = match text.strip_prefix(prefix) {
+                Some(text) => text,
+                None => return false,
+            };
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| matches_pattern(rest, &text[i..]))
         }
     }
+}
+
+fn is_procedure_comment(line: &CodeLine, config: &Config) -> bool {
+    match (&line.comment, line.is_comment_only()) {
+        (Some(comment), true) => config
+            .procedure_comments
+            .iter()
+            .any(|p| matches_pattern(p.trim_start_matches('#').trim(), comment)),
+        _ => false,
+    }
+}
+
+fn macro_closed(lines: &[CodeLine]) -> bool {
+    lines.len() > 1 && lines.last().unwrap().starts_with(".end_macro")
+}
+
+// The body of a macro chunk, between its `.macro` and `.end_macro`
+fn macro_body(lines: &mut [CodeLine]) -> &mut [CodeLine] {
+    let end = match macro_closed(lines) {
+        true => lines.len() - 1,
+        false => lines.len(),
+    };
+    &mut lines[1..end]
+}
 
-    return sections;
+// `.macro name(%a,%b)` as `.macro name (%a, %b)`, the way MARS documents it
+fn format_macro_header(line: &mut CodeLine) {
+    if let Some(code) = line.code.as_mut().filter(|c| c.starts_with(".macro ")) {
+        if let Some(paren) = code.find('(').filter(|&i| !code[..i].ends_with(' ')) {
+            code.insert(paren, ' ');
+        }
+    }
 }
 
-fn parse_chunks(lines: Vec<CodeLine>, dir: &Directive) -> Vec<Chunk> {
-    let mut chunks = vec![Chunk::Space];
+fn parse_chunks(lines: Vec<CodeLine>, dir: &Directive, config: &Config) -> Vec<Chunk> {
+    let mut chunks = vec![Chunk::Space(0)];
 
     for line in lines {
         let cur_chunk = chunks.last_mut().unwrap();
         match (cur_chunk, dir, line) {
-            (Chunk::Space, _, line) if line.is_empty() => {}
-            (_, _, line) if line.is_empty() => chunks.push(Chunk::Space),
-            (_, _, line) if line.starts_with(".globl") => chunks.push(Chunk::GlobDec(line)),
+            // === MACROS ===
+            (Chunk::Macro(cur), _, line)
+                if !macro_closed(cur) && line.starts_with(".end_macro") =>
+            {
+                cur.push(line)
+            }
+            (Chunk::Macro(cur), _, mut line) if !macro_closed(cur) => {
+                line.indent();
+                cur.push(line);
+            }
+            (_, _, line) if line.starts_with(".macro") => chunks.push(Chunk::Macro(vec![line])),
+
+            (Chunk::Space(n), _, line) if line.is_empty() => *n += 1,
+            (_, _, line) if line.is_empty() => chunks.push(Chunk::Space(1)),
+            (Chunk::GlobDec(cur), _, line) if line.starts_with(".globl") => cur.push(line),
+            (_, _, line) if line.starts_with(".globl") => chunks.push(Chunk::GlobDec(vec![line])),
+            (Chunk::Extern(cur), _, line) if line.starts_with(".extern") => cur.push(line),
+            (_, _, line) if line.starts_with(".extern") => chunks.push(Chunk::Extern(vec![line])),
+            (Chunk::Eqv(cur), _, line) if line.starts_with(".eqv") => cur.push(line),
+            (_, _, line) if line.starts_with(".eqv") => chunks.push(Chunk::Eqv(vec![line])),
+            (Chunk::Include(cur), _, line) if line.starts_with(".include") => cur.push(line),
+            (_, _, line) if line.starts_with(".include") => chunks.push(Chunk::Include(vec![line])),
+            (_, Directive::Text, line) if is_procedure_comment(&line, config) => {
+                chunks.push(Chunk::Modifier(line));
+            }
 
             // === COMMENT PARSING ===
             (Chunk::Comment(cur), _, line) if line.is_comment_only() => {
@@ -310,6 +700,7 @@ fn parse_chunks(lines: Vec<CodeLine>, dir: &Directive) -> Vec<Chunk> {
             (_, _, line) if line.is_comment_only() => chunks.push(Chunk::Comment(vec![line])),
 
             // === Modifiers ===
+            (_, _, line) if line.starts_with(".set ") => chunks.push(Chunk::Modifier(line)),
             (_, Directive::Data, line) if line.starts_with(".align") => {
                 chunks.push(Chunk::Modifier(line));
             }
@@ -325,57 +716,215 @@ fn parse_chunks(lines: Vec<CodeLine>, dir: &Directive) -> Vec<Chunk> {
         }
     }
 
-    return chunks;
+    join_interrupted_code(chunks)
 }
 
-fn calc_hash_index(lines: &Vec<CodeLine>) -> usize {
+// Rejoins code split by comments on their own lines with no blank line
+// around them, which belong to the code rather than introducing it
+fn join_interrupted_code(chunks: Vec<Chunk>) -> Vec<Chunk> {
+    let mut joined: Vec<Chunk> = Vec::new();
+    let mut chunks = chunks.into_iter().peekable();
+
+    while let Some(chunk) = chunks.next() {
+        match (joined.last_mut(), chunk) {
+            (Some(Chunk::Code(code)), Chunk::Comment(comments))
+                if matches!(chunks.peek(), Some(Chunk::Code(_))) =>
+            {
+                code.extend(comments);
+                if let Some(Chunk::Code(rest)) = chunks.next() {
+                    code.extend(rest);
+                }
+            }
+            (_, chunk) => joined.push(chunk),
+        }
+    }
+
+    joined
+}
+
+fn calc_hash_index(lines: &[CodeLine], config: &Config) -> usize {
     let max_length_all = lines.iter().map(|l| l.code_w()).max().unwrap_or(0);
     let max_length_comments = lines
         .iter()
-        .filter_map(|l| match l.comment {
-            Some(_) => Some(l.code_w()),
-            None => None,
-        })
+        .filter_map(|l| l.comment.as_ref().map(|_| l.code_w()))
         .max()
         .unwrap_or(0);
 
-    if max_length_all - max_length_comments >= MAX_COMMENT_DISPARITY {
-        max_length_comments + 2
+    if max_length_all - max_length_comments >= config.comment_disparity {
+        max_length_comments + config.comment_gap
     } else {
-        max_length_all + 2
+        max_length_all + config.comment_gap
     }
 }
 
-fn align_comments(chunk: &mut Chunk) {
-    if let Chunk::Code(lines) = chunk {
-        let comment_index = calc_hash_index(&lines);
-        lines
-            .into_iter()
-            .for_each(|l| l.set_hash_index(comment_index));
+// The column trailing comments are pinned to or capped at, if any
+fn comment_column(config: &Config) -> Option<usize> {
+    match (config.comment_column, config.comment_column_max) {
+        (0, 0) => None,
+        (0, max) => Some(max),
+        (column, _) => Some(column),
+    }
+}
+
+// Whether a line's code reaches past the comment column, leaving no room for
+// its comment there
+fn overflows(line: &CodeLine, config: &Config) -> bool {
+    match comment_column(config) {
+        Some(column) => {
+            line.code.is_some()
+                && line.comment.is_some()
+                && line.indent_width(config) + line.code_w() + config.comment_gap > column
+        }
+        None => false,
+    }
+}
+
+// Splits a comment into lines of at most `width` characters, breaking
+// between words
+fn wrap_comment(comment: &str, width: usize) -> Vec<String> {
+    let mut wrapped: Vec<String> = Vec::new();
+    for word in comment.split_whitespace() {
+        match wrapped.last_mut() {
+            Some(line) if line.len() + 1 + word.len() <= width => {
+                *line += " ";
+                *line += word;
+            }
+            _ => wrapped.push(word.to_string()),
+        }
+    }
+    wrapped
+}
+
+// Whether a line with its trailing comment would run past `max_width`
+fn too_wide(line: &CodeLine, config: &Config) -> bool {
+    match (&line.code, &line.comment) {
+        (Some(_), Some(comment)) => {
+            let width = line.indent_width(config)
+                + line.code_w()
+                + config.comment_gap
+                + 2
+                + comment.chars().count();
+            config.max_width > 0 && width > config.max_width
+        }
+        _ => false,
     }
 }
 
-fn indent_chunks(chunks: &mut Vec<Chunk>) {
+// Whether a line's trailing comment should go on its own line above it
+fn moves_above(line: &CodeLine, config: &Config) -> bool {
+    let too_long = match (&line.code, &line.comment) {
+        (Some(_), Some(comment)) => {
+            config.trailing_comment_max > 0 && comment.chars().count() > config.trailing_comment_max
+        }
+        _ => false,
+    };
+    too_long
+        || ((overflows(line, config) || too_wide(line, config))
+            && config.comment_overflow != CommentOverflow::Inline)
+}
+
+// Moves the comments of overflowing or overlong lines onto their own lines
+// above them, in the same chunk so the code around them stays together
+fn split_moved_comments(chunks: Vec<Chunk>, config: &Config) -> Vec<Chunk> {
+    let mut split = Vec::new();
+
+    for chunk in chunks {
+        let lines = match chunk {
+            Chunk::Code(lines) if lines.iter().any(|l| moves_above(l, config)) => lines,
+            chunk => {
+                split.push(chunk);
+                continue;
+            }
+        };
+
+        let mut code = Vec::new();
+        for mut line in lines {
+            if moves_above(&line, config) {
+                let width = match comment_column(config) {
+                    Some(column) if overflows(&line, config) => Some(column),
+                    _ if too_wide(&line, config) => Some(config.max_width),
+                    _ => None,
+                };
+                let comment = line.comment.take().unwrap();
+                let comments = match (config.comment_overflow, width) {
+                    (CommentOverflow::Wrap, Some(width)) => {
+                        let used = line.indent_width(config) + 2;
+                        wrap_comment(&comment, width.saturating_sub(used))
+                    }
+                    _ => vec![comment],
+                };
+                code.extend(comments.into_iter().map(|c| line.standalone_comment(c)));
+            }
+            code.push(line);
+        }
+        split.push(Chunk::Code(code));
+    }
+
+    split
+}
+
+fn align_comments(chunk: &mut Chunk, config: &Config) {
+    match chunk {
+        Chunk::Code(lines) | Chunk::Eqv(lines) => align_lines(lines, config),
+        Chunk::Macro(lines) => align_lines(macro_body(lines), config),
+        _ => {}
+    }
+}
+
+fn align_lines(lines: &mut [CodeLine], config: &Config) {
+    let fitting: Vec<CodeLine> = lines
+        .iter()
+        .filter(|l| !overflows(l, config))
+        .cloned()
+        .collect();
+    // Columns are counted from the start of the line, but the hash index
+    // from the start of the code
+    let indent = lines.first().map_or(0, |l| l.indent_width(config));
+    let mut comment_index = match config.comment_column {
+        0 => calc_hash_index(&fitting, config),
+        column => column.saturating_sub(indent),
+    };
+    if let Some(column) = comment_column(config) {
+        comment_index = comment_index.min(column.saturating_sub(indent));
+    }
+
+    for line in lines.iter_mut() {
+        match overflows(line, config) {
+            true => line.com_gap = None,
+            false => line.set_hash_index(comment_index),
+        }
+    }
+}
+
+fn indent_chunks(chunks: &mut [Chunk]) {
     let first_proc_index = chunks.iter().enumerate().find_map(|(i, b)| match b {
-        Chunk::Modifier(_) => Some(i),
+        Chunk::Modifier(line) if !line.starts_with(".set ") => Some(i),
         _ => None,
     });
 
     if let Some(index) = first_proc_index {
         let mut should_indent = false;
 
-        for block in chunks.into_iter().skip(index + 1).rev() {
+        for block in chunks.iter_mut().skip(index + 1).rev() {
             match (should_indent, block) {
                 (_, Chunk::Modifier(_)) => should_indent = false,
-                (_, Chunk::Code(lines)) => {
+                (_, Chunk::Code(lines) | Chunk::Macro(lines)) => {
                     should_indent = true;
-                    lines.into_iter().for_each(|l| l.indent());
+                    lines.iter_mut().for_each(|l| l.indent());
                 }
 
-                (true, Chunk::Comment(lines)) => lines.into_iter().for_each(|l| l.indent()),
+                (true, Chunk::Comment(lines)) => lines.iter_mut().for_each(|l| l.indent()),
                 (false, Chunk::Comment(_)) => {}
 
-                (_, Chunk::Space | Chunk::GlobDec(_)) => {}
+                (
+                    _,
+                    Chunk::Space(_)
+                    | Chunk::GlobDec(_)
+                    | Chunk::Extern(_)
+                    | Chunk::Verbatim(_)
+                    | Chunk::Eqv(_)
+                    | Chunk::Include(_),
+                ) => {}
             }
         }
     }
@@ -388,27 +937,48 @@ enum CompileState {
     AfterModifier,
 }
 
-fn compile_section(lines: &mut Vec<CodeLine>, dir_line: Option<CodeLine>, chunks: Vec<Chunk>) {
+// Pads `lines` out to end in at least `n` blank lines
+fn blank_lines(lines: &mut Vec<CodeLine>, n: usize) {
+    let trailing = lines.iter().rev().take_while(|l| l.is_empty()).count();
+    lines.extend((trailing..n).map(|_| CodeLine::default()));
+}
+
+fn compile_section(
+    lines: &mut Vec<CodeLine>,
+    dir_line: Option<CodeLine>,
+    chunks: Vec<Chunk>,
+    config: &Config,
+) {
     if let Some(dir_line) = dir_line {
-        lines.extend([dir_line, CodeLine::default()]);
+        lines.push(dir_line);
+        lines.extend((0..config.blank_lines_after_directive).map(|_| CodeLine::default()));
     }
 
     let mut state = CompileState::Free;
 
-    for block in chunks {
+    for (i, block) in chunks.into_iter().enumerate() {
         state = match (state, block) {
-            (CompileState::Free, Chunk::GlobDec(line)) => {
-                lines.extend([line, CodeLine::default()]);
+            (CompileState::Free, Chunk::GlobDec(_lines) | Chunk::Extern(_lines)) => {
+                lines.extend(_lines);
+                blank_lines(lines, config.blank_lines_around_globals);
                 CompileState::Free
             }
-            (_, Chunk::GlobDec(line)) => {
-                lines.extend([CodeLine::default(), line, CodeLine::default()]);
+            (_, Chunk::GlobDec(_lines) | Chunk::Extern(_lines)) => {
+                blank_lines(lines, config.blank_lines_around_globals);
+                lines.extend(_lines);
+                blank_lines(lines, config.blank_lines_around_globals);
                 CompileState::Free
             }
 
-            (_, Chunk::Code(_lines)) => {
+            (
+                _,
+                Chunk::Code(_lines)
+                | Chunk::Macro(_lines)
+                | Chunk::Eqv(_lines)
+                | Chunk::Include(_lines),
+            ) => {
                 lines.extend(_lines);
-                lines.push(CodeLine::default());
+                blank_lines(lines, config.blank_lines_between_chunks);
                 CompileState::Free
             }
             (_, Chunk::Comment(_lines)) => {
@@ -419,12 +989,22 @@ fn compile_section(lines: &mut Vec<CodeLine>, dir_line: Option<CodeLine>, chunks
                 lines.push(line);
                 CompileState::AfterModifier
             }
+            (_, Chunk::Verbatim(_lines)) => {
+                lines.extend(_lines);
+                CompileState::Free
+            }
 
-            (CompileState::AfterComment, Chunk::Space) => {
-                lines.push(CodeLine::default());
+            // Blank lines at the start of a section or after a label are
+            // dropped
+            (state, Chunk::Space(_)) if i == 0 => state,
+            (CompileState::AfterModifier, Chunk::Space(_)) => CompileState::AfterModifier,
+            (_, Chunk::Space(n)) => {
+                match config.blank_line_runs {
+                    BlankLineRuns::Collapse => blank_lines(lines, 1),
+                    BlankLineRuns::Preserve => blank_lines(lines, n),
+                }
                 CompileState::Free
             }
-            (state, Chunk::Space) => state,
         };
     }
 
@@ -434,30 +1014,1250 @@ fn compile_section(lines: &mut Vec<CodeLine>, dir_line: Option<CodeLine>, chunks
     }
 }
 
+#[cfg(test)]
 pub fn format(contents: String) -> Result<String, Error> {
-    let raw_lines: Vec<&str> = contents.lines().map(|l| l.trim()).collect();
-    let sections = parse_sections(&raw_lines);
-    let mut output_lines: Vec<CodeLine> = Vec::new();
+    format_with_config(contents, &Config::default())
+}
+
+// MARS syscall numbers, with the argument registers each one reads
+static SYSCALLS: &[(u32, &str, &[&str])] = &[
+    (1, "print_int", &["$a0"]),
+    (2, "print_float", &["$f12"]),
+    (3, "print_double", &["$f12"]),
+    (4, "print_string", &["$a0"]),
+    (5, "read_int", &[]),
+    (6, "read_float", &[]),
+    (7, "read_double", &[]),
+    (8, "read_string", &["$a0", "$a1"]),
+    (9, "sbrk", &["$a0"]),
+    (10, "exit", &[]),
+    (11, "print_char", &["$a0"]),
+    (12, "read_char", &[]),
+    (13, "open", &["$a0", "$a1", "$a2"]),
+    (14, "read", &["$a0", "$a1", "$a2"]),
+    (15, "write", &["$a0", "$a1", "$a2"]),
+    (16, "close", &["$a0"]),
+    (17, "exit2", &["$a0"]),
+    (30, "time", &[]),
+    (31, "midi_out", &["$a0", "$a1", "$a2", "$a3"]),
+    (32, "sleep", &["$a0"]),
+    (33, "midi_out_sync", &["$a0", "$a1", "$a2", "$a3"]),
+    (34, "print_hex", &["$a0"]),
+    (35, "print_bin", &["$a0"]),
+    (36, "print_unsigned", &["$a0"]),
+    (40, "set_seed", &["$a0", "$a1"]),
+    (41, "random_int", &["$a0"]),
+    (42, "random_int_range", &["$a0", "$a1"]),
+];
+
+fn is_syscall_annotation(comment: &str) -> bool {
+    SYSCALLS
+        .iter()
+        .any(|(_, name, _)| comment.starts_with(&format!("{}(", name)) && comment.ends_with(')'))
+}
+
+// Comments each syscall with the call it makes, using the constants loaded
+// into its argument registers since the last label
+fn annotate_syscalls(lines: &mut [CodeLine]) {
+    let mut registers: Vec<(String, String)> = Vec::new();
+
+    for line in lines.iter_mut() {
+        let code = match &line.code {
+            Some(code) => code.clone(),
+            None => continue,
+        };
+        // Anything could have run before a label, since it can be jumped to
+        let code = match line::split_label(&code) {
+            Some((_, rest)) => {
+                registers.clear();
+                match rest.trim() {
+                    "" => continue,
+                    rest => rest.to_string(),
+                }
+            }
+            None => code,
+        };
+
+        let (mnemonic, operands) = match code.split_once(' ') {
+            Some((mnemonic, rest)) => (mnemonic, split_operands(rest)),
+            None => (code.as_str(), Vec::new()),
+        };
+
+        match (mnemonic, operands.as_slice()) {
+            ("li" | "la" | "move", [reg, value]) => {
+                registers.retain(|(r, _)| r != reg);
+                registers.push((reg.clone(), value.clone()));
+            }
+            ("syscall", _) => {
+                let value_of = |reg: &str| {
+                    registers
+                        .iter()
+                        .find(|(r, _)| r == reg)
+                        .map(|(_, v)| v.clone())
+                };
+                let call = value_of("$v0")
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .and_then(|v| SYSCALLS.iter().find(|(n, _, _)| *n == v));
+
+                let can_annotate = match &line.comment {
+                    Some(comment) => is_syscall_annotation(comment),
+                    None => true,
+                };
+                if let (Some((_, name, args)), true) = (call, can_annotate) {
+                    let args: Vec<String> = args
+                        .iter()
+                        .map(|a| value_of(a).unwrap_or_else(|| String::from("?")))
+                        .collect();
+                    line.comment = Some(format!("{}({})", name, args.join(", ")));
+                }
+                // Syscalls return their results in `$v0`
+                registers.retain(|(r, _)| r != "$v0");
+            }
+            ("jal" | "jalr", _) => registers.clear(),
+            (_, [reg, ..]) => registers.retain(|(r, _)| r != reg),
+            _ => {}
+        }
+    }
+}
+
+// Data labels with the size of the values laid out after them, if known
+fn data_labels(statements: &[Statement]) -> Vec<(String, Option<i64>)> {
+    let mut labels = Vec::new();
+    let mut pending: Vec<String> = Vec::new();
+
+    for statement in statements {
+        if statement.segment != Segment::Data {
+            pending.clear();
+            continue;
+        }
+        pending.extend(statement.labels.iter().cloned());
+        if let Some(mnemonic) = &statement.mnemonic {
+            let size = analysis::element_size(mnemonic);
+            labels.extend(pending.drain(..).map(|label| (label, size)));
+        }
+    }
+
+    labels
+}
+
+// Evaluates sums and products of integers and `.eqv` constants
+fn eval_offset(expr: &str, eqvs: &[(String, i64)]) -> Option<i64> {
+    let mut total = 0;
+    let mut term_start = 0;
+    let chars: Vec<char> = expr.chars().collect();
+
+    for end in 1..=chars.len() {
+        if end < chars.len() && !matches!(chars[end], '+' | '-') {
+            continue;
+        }
+        let term: String = chars[term_start..end].iter().collect();
+        let (sign, term) = match term.strip_prefix('-') {
+            Some(term) => (-1, term.to_string()),
+            None => (1, term.trim_start_matches('+').to_string()),
+        };
+        let mut product = 1;
+        for factor in term.split('*') {
+            product *= analysis::parse_int(factor)
+                .or_else(|| eqvs.iter().find(|(n, _)| n == factor).map(|e| e.1))?;
+        }
+        total += sign * product;
+        term_start = end;
+    }
+
+    Some(total)
+}
+
+fn is_offset_annotation(comment: &str) -> bool {
+    comment.starts_with("byte offset ")
+        || (comment.contains("] (byte offset ") && comment.ends_with(')'))
+}
+
+// Comments memory accesses like `lw $t0, arr+8` with the byte offset and
+// element index they resolve to
+fn annotate_offsets(
+    lines: &mut [CodeLine],
+    data: &[(String, Option<i64>)],
+    eqvs: &[(String, i64)],
+) {
+    for line in lines.iter_mut() {
+        let code = match &line.code {
+            Some(code) => code,
+            None => continue,
+        };
+        let operand: String = match code.split_once(' ') {
+            Some((_, rest)) => split_operands(rest).pop().unwrap_or_default(),
+            None => continue,
+        }
+        .split_whitespace()
+        .collect();
+        let address = match operand.find('(') {
+            Some(paren) if operand.ends_with(')') => &operand[..paren],
+            _ => &operand,
+        };
+
+        let (label, expr) = match address.find(['+', '-']) {
+            Some(i) if i > 0 => address.split_at(i),
+            _ => continue,
+        };
+        let (size, offset) = match (
+            data.iter().find(|(l, _)| l == label),
+            eval_offset(expr, eqvs),
+        ) {
+            (Some((_, size)), Some(offset)) => (*size, offset),
+            _ => continue,
+        };
+
+        let can_annotate = match &line.comment {
+            Some(comment) => is_offset_annotation(comment),
+            None => true,
+        };
+        if can_annotate {
+            line.comment = Some(match size {
+                Some(size) if offset % size == 0 => {
+                    format!("{}[{}] (byte offset {})", label, offset / size, offset)
+                }
+                _ => format!("byte offset {}", offset),
+            });
+        }
+    }
+}
+
+fn is_data_offset_annotation(comment: &str) -> bool {
+    comment
+        .strip_prefix("offset ")
+        .is_some_and(|n| n.parse::<i64>().is_ok())
+}
+
+// Comments each data declaration with its byte offset from the start of the
+// section, aligning values as the assembler does. Stops at the first one
+// whose size can't be worked out.
+fn annotate_data_offsets(lines: &mut [CodeLine]) {
+    let mut offset = 0;
+
+    for line in lines.iter_mut() {
+        let code = match &line.code {
+            Some(code) => code,
+            None => continue,
+        };
+        let declaration = match data_label(line) {
+            Some(label) => code[label.len() + 1..].trim_start(),
+            None => code,
+        };
+        let (directive, operands) = match declaration.split_once(' ') {
+            Some((directive, rest)) => (directive, split_operands(rest)),
+            None => (declaration, Vec::new()),
+        };
+        if directive == ".align" {
+            match operands.first().and_then(|n| analysis::parse_int(n)) {
+                Some(n) if (0..=16).contains(&n) => {
+                    let align = 1 << n;
+                    offset = (offset + align - 1) / align * align;
+                    continue;
+                }
+                _ => return,
+            }
+        }
+        if !DATA_DIRECTIVES.contains(&directive) {
+            continue;
+        }
+        let Some((align, size)) = analysis::data_layout(directive, &operands) else {
+            return;
+        };
+
+        offset = (offset + align - 1) / align * align;
+        let can_annotate = match &line.comment {
+            Some(comment) => is_data_offset_annotation(comment),
+            None => true,
+        };
+        if can_annotate {
+            line.comment = Some(format!("offset {}", offset));
+        }
+        offset += size;
+    }
+}
+
+// Splits `.eqv NAME VALUE` code, or a `NAME = VALUE` comment, into its name
+// and value
+fn constant_of(line: &CodeLine) -> Option<(&str, &str)> {
+    let (name, value) = match (&line.code, &line.comment) {
+        (Some(code), _) => code
+            .strip_prefix(".eqv ")
+            .and_then(|rest| rest.split_once(' '))?,
+        (None, Some(comment)) => comment.split_once('=')?,
+        (None, None) => return None,
+    };
+    let name = name.trim();
+    let value = value.trim();
+    let is_name = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    match is_name(name) && !value.is_empty() {
+        true => Some((name, value)),
+        false => None,
+    }
+}
+
+// Rewrites a numeric literal with its `0x` or `0b` prefix in lower case and
+// its hex digits in `config.hex_case`, first writing it in hex or decimal as
+// `config.address_literals` asks when it's an address
+fn normalize_number(number: &str, is_address: bool, config: &Config) -> String {
+    let unsigned = number.trim_start_matches(['-', '+']);
+    if unsigned.len() != number.len() {
+        let sign = &number[..(number.len() - unsigned.len())];
+        return sign.to_string() + &normalize_number(unsigned, false, config);
+    }
+    let (radix, digits) = match number.get(..2) {
+        Some("0x" | "0X") => (16, &number[2..]),
+        Some("0b" | "0B") => (2, &number[2..]),
+        _ => (10, number),
+    };
+    let value = u32::from_str_radix(digits, radix).ok();
+    let number = match (is_address, config.address_literals, radix, value) {
+        (true, AddressLiterals::Hex, 2 | 10, Some(value)) => format!("0x{:08x}", value),
+        (true, AddressLiterals::Decimal, 2 | 16, Some(value)) => return value.to_string(),
+        _ => number.to_string(),
+    };
+    if config.hex_case == HexCase::Preserve {
+        return number;
+    }
+    match number.get(..2).map(|p| p.to_lowercase()) {
+        Some(prefix) if prefix == "0x" => {
+            prefix
+                + &match config.hex_case {
+                    HexCase::Upper => number[2..].to_uppercase(),
+                    _ => number[2..].to_lowercase(),
+                }
+        }
+        Some(prefix) if prefix == "0b" => prefix + &number[2..],
+        _ => number,
+    }
+}
+
+// The mnemonic of a formatted code line, after any label, and its operands
+fn split_mnemonic(code: &str) -> Option<(&str, &str)> {
+    let code = match code.split_whitespace().next() {
+        Some(label) if label.ends_with(':') => code[label.len()..].trim_start(),
+        _ => code,
+    };
+    let (mnemonic, operands) = code.split_once(' ').unwrap_or((code, ""));
+    (!mnemonic.is_empty()).then_some((mnemonic, operands.trim()))
+}
+
+// Normalizes the numeric literals in `line`, treating a bare number as its
+// last operand as an address when the instruction takes one
+fn normalize_numbers(line: &mut CodeLine, is_section: bool, config: &Config) {
+    let mnemonic = line
+        .code
+        .as_deref()
+        .and_then(split_mnemonic)
+        .map(|(m, _)| m);
+    let takes_address = is_section || mnemonic.is_some_and(isa::takes_address);
+    line.map_numbers(|number, bare| normalize_number(number, takes_address && bare, config));
+}
+
+// The start and end column of each word in `code`
+fn word_spans(code: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in code.char_indices().chain([(code.len(), ' ')]) {
+        match (c.is_whitespace(), start) {
+            (true, Some(from)) => {
+                spans.push((from, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    spans
+}
+
+// Whether lines with these word spans line up in columns, each starting or
+// ending at the same place on every line
+fn in_columns(spans: &[Vec<(usize, usize)>]) -> bool {
+    spans.iter().all(|s| s.len() == spans[0].len())
+        && (0..spans[0].len()).all(|col| {
+            spans.iter().all(|s| s[col].0 == spans[0][col].0)
+                || spans.iter().all(|s| s[col].1 == spans[0][col].1)
+        })
+}
+
+// Each run of two or more data declarations that were spaced into columns by
+// hand, with some gap wider than the single space formatting would leave
+fn hand_aligned(lines: &[CodeLine]) -> Vec<Range<usize>> {
+    let spans: Vec<Option<Vec<(usize, usize)>>> = lines
+        .iter()
+        .map(|l| l.code.as_deref().map(word_spans))
+        .collect();
+    let mut aligned = Vec::new();
+    let mut start = 0;
+
+    while start < lines.len() {
+        let mut run: Vec<Vec<(usize, usize)>> = Vec::new();
+        while let Some(Some(next)) = spans.get(start + run.len()) {
+            run.push(next.clone());
+            if !in_columns(&run) {
+                run.pop();
+                break;
+            }
+        }
+
+        let widened = run
+            .iter()
+            .any(|s| s.windows(2).any(|pair| pair[1].0 - pair[0].1 > 1));
+        if run.len() >= 2 && widened {
+            aligned.push(start..(start + run.len()));
+        }
+        start += run.len().max(1);
+    }
+
+    aligned
+}
+
+// Lays the formatted words of a hand-aligned table back out in the columns
+// they were in before formatting, each as far from the one before as it was.
+// Tables whose lines no longer have the same number of words are left as
+// formatted.
+fn restore_columns(lines: &mut [CodeLine], original: &[String]) {
+    let spans: Vec<Vec<(usize, usize)>> = original.iter().map(|c| word_spans(c)).collect();
+    let words: Vec<Vec<String>> = lines
+        .iter()
+        .map(|l| {
+            let code = l.code.as_deref().unwrap_or("");
+            word_spans(code)
+                .into_iter()
+                .map(|(from, to)| code[from..to].to_string())
+                .collect()
+        })
+        .collect();
+    let columns = spans[0].len();
+    if words.iter().any(|w| w.len() != columns) {
+        return;
+    }
+
+    let left: Vec<bool> = (0..columns)
+        .map(|col| spans.iter().all(|s| s[col].0 == spans[0][col].0))
+        .collect();
+    let widths: Vec<usize> = (0..columns)
+        .map(|col| words.iter().map(|w| w[col].len()).max().unwrap_or(0))
+        .collect();
+    let gaps: Vec<usize> = (1..columns)
+        .map(|col| {
+            spans
+                .iter()
+                .map(|s| s[col].0 - s[col - 1].1)
+                .min()
+                .unwrap_or(1)
+        })
+        .collect();
+
+    for (line, words) in lines.iter_mut().zip(words) {
+        let mut code = String::new();
+        for (col, word) in words.iter().enumerate() {
+            if col > 0 {
+                code.push_str(&" ".repeat(gaps[col - 1]));
+            }
+            let padding = " ".repeat(widths[col] - word.len());
+            match left[col] {
+                true if col + 1 < columns => code.push_str(&format!("{}{}", word, padding)),
+                true => code.push_str(word),
+                false => code.push_str(&format!("{}{}", padding, word)),
+            }
+        }
+        line.code = Some(code);
+    }
+}
+
+// The label prefix and values of a `.word` declaration
+fn word_row(line: &CodeLine) -> Option<(&str, Vec<String>)> {
+    let code = line.code.as_deref()?;
+    let at = code.find(".word ")?;
+    let prefix = code[..at].trim_end();
+    if !(prefix.is_empty() || data_label(line).is_some_and(|l| prefix == format!("{}:", l))) {
+        return None;
+    }
+    let values = split_operands(&code[at + ".word ".len()..]);
+    Some((
+        prefix,
+        values.iter().map(|v| v.trim().to_string()).collect(),
+    ))
+}
+
+// Lays out each run of two or more `.word` lines with the same number of
+// values as a grid, lining up the directives and right-justifying the values
+fn align_matrices(lines: &mut [CodeLine]) {
+    let mut start = 0;
+    while start < lines.len() {
+        let width = match word_row(&lines[start]) {
+            Some((_, values)) if values.len() >= 2 => values.len(),
+            _ => {
+                start += 1;
+                continue;
+            }
+        };
+        let len = lines[start..]
+            .iter()
+            .take_while(|l| word_row(l).is_some_and(|(_, values)| values.len() == width))
+            .count();
+        if len < 2 {
+            start += 1;
+            continue;
+        }
+
+        let rows: Vec<(String, Vec<String>)> = lines[start..start + len]
+            .iter()
+            .map(|l| {
+                word_row(l)
+                    .map(|(prefix, values)| (prefix.to_string(), values))
+                    .unwrap()
+            })
+            .collect();
+        let prefix_w = rows
+            .iter()
+            .map(|(p, _)| p.chars().count())
+            .max()
+            .unwrap_or(0);
+        let column_w: Vec<usize> = (0..width)
+            .map(|i| {
+                rows.iter()
+                    .map(|(_, v)| v[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        for (line, (prefix, values)) in lines[start..start + len].iter_mut().zip(rows) {
+            let values: Vec<String> = values
+                .iter()
+                .zip(&column_w)
+                .map(|(v, w)| format!("{:>w$}", v, w = w))
+                .collect();
+            let prefix = match prefix_w {
+                0 => String::new(),
+                w => format!("{:<w$} ", prefix, w = w),
+            };
+            line.code = Some(format!("{}.word {}", prefix, values.join(", ")));
+        }
+        start += len;
+    }
+}
+
+static LIST_DIRECTIVES: &[&str] = &[".word", ".half", ".byte", ".float", ".double"];
+
+// Where the values of a `.word`, `.half`, `.byte`, `.float` or `.double`
+// declaration start, and its directive
+fn list_head(code: &str) -> Option<(usize, &'static str)> {
+    let after_label = code
+        .split_once(':')
+        .filter(|(label, _)| {
+            !label.is_empty() && !label.contains(|c: char| c.is_whitespace() || c == '"')
+        })
+        .map_or(0, |(label, _)| label.len() + 1);
+    let at = code.len() - code[after_label..].trim_start().len();
+    let directive = LIST_DIRECTIVES
+        .iter()
+        .find(|directive| code[at..].starts_with(&format!("{} ", directive)))?;
+    Some((at + directive.len(), *directive))
+}
+
+// Joins lines of values continuing a data list that ends in a comma back
+// onto it, so they can be wrapped afresh. Continuations with comments of
+// their own are left where they are.
+fn join_data_lists(lines: Vec<CodeLine>) -> Vec<CodeLine> {
+    let mut joined: Vec<CodeLine> = Vec::new();
+
+    for line in lines {
+        let continues = match (joined.last().and_then(|l| l.code.as_deref()), &line.code) {
+            (Some(prev), Some(code)) => {
+                prev.ends_with(',')
+                    && list_head(prev).is_some()
+                    && line.comment.is_none()
+                    && !code.starts_with('.')
+                    && data_label(&line).is_none()
+            }
+            _ => false,
+        };
+        if continues {
+            let prev = joined.last_mut().unwrap().code.as_mut().unwrap();
+            *prev += " ";
+            *prev += line.code.unwrap().trim();
+        } else {
+            joined.push(line);
+        }
+    }
+
+    joined
+}
+
+// Splits a data declaration wider than `data_list_width`, or failing that
+// `max_width`, into several, each continuing its list of values with the same
+// directive or lined up under the first value
+fn wrap_data_list(mut line: CodeLine, config: &Config) -> Vec<CodeLine> {
+    let limit = match config.data_list_width {
+        0 => config.max_width,
+        width => width,
+    };
+    let width = limit.saturating_sub(line.indent_width(config));
+    let code = match &line.code {
+        Some(code) if limit > 0 && code.len() > width => code.clone(),
+        _ => return vec![line],
+    };
+    let (head_len, directive) = match list_head(&code) {
+        Some(found) => found,
+        None => return vec![line],
+    };
+    let aligned = config.data_list_continuation == DataListContinuation::Aligned;
+    let continuation = match aligned {
+        true => " ".repeat(head_len),
+        false => directive.to_string(),
+    };
+
+    let mut wrapped = vec![code[..head_len].to_string()];
+    let mut on_line = 0;
+    for value in split_operands(&code[head_len..]) {
+        let last = wrapped.last_mut().unwrap();
+        if on_line > 0 && last.len() + 2 + value.len() + usize::from(aligned) > width {
+            if aligned {
+                *last += ",";
+            }
+            wrapped.push(continuation.clone());
+            on_line = 0;
+        }
+        let last = wrapped.last_mut().unwrap();
+        *last += if on_line == 0 { " " } else { ", " };
+        *last += &value;
+        on_line += 1;
+    }
+
+    let mut wrapped = wrapped.into_iter();
+    line.code = wrapped.next();
+    std::iter::once(line)
+        .chain(wrapped.map(|code| {
+            let mut line = CodeLine::default();
+            line.code = Some(code);
+            line
+        }))
+        .collect()
+}
+
+static DATA_DIRECTIVES: &[&str] = &[
+    ".word", ".half", ".byte", ".float", ".double", ".ascii", ".asciiz", ".space",
+];
+
+// The label a data declaration starts with, if any
+fn data_label(line: &CodeLine) -> Option<&str> {
+    let (label, _) = line.code.as_deref()?.split_once(':')?;
+    (!label.is_empty() && !label.contains(|c: char| c.is_whitespace() || c == '"')).then_some(label)
+}
+
+// Sorts the labelled declarations in each run of data lines by label, taking
+// the comments and `.align`s above each, and any unlabelled values continuing
+// it, along with it. Blank lines and other directives stay where they are.
+fn sort_data(lines: Vec<CodeLine>) -> Vec<CodeLine> {
+    let mut sorted = Vec::new();
+    let mut items: Vec<(String, Vec<CodeLine>)> = Vec::new();
+    let mut attached: Vec<CodeLine> = Vec::new();
+
+    let flush = |sorted: &mut Vec<CodeLine>,
+                 items: &mut Vec<(String, Vec<CodeLine>)>,
+                 attached: &mut Vec<CodeLine>| {
+        items.sort_by(|(a, _), (b, _)| a.to_lowercase().cmp(&b.to_lowercase()).then(a.cmp(b)));
+        sorted.extend(items.drain(..).flat_map(|(_, lines)| lines));
+        sorted.append(attached);
+    };
+
+    for line in lines {
+        if line.is_comment_only() || line.starts_with(".align") {
+            attached.push(line);
+        } else if let Some(label) = data_label(&line) {
+            let label = label.to_string();
+            attached.push(line);
+            items.push((label, std::mem::take(&mut attached)));
+        } else if attached.is_empty()
+            && !items.is_empty()
+            && DATA_DIRECTIVES.iter().any(|d| line.starts_with(d))
+        {
+            items.last_mut().unwrap().1.push(line);
+        } else {
+            flush(&mut sorted, &mut items, &mut attached);
+            sorted.push(line);
+        }
+    }
+    flush(&mut sorted, &mut items, &mut attached);
+
+    sorted
+}
+
+// The symbols a `.globl` line declares
+fn globl_symbols(line: &CodeLine) -> Vec<String> {
+    let code = line.code.as_deref().unwrap_or("");
+    code.strip_prefix(".globl")
+        .unwrap_or("")
+        .split([',', ' '])
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Moves a text section's `.globl` lines above its first code, and the
+// comments directly above it, as one line or a line per symbol
+fn group_globls(lines: Vec<CodeLine>, style: GloblStyle) -> Vec<CodeLine> {
+    let mut in_macro = false;
+    let movable: Vec<bool> = lines
+        .iter()
+        .map(|l| {
+            in_macro = (in_macro || l.starts_with(".macro")) && !l.starts_with(".end_macro");
+            !in_macro && l.starts_with(".globl")
+        })
+        .collect();
+    if !movable.contains(&true) {
+        return lines;
+    }
+
+    let mut rest: Vec<CodeLine> = Vec::new();
+    // Each symbol, with the comment from the line it was declared on
+    let mut symbols: Vec<(String, Option<String>)> = Vec::new();
+    let mut removed = false;
+    for (line, movable) in lines.into_iter().zip(movable) {
+        if !movable {
+            // Don't leave a double blank line where a `.globl` was
+            if !(line.is_empty() && removed && rest.last().is_some_and(CodeLine::is_empty)) {
+                removed = false;
+                rest.push(line);
+            }
+            continue;
+        }
+        removed = true;
+        let mut comment = line.comment.clone();
+        for symbol in globl_symbols(&line) {
+            if !symbols.iter().any(|(s, _)| *s == symbol) {
+                symbols.push((symbol, comment.take()));
+            }
+        }
+    }
+
+    let globls: Vec<CodeLine> = match style {
+        GloblStyle::Joined => {
+            let names: Vec<&str> = symbols.iter().map(|(s, _)| s.as_str()).collect();
+            let comments: Vec<&str> = symbols.iter().filter_map(|(_, c)| c.as_deref()).collect();
+            let mut line = CodeLine::default();
+            line.code = Some(format!(".globl {}", names.join(", ")));
+            line.comment = (!comments.is_empty()).then(|| comments.join("; "));
+            vec![line]
+        }
+        _ => symbols
+            .into_iter()
+            .map(|(symbol, comment)| {
+                let mut line = CodeLine::default();
+                line.code = Some(format!(".globl {}", symbol));
+                line.comment = comment;
+                line
+            })
+            .collect(),
+    };
+    let at = match rest.iter().position(|l| l.code.is_some()) {
+        Some(first_code) => rest[..first_code]
+            .iter()
+            .rposition(|l| !l.is_comment_only())
+            .map_or(0, |i| i + 1),
+        None => rest.len(),
+    };
+    rest.splice(at..at, globls);
+    rest
+}
+
+// Moves the `.eqv` lines of a section up above its first code, and any
+// comments directly above that, unless a name is defined more than once and
+// their order matters. Those in macro bodies stay put.
+fn group_eqvs(lines: Vec<CodeLine>) -> Vec<CodeLine> {
+    let mut in_macro = false;
+    let movable: Vec<bool> = lines
+        .iter()
+        .map(|l| {
+            in_macro = (in_macro || l.starts_with(".macro")) && !l.starts_with(".end_macro");
+            !in_macro && l.starts_with(".eqv")
+        })
+        .collect();
+    let names: Vec<&str> = lines
+        .iter()
+        .zip(&movable)
+        .filter(|(_, &m)| m)
+        .filter_map(|(l, _)| constant_of(l).map(|(name, _)| name))
+        .collect();
+    if names
+        .iter()
+        .enumerate()
+        .any(|(i, n)| names[..i].contains(n))
+    {
+        return lines;
+    }
+    if !movable.contains(&true) {
+        return lines;
+    }
+    let first_code = lines.iter().position(|l| l.code.is_some()).unwrap();
+    let at = match movable[first_code] {
+        true => first_code,
+        false => lines[..first_code]
+            .iter()
+            .rposition(|l| !l.is_comment_only())
+            .map_or(0, |i| i + 1),
+    };
+
+    let mut grouped = Vec::new();
+    let mut eqvs = Vec::new();
+    let mut removed = false;
+    for (line, movable) in lines.into_iter().zip(movable) {
+        if movable {
+            eqvs.push(line);
+            removed = true;
+        } else if line.is_empty() && removed && grouped.last().is_some_and(CodeLine::is_empty) {
+            // Don't leave a double blank line where an `.eqv` was
+        } else {
+            removed = false;
+            grouped.push(line);
+        }
+    }
+    grouped.splice(at..at, eqvs);
+    grouped
+}
+
+// Lines up the values of consecutive `.eqv` lines, and of consecutive
+// `# NAME = VALUE` comments, so their comments then align as a third column
+fn align_constants(lines: &mut [CodeLine]) {
+    let mut start = 0;
+    while start < lines.len() {
+        if constant_of(&lines[start]).is_none() {
+            start += 1;
+            continue;
+        }
+        let is_eqv = lines[start].code.is_some();
+        let end = start
+            + lines[start..]
+                .iter()
+                .take_while(|l| constant_of(l).is_some() && l.code.is_some() == is_eqv)
+                .count();
+
+        let run = &mut lines[start..end];
+        let width = run
+            .iter()
+            .filter_map(|l| constant_of(l).map(|(name, _)| name.len()))
+            .max()
+            .unwrap_or(0);
+        for line in run.iter_mut() {
+            let (name, value) = constant_of(line).unwrap();
+            match is_eqv {
+                true => line.code = Some(format!(".eqv {:width$} {}", name, value)),
+                false => line.comment = Some(format!("{:width$} = {}", name, value)),
+            }
+        }
+        start = end;
+    }
+}
+
+// Whether a code line matches one side of a `keep_together` pattern: a
+// mnemonic, or `|`-separated mnemonics or `*`, then optionally the first
+// operands it may have, like `li $v0` or `* $a0|$a1`
+fn matches_line_pattern(pattern: &str, code: &str) -> bool {
+    let mut parts = pattern.split_whitespace();
+    let Some((mnemonic, operands)) = split_mnemonic(code) else {
+        return false;
+    };
+    let first = operands.split(',').next().unwrap_or("").trim();
+    let allows = |alternatives: Option<&str>, value: &str| match alternatives {
+        None | Some("*") => true,
+        Some(alternatives) => alternatives.split('|').any(|a| a == value),
+    };
+    allows(parts.next(), mnemonic) && allows(parts.next(), first)
+}
+
+// Removes the blank lines between lines matching the `keep_together`
+// patterns: a run of lines matching the left side, then one matching the right
+fn keep_together(lines: Vec<CodeLine>, config: &Config) -> Vec<CodeLine> {
+    let patterns: Vec<(&str, &str)> = config
+        .keep_together
+        .iter()
+        .filter_map(|p| p.split_once('+'))
+        .map(|(a, b)| (a.trim(), b.trim()))
+        .collect();
+    // The code line before each line, when only blank lines come between
+    let mut previous: Vec<Option<usize>> = vec![None; lines.len()];
+    let mut last_code: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        previous[i] = last_code;
+        match line.code.is_some() {
+            true => last_code = Some(i),
+            false if !line.is_empty() => last_code = None,
+            false => {}
+        }
+    }
+
+    let code = |i: usize| lines[i].code.as_deref().unwrap_or("");
+    let mut joined = vec![false; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        if line.code.is_none() {
+            continue;
+        }
+        for (before, after) in &patterns {
+            if !matches_line_pattern(after, code(i)) {
+                continue;
+            }
+            let mut at = i;
+            while let Some(prev) = previous[at].filter(|p| matches_line_pattern(before, code(*p))) {
+                joined[(prev + 1)..at].iter_mut().for_each(|j| *j = true);
+                at = prev;
+            }
+        }
+    }
+
+    lines
+        .into_iter()
+        .zip(joined)
+        .filter(|(line, joined)| !(*joined && line.is_empty()))
+        .map(|(line, _)| line)
+        .collect()
+}
+
+fn is_blank_comment(line: &CodeLine) -> bool {
+    line.is_comment_only() && line.comment.as_deref() == Some("")
+}
+
+// Applies `blank_comments` to lines holding only `#`
+fn normalize_blank_comments(lines: Vec<CodeLine>, config: &Config) -> Vec<CodeLine> {
+    let mut normalized: Vec<CodeLine> = Vec::new();
+    for line in lines {
+        match config.blank_comments {
+            BlankComments::Blank if is_blank_comment(&line) => normalized.push(CodeLine::default()),
+            BlankComments::Collapse
+                if is_blank_comment(&line) && normalized.last().is_some_and(is_blank_comment) => {}
+            _ => normalized.push(line),
+        }
+    }
+    normalized
+}
+
+fn chunk_sections(contents: &str, config: &Config) -> Vec<(Section, Vec<Chunk>)> {
+    let unformatted = unformatted_regions(contents, config);
+    let renamed = labels::rename_labels(contents, config.label_case, &unformatted);
+    // Renaming labels never changes the number of lines, so unformatted
+    // regions can be taken from the original
+    let raw_lines: Vec<&str> = renamed
+        .lines()
+        .zip(contents.lines())
+        .enumerate()
+        .map(|(i, (renamed, original))| {
+            match unformatted.iter().any(|(s, e)| (*s..=*e).contains(&i)) {
+                true => original,
+                false => renamed,
+            }
+        })
+        .collect();
+    let contents = &renamed;
+    let (data, eqvs) = match config.annotate_offsets {
+        true => {
+            let statements = analysis::parse(contents);
+            (data_labels(&statements), analysis::eqvs(&statements))
+        }
+        false => (Vec::new(), Vec::new()),
+    };
+
+    parse_sections(&raw_lines, &unformatted)
+        .into_iter()
+        .map(|mut section| {
+            if let Some(line) = section.dir_line.as_mut() {
+                normalize_numbers(line, true, config);
+            }
+            let mut lines = std::mem::take(&mut section.lines);
+            if matches!(section.dir, Directive::Verbatim) {
+                return (section, vec![Chunk::Verbatim(lines)]);
+            }
+            let tables: Vec<(Range<usize>, Vec<String>)> =
+                match (&section.dir, config.preserve_tables) {
+                    (Directive::Data, true) => hand_aligned(&lines)
+                        .into_iter()
+                        .map(|run| {
+                            let original = lines[run.clone()]
+                                .iter()
+                                .map(|l| l.code.clone().unwrap_or_default())
+                                .collect();
+                            (run, original)
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+            lines.iter_mut().for_each(|l| {
+                l.format();
+                if config.operator_spacing != OperatorSpacing::Preserve {
+                    l.space_operators(config.operator_spacing == OperatorSpacing::Spaced);
+                }
+                normalize_numbers(l, false, config);
+            });
+            for (run, original) in tables {
+                restore_columns(&mut lines[run], &original);
+            }
+            lines.iter_mut().for_each(format_macro_header);
+            if matches!(section.dir, Directive::Data) {
+                if config.data_list_continuation == DataListContinuation::Aligned {
+                    lines = join_data_lists(lines);
+                }
+                lines = lines
+                    .into_iter()
+                    .flat_map(|l| wrap_data_list(l, config))
+                    .collect();
+                if config.sort_data {
+                    lines = sort_data(lines);
+                }
+                if config.align_matrices {
+                    align_matrices(&mut lines);
+                }
+            }
+            if config.globl_style != GloblStyle::Preserve && matches!(section.dir, Directive::Text)
+            {
+                lines = group_globls(lines, config.globl_style);
+            }
+            lines = group_eqvs(lines);
+            if config.align_constants {
+                align_constants(&mut lines);
+            }
+            if !config.keep_together.is_empty() && matches!(section.dir, Directive::Text) {
+                lines = keep_together(lines, config);
+            }
+            if config.annotate_syscalls && matches!(section.dir, Directive::Text) {
+                annotate_syscalls(&mut lines);
+            }
+            if config.annotate_data_offsets && matches!(section.dir, Directive::Data) {
+                annotate_data_offsets(&mut lines);
+            }
+            if config.annotate_offsets && matches!(section.dir, Directive::Text) {
+                annotate_offsets(&mut lines, &data, &eqvs);
+            }
+            let lines = normalize_blank_comments(lines, config);
+            let mut chunks = parse_chunks(lines, &section.dir, config);
 
-    for section in sections {
-        // === Formatting ===
-        let mut lines = section.lines;
-        lines.iter_mut().for_each(|l| l.format());
-        let mut chunks = parse_chunks(lines, &section.dir);
-        chunks.iter_mut().for_each(|c| align_comments(c));
+            match &section.dir {
+                Directive::Text => indent_chunks(&mut chunks),
+                Directive::Data | Directive::Verbatim => {}
+            }
+            chunks = split_moved_comments(chunks, config);
+            chunks.iter_mut().for_each(|c| align_comments(c, config));
 
-        match &section.dir {
-            Directive::Text => indent_chunks(&mut chunks),
-            Directive::Data => {}
+            (section, chunks)
+        })
+        .collect()
+}
+
+// A file of nothing but comments and blank lines skips chunking entirely,
+// unless it has regions to keep as written
+fn format_comments_only(contents: &str, config: &Config) -> Option<String> {
+    if !unformatted_regions(contents, config).is_empty()
+        || !contents
+            .lines()
+            .map(str::trim)
+            .all(|l| l.is_empty() || l.starts_with('#'))
+    {
+        return None;
+    }
+    let lines = contents
+        .lines()
+        .map(|l| CodeLine::parse(l.trim()))
+        .collect();
+
+    let mut formatted = String::new();
+    let mut blanks = 0;
+    for line in normalize_blank_comments(lines, config) {
+        if line.is_empty() {
+            blanks += 1;
+            continue;
         }
+        if !formatted.is_empty() {
+            let blanks = match config.blank_line_runs {
+                BlankLineRuns::Collapse => blanks.min(1),
+                BlankLineRuns::Preserve => blanks,
+            };
+            formatted += &"\n".repeat(blanks);
+        }
+        blanks = 0;
+        formatted += &line.render_without_indent(config);
+        formatted.push('\n');
+    }
+
+    Some(formatted)
+}
+
+const VERSION_HEADER: &str = "# formatted with mac-mips v";
+
+// The comment recording the formatter version, and a hash of the options it
+// ran with so different styles can be told apart
+fn version_header(config: &Config) -> String {
+    let options: Vec<String> = config::OPTIONS
+        .iter()
+        .map(|option| format!("{}={:?}", option, config.get(option)))
+        .collect();
+    format!(
+        "{}{}, style={}",
+        VERSION_HEADER,
+        env!("CARGO_PKG_VERSION"),
+        &stable_hash(&options.join("\n"))[..8]
+    )
+}
+
+pub fn format_with_config(contents: String, config: &Config) -> Result<String, Error> {
+    if config.version_header == VersionHeader::Off {
+        return format_contents(contents, config);
+    }
+    let contents = contents
+        .lines()
+        .filter(|line| !line.trim().starts_with(VERSION_HEADER))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let formatted = format_contents(contents, config)?;
+    let header = version_header(config);
+    Ok(match config.version_header {
+        VersionHeader::Top => format!("{}\n{}", header, formatted),
+        _ => format!("{}{}\n", formatted, header),
+    })
+}
+
+fn format_contents(contents: String, config: &Config) -> Result<String, Error> {
+    if let Some(formatted) = format_comments_only(&contents, config) {
+        return Ok(formatted);
+    }
+    let mut output_lines: Vec<CodeLine> = Vec::new();
 
-        // === Compilation ===
-        compile_section(&mut output_lines, section.dir_line, chunks);
+    for (section, chunks) in chunk_sections(&contents, config) {
+        compile_section(&mut output_lines, section.dir_line, chunks, config);
+    }
+    // The file ends with a single newline however many blank lines follow
+    // its last chunk
+    while output_lines.len() > 1 && output_lines[output_lines.len() - 2].is_empty() {
+        output_lines.pop();
     }
 
-    Ok(output_lines
+    let mut formatted = output_lines
         .into_iter()
-        .map(|l| l.to_string())
+        .map(|l| l.render(config))
         .collect::<Vec<String>>()
-        .join("\n"))
+        .join("\n");
+    // A verbatim section at the end of the file has no trailing blank line
+    if !formatted.is_empty() && !formatted.ends_with('\n') {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+/// The line and directive of each section passed through unformatted.
+pub fn verbatim_sections(contents: &str) -> Vec<(usize, String)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| matches!(section_of(line.trim()), Some(Directive::Verbatim)))
+        .map(|(i, line)| (i + 1, line.trim().to_string()))
+        .collect()
+}
+
+// FNV-1a, which unlike `DefaultHasher` is stable across Rust versions
+fn stable_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn chunk_kind(chunk: &Chunk) -> Option<&'static str> {
+    match chunk {
+        Chunk::Space(_) => None,
+        Chunk::GlobDec(_) => Some("globl"),
+        Chunk::Modifier(line) if line.ends_with(":") => Some("label"),
+        Chunk::Modifier(_) => Some("modifier"),
+        Chunk::Code(_) => Some("code"),
+        Chunk::Comment(_) => Some("comment"),
+        Chunk::Verbatim(_) => Some("verbatim"),
+        Chunk::Macro(_) => Some("macro"),
+        Chunk::Eqv(_) => Some("eqv"),
+        Chunk::Include(_) => Some("include"),
+        Chunk::Extern(_) => Some("extern"),
+    }
+}
+
+fn dump_chunk(chunk: &Chunk, label: &mut Option<String>, config: &Config) -> Option<Json> {
+    let kind = chunk_kind(chunk)?;
+    let lines = match chunk {
+        Chunk::Space(_) => return None,
+        Chunk::Modifier(line) => vec![line],
+        Chunk::GlobDec(lines)
+        | Chunk::Code(lines)
+        | Chunk::Comment(lines)
+        | Chunk::Verbatim(lines)
+        | Chunk::Macro(lines)
+        | Chunk::Eqv(lines)
+        | Chunk::Include(lines)
+        | Chunk::Extern(lines) => lines.iter().collect(),
+    };
+    if kind == "label" {
+        *label = lines[0]
+            .code
+            .as_ref()
+            .map(|c| c.trim_end_matches(':').to_string());
+    }
+
+    let lines: Vec<String> = lines
+        .into_iter()
+        .map(|l| l.render_without_indent(config))
+        .collect();
+    let label_name = label.clone().unwrap_or_default();
+    let id = stable_hash(&format!("{}\n{}", label_name, lines.join("\n")));
+
+    Some(Json::object(vec![
+        ("id", id.into()),
+        ("kind", kind.into()),
+        ("label", label_name.into()),
+        (
+            "lines",
+            Json::Array(lines.into_iter().map(Json::from).collect()),
+        ),
+    ]))
+}
+
+/// Describes the parsed sections and chunks of a file. Chunk ids hash the
+/// enclosing label with the formatted content, so they survive reformatting
+/// and edits elsewhere in the file.
+pub fn dump_ast(contents: &str, config: &Config) -> Json {
+    let sections = chunk_sections(contents, config)
+        .into_iter()
+        .map(|(section, chunks)| {
+            let mut label = None;
+            let chunks = chunks
+                .iter()
+                .filter_map(|c| dump_chunk(c, &mut label, config))
+                .collect();
+            let directive = match section.dir {
+                Directive::Text => "text",
+                Directive::Data => "data",
+                Directive::Verbatim => "verbatim",
+            };
+
+            Json::object(vec![
+                ("directive", directive.into()),
+                ("chunks", Json::Array(chunks)),
+            ])
+        })
+        .collect();
+
+    Json::object(vec![("sections", Json::Array(sections))])
+}
+
+/// Number of chunks of each kind in a file, in order of first appearance.
+pub fn chunk_counts(contents: &str, config: &Config) -> Vec<(&'static str, usize)> {
+    let mut counts: Vec<(&'static str, usize)> = Vec::new();
+
+    for (_, chunks) in chunk_sections(contents, config) {
+        for kind in chunks.iter().filter_map(chunk_kind) {
+            match counts.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((kind, 1)),
+            }
+        }
+    }
+
+    counts
 }
@@ -0,0 +1,105 @@
+use crate::analysis::{self, Statement};
+use crate::config::parse_toml;
+use crate::isa::InstructionSet;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Rubric {
+    pub required_labels: Vec<String>,
+    pub forbidden_instructions: Vec<String>,
+    pub max_pseudo_instructions: Option<usize>,
+    pub min_comment_coverage: Option<usize>,
+}
+
+impl Rubric {
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut rubric = Rubric::default();
+
+        for (key, value) in parse_toml(contents)? {
+            match key.as_str() {
+                "required_labels" => rubric.required_labels = value.as_str_list(&key)?,
+                "forbidden_instructions" => {
+                    rubric.forbidden_instructions = value.as_str_list(&key)?
+                }
+                "max_pseudo_instructions" => {
+                    rubric.max_pseudo_instructions = Some(value.as_usize(&key)?)
+                }
+                "min_comment_coverage" => rubric.min_comment_coverage = Some(value.as_usize(&key)?),
+                _ => return Err(format!("unknown rubric key `{}`", key)),
+            }
+        }
+
+        Ok(rubric)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+fn line_list(lines: &[usize]) -> String {
+    lines
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn grade(
+    rubric: &Rubric,
+    statements: &[Statement],
+    instruction_set: &InstructionSet,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for label in &rubric.required_labels {
+        let defined = statements.iter().any(|s| s.labels.contains(label));
+        checks.push(Check {
+            name: format!("required label `{}`", label),
+            passed: defined,
+            detail: String::from(if defined { "defined" } else { "missing" }),
+        });
+    }
+
+    for forbidden in &rubric.forbidden_instructions {
+        let uses: Vec<usize> = statements
+            .iter()
+            .filter(|s| matches!(&s.mnemonic, Some(m) if m.eq_ignore_ascii_case(forbidden)))
+            .map(|s| s.line)
+            .collect();
+        checks.push(Check {
+            name: format!("forbidden instruction `{}`", forbidden),
+            passed: uses.is_empty(),
+            detail: match uses.is_empty() {
+                true => String::from("not used"),
+                false => format!("used on line {}", line_list(&uses)),
+            },
+        });
+    }
+
+    if let Some(max) = rubric.max_pseudo_instructions {
+        let count = statements
+            .iter()
+            .filter_map(|s| instruction_set.get(s.mnemonic.as_deref()?))
+            .filter(|i| i.pseudo)
+            .count();
+        checks.push(Check {
+            name: String::from("pseudo-instructions"),
+            passed: count <= max,
+            detail: format!("{} used, at most {} allowed", count, max),
+        });
+    }
+
+    if let Some(min) = rubric.min_comment_coverage {
+        let coverage = analysis::comment_coverage(statements);
+        checks.push(Check {
+            name: String::from("comment coverage"),
+            passed: coverage >= min,
+            detail: format!("{}% of instructions, at least {}% required", coverage, min),
+        });
+    }
+
+    checks
+}
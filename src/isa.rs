@@ -0,0 +1,302 @@
+use std::fmt;
+
+use crate::analysis;
+use crate::config;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Isa {
+    Mips1,
+    Mips32,
+    Mips32r2,
+}
+
+impl Isa {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "mips1" | "mipsi" => Some(Isa::Mips1),
+            "mips32" | "mips32r1" => Some(Isa::Mips32),
+            "mips32r2" => Some(Isa::Mips32r2),
+            _ => None,
+        }
+    }
+
+    // The name used for this ISA in config files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Isa::Mips1 => "mips1",
+            Isa::Mips32 => "mips32",
+            Isa::Mips32r2 => "mips32r2",
+        }
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Isa::Mips1 => write!(f, "MIPS I"),
+            Isa::Mips32 => write!(f, "MIPS32"),
+            Isa::Mips32r2 => write!(f, "MIPS32r2"),
+        }
+    }
+}
+
+static MIPS1: &[&str] = &[
+    "add", "addu", "sub", "subu", "and", "or", "xor", "nor", "slt", "sltu", "addi", "addiu",
+    "andi", "ori", "xori", "slti", "sltiu", "lui", "sll", "srl", "sra", "sllv", "srlv", "srav",
+    "mult", "multu", "div", "divu", "mfhi", "mflo", "mthi", "mtlo", "j", "jal", "jr", "jalr",
+    "beq", "bne", "blez", "bgtz", "bltz", "bgez", "bltzal", "bgezal", "lb", "lbu", "lh", "lhu",
+    "lw", "lwl", "lwr", "sb", "sh", "sw", "swl", "swr", "syscall", "break", "nop", "mfc0", "mtc0",
+    "lwc1", "swc1", "mfc1", "mtc1", "cfc1", "ctc1", "bc1t", "bc1f", "add.s", "add.d", "sub.s",
+    "sub.d", "mul.s", "mul.d", "div.s", "div.d", "abs.s", "abs.d", "neg.s", "neg.d", "mov.s",
+    "mov.d", "cvt.s.d", "cvt.s.w", "cvt.d.s", "cvt.d.w", "cvt.w.s", "cvt.w.d", "c.eq.s", "c.eq.d",
+    "c.lt.s", "c.lt.d", "c.le.s", "c.le.d", "l.s", "s.s",
+];
+
+// Includes the MIPS II additions, which MIPS32 is a superset of
+static MIPS32: &[&str] = &[
+    "ll",
+    "sc",
+    "sync",
+    "teq",
+    "tne",
+    "tge",
+    "tgeu",
+    "tlt",
+    "tltu",
+    "teqi",
+    "tnei",
+    "tgei",
+    "tgeiu",
+    "tlti",
+    "tltiu",
+    "beql",
+    "bnel",
+    "blezl",
+    "bgtzl",
+    "bltzl",
+    "bgezl",
+    "bc1tl",
+    "bc1fl",
+    "ldc1",
+    "sdc1",
+    "l.d",
+    "s.d",
+    "sqrt.s",
+    "sqrt.d",
+    "round.w.s",
+    "round.w.d",
+    "trunc.w.s",
+    "trunc.w.d",
+    "ceil.w.s",
+    "ceil.w.d",
+    "floor.w.s",
+    "floor.w.d",
+    "movn",
+    "movz",
+    "movf",
+    "movt",
+    "movn.s",
+    "movn.d",
+    "movz.s",
+    "movz.d",
+    "movf.s",
+    "movf.d",
+    "movt.s",
+    "movt.d",
+    "mul",
+    "madd",
+    "maddu",
+    "msub",
+    "msubu",
+    "clo",
+    "clz",
+    "eret",
+    "cache",
+    "pref",
+];
+
+static MIPS32R2: &[&str] = &[
+    "rotr", "rotrv", "seb", "seh", "wsbh", "ins", "ext", "di", "ei", "rdhwr", "synci", "mfhc1",
+    "mthc1", "ehb", "jalr.hb", "jr.hb",
+];
+
+// Pseudo-instructions MARS expands into one or more real instructions
+static PSEUDO: &[&str] = &[
+    "abs", "b", "beqz", "bge", "bgeu", "bgt", "bgtu", "ble", "bleu", "blt", "bltu", "bnez", "la",
+    "ld", "li", "move", "mulo", "mulou", "mulu", "neg", "negu", "not", "rem", "remu", "rol", "ror",
+    "sd", "seq", "sge", "sgeu", "sgt", "sgtu", "sle", "sleu", "sne", "subi", "subiu", "ulh",
+    "ulhu", "ulw", "ush", "usw",
+];
+
+// Pseudo-instructions MARS always expands using `$at`
+static USES_AT: &[&str] = &[
+    "la", "blt", "bltu", "bgt", "bgtu", "ble", "bleu", "bge", "bgeu", "subi", "subiu", "mulo",
+    "mulou", "rol", "ror", "seq", "sge", "sgeu",
+];
+
+// Instructions taking an immediate that MARS builds in `$at` when it doesn't
+// fit in 16 bits
+static WIDE_IMMEDIATE: &[&str] = &[
+    "li", "addi", "addiu", "andi", "ori", "xori", "slti", "sltiu",
+];
+
+static MEMORY: &[&str] = &[
+    "lb", "lbu", "lh", "lhu", "lw", "sb", "sh", "sw", "lwc1", "swc1", "l.s", "s.s", "l.d", "s.d",
+];
+
+/// Whether the assembler expands an instruction with these operands into
+/// code that overwrites `$at`.
+pub fn clobbers_at(mnemonic: &str, operands: &[String]) -> bool {
+    let last = operands.last().map(String::as_str).unwrap_or("");
+    if USES_AT.contains(&mnemonic) {
+        return true;
+    }
+    if WIDE_IMMEDIATE.contains(&mnemonic) {
+        return analysis::parse_int(last).is_some_and(|n| !(-0x8000..=0xffff).contains(&n));
+    }
+    if !MEMORY.contains(&mnemonic) || operands.len() != 2 {
+        return false;
+    }
+    // Addresses given by a label, or an offset too wide for the instruction.
+    // A `%lo(sym)` offset always fits, `%hi(sym)` having been loaded already
+    let offset = match last.rsplit_once('(') {
+        Some((offset, _)) if last.ends_with(')') => offset.trim(),
+        _ => last,
+    };
+    if analysis::relocation(offset).is_some() {
+        return false;
+    }
+    match analysis::parse_int(offset) {
+        Some(n) => !(-0x8000..=0x7fff).contains(&n),
+        None => !offset.is_empty(),
+    }
+}
+
+static REGISTERS: &[&str] = &[
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+/// The number of a general register written like `$t0`, `$8` or `$s8`.
+pub fn register(operand: &str) -> Option<u32> {
+    let name = operand.strip_prefix('$')?;
+    match name.parse::<u32>() {
+        Ok(n) if n < 32 => Some(n),
+        Ok(_) => None,
+        Err(_) if name == "s8" => Some(30),
+        Err(_) => REGISTERS.iter().position(|r| *r == name).map(|n| n as u32),
+    }
+}
+
+/// The conventional name of general register `number`, like `t0`.
+pub fn register_name(number: u32) -> Option<&'static str> {
+    REGISTERS.get(number as usize).copied()
+}
+
+/// Whether an instruction's first operand is a register it writes, rather than
+/// one it stores or compares.
+pub fn writes_first_operand(mnemonic: &str) -> bool {
+    !matches!(
+        mnemonic,
+        "sb" | "sh"
+            | "sw"
+            | "swl"
+            | "swr"
+            | "swc1"
+            | "s.s"
+            | "s.d"
+            | "sc"
+            | "jr"
+            | "jalr"
+            | "mthi"
+            | "mtlo"
+            | "mtc0"
+            | "mtc1"
+            | "mult"
+            | "multu"
+            | "div"
+            | "divu"
+    ) && !mnemonic.starts_with('b')
+        && !mnemonic.starts_with('t')
+}
+
+/// Whether an instruction loads from or stores to memory.
+pub fn is_memory(mnemonic: &str) -> bool {
+    MEMORY.contains(&mnemonic)
+}
+
+/// Whether a bare number as the instruction's last operand is an address.
+pub fn takes_address(mnemonic: &str) -> bool {
+    matches!(mnemonic, "la" | "j" | "jal") || MEMORY.contains(&mnemonic)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub mnemonic: String,
+    pub isa: Isa,
+    pub pseudo: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionSet {
+    instructions: Vec<Instruction>,
+}
+
+impl InstructionSet {
+    pub fn standard() -> Self {
+        let levels = [
+            (MIPS1, Isa::Mips1, false),
+            (MIPS32, Isa::Mips32, false),
+            (MIPS32R2, Isa::Mips32r2, false),
+            (PSEUDO, Isa::Mips1, true),
+        ];
+
+        let instructions = levels
+            .iter()
+            .flat_map(|(mnemonics, isa, pseudo)| {
+                mnemonics.iter().map(|m| Instruction {
+                    mnemonic: m.to_string(),
+                    isa: *isa,
+                    pseudo: *pseudo,
+                })
+            })
+            .collect();
+
+        InstructionSet { instructions }
+    }
+
+    /// Adds the instructions from TOML like `pseudo = ["print_int"]` or
+    /// `mips32 = ["madd"]`, replacing any of the same name.
+    pub fn extend_from_toml(&mut self, contents: &str) -> Result<(), String> {
+        for (key, value) in config::parse_toml(contents)? {
+            let (isa, pseudo) = match Isa::parse(&key) {
+                Some(isa) => (isa, false),
+                None if key == "pseudo" => (Isa::Mips1, true),
+                None => {
+                    return Err(format!(
+                        "unknown key `{}`, expected an ISA name or `pseudo`",
+                        key
+                    ))
+                }
+            };
+
+            for mnemonic in value.as_str_list(&key)? {
+                self.instructions
+                    .retain(|i| !i.mnemonic.eq_ignore_ascii_case(&mnemonic));
+                self.instructions.push(Instruction {
+                    mnemonic,
+                    isa,
+                    pseudo,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, mnemonic: &str) -> Option<&Instruction> {
+        self.instructions
+            .iter()
+            .find(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic))
+    }
+}
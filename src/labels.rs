@@ -0,0 +1,133 @@
+use crate::analysis::{self, comment_start};
+use crate::config::LabelCase;
+
+/// Converts a label to `case`, keeping any `.` separators.
+pub fn to_case(label: &str, case: LabelCase) -> String {
+    match case {
+        LabelCase::Preserve => label.to_string(),
+        LabelCase::Lowercase => label.to_lowercase(),
+        LabelCase::SnakeCase => {
+            let chars: Vec<char> = label.chars().collect();
+            let mut out = String::new();
+            for (i, c) in chars.iter().enumerate() {
+                let prev = i.checked_sub(1).map(|p| chars[p]);
+                let next = chars.get(i + 1);
+                // A new word starts at `printInt` and at the `S` of `HTTPServer`
+                let word_start = c.is_uppercase()
+                    && match prev {
+                        Some(p) if p.is_lowercase() || p.is_ascii_digit() => true,
+                        Some(p) if p.is_uppercase() => next.is_some_and(|n| n.is_lowercase()),
+                        _ => false,
+                    };
+                if word_start {
+                    out.push('_');
+                }
+                out.extend(c.to_lowercase());
+            }
+            out
+        }
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+// Rewrites the symbols in one line's code, leaving its mnemonic, strings and
+// comment alone
+fn rename_line(line: &str, renames: &[(String, String)]) -> String {
+    let code_end = comment_start(line).unwrap_or(line.len());
+    let mut out = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut seen_mnemonic = false;
+    let mut chars = line[..code_end].char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '$' | '%') => {
+                // Registers and macro parameters aren't symbols
+                out.push(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_symbol_char(*c)) {
+                    out.push(c);
+                }
+                continue;
+            }
+            (None, c) if is_symbol_char(c) && !c.is_ascii_digit() => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, c)) = chars.next_if(|(_, c)| is_symbol_char(*c)) {
+                    end = j + c.len_utf8();
+                }
+                let symbol = &line[i..end];
+                let defines = line[end..code_end].trim_start().starts_with(':');
+
+                match renames.iter().find(|(old, _)| old == symbol) {
+                    Some((_, new)) if defines || seen_mnemonic => out.push_str(new),
+                    _ => out.push_str(symbol),
+                }
+                seen_mnemonic |= !defines;
+                continue;
+            }
+            (None, _) => {}
+        }
+        out.push(c);
+    }
+
+    out + &line[code_end..]
+}
+
+/// Renames every label defined in `contents`, and all references to it, into
+/// `case`. Labels exported with `.globl` are left alone since other files may
+/// use them, as are any whose new name would clash with another label, and
+/// any defined in `kept`, the ranges of lines left as written.
+pub fn rename_labels(contents: &str, case: LabelCase, kept: &[(usize, usize)]) -> String {
+    if case == LabelCase::Preserve {
+        return contents.to_string();
+    }
+
+    let statements = analysis::parse(contents);
+    let exported: Vec<&String> = statements
+        .iter()
+        .filter(|s| matches!(s.mnemonic.as_deref(), Some(".globl" | ".global")))
+        .flat_map(|s| &s.operands)
+        .collect();
+    let labels: Vec<&String> = statements.iter().flat_map(|s| &s.labels).collect();
+    // Lines in `kept` count from 0, and statements from 1
+    let fixed: Vec<&String> = statements
+        .iter()
+        .filter(|s| {
+            kept.iter()
+                .any(|(from, to)| (*from..=*to).contains(&(s.line - 1)))
+        })
+        .flat_map(|s| &s.labels)
+        .collect();
+
+    let renames: Vec<(String, String)> = labels
+        .iter()
+        .filter(|label| !exported.contains(label) && !fixed.contains(label))
+        .map(|label| (label.to_string(), to_case(label, case)))
+        .filter(|(old, new)| old != new)
+        .collect();
+    let renames: Vec<(String, String)> = renames
+        .iter()
+        .filter(|(old, new)| {
+            let clashes =
+                labels.contains(&new) || renames.iter().any(|(o, n)| n == new && o != old);
+            !clashes
+        })
+        .cloned()
+        .collect();
+
+    if renames.is_empty() {
+        return contents.to_string();
+    }
+    contents
+        .split_inclusive('\n')
+        .map(|line| rename_line(line, &renames))
+        .collect()
+}
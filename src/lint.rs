@@ -0,0 +1,785 @@
+use std::cell::{Cell, OnceCell};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::analysis::{self, Indent, IndentHealth, Segment, Statement};
+use crate::config::Config;
+use crate::isa;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub severity: Severity,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+fn forbidden_instructions(statements: &[Statement], config: &Config, out: &mut Vec<Diagnostic>) {
+    for statement in statements {
+        let mnemonic = match &statement.mnemonic {
+            Some(mnemonic) => mnemonic,
+            None => continue,
+        };
+
+        let kind = if statement.is_directive() {
+            "directive"
+        } else {
+            "instruction"
+        };
+        if config
+            .forbidden_instructions
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(mnemonic))
+        {
+            out.push(Diagnostic {
+                line: statement.line,
+                severity: Severity::Error,
+                rule: "forbidden-instruction",
+                message: format!(
+                    "{} `{}` is not allowed (see `forbidden_instructions` in config)",
+                    kind, mnemonic
+                ),
+            });
+        }
+    }
+}
+
+fn isa_subset(statements: &[Statement], config: &Config, out: &mut Vec<Diagnostic>) {
+    for statement in statements.iter().filter(|s| s.is_instruction()) {
+        let mnemonic = statement.mnemonic.as_ref().unwrap();
+        match config.instructions.get(mnemonic) {
+            Some(instruction) if instruction.isa > config.isa => out.push(Diagnostic {
+                line: statement.line,
+                severity: Severity::Error,
+                rule: "isa",
+                message: format!(
+                    "`{}` requires {}, but the selected ISA is {}",
+                    mnemonic, instruction.isa, config.isa
+                ),
+            }),
+            _ => {}
+        }
+    }
+}
+
+fn condition_flag(operands: &[String], with_flag: usize) -> Option<u32> {
+    match operands.len() == with_flag {
+        true => operands[0].parse().ok(),
+        false => Some(0),
+    }
+}
+
+fn is_fp_compare(mnemonic: &str) -> bool {
+    mnemonic.starts_with("c.") && (mnemonic.ends_with(".s") || mnemonic.ends_with(".d"))
+}
+
+// Branches on FPU condition flags must follow a comparison setting that flag
+fn fp_condition_flags(statements: &[Statement], out: &mut Vec<Diagnostic>) {
+    let mut flags_set: Vec<u32> = Vec::new();
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Text) {
+        if !statement.labels.is_empty() {
+            flags_set.clear();
+        }
+
+        let mnemonic = match &statement.mnemonic {
+            Some(mnemonic) => mnemonic.to_lowercase(),
+            None => continue,
+        };
+
+        if is_fp_compare(&mnemonic) {
+            if let Some(flag) = condition_flag(&statement.operands, 3) {
+                flags_set.push(flag);
+            }
+            continue;
+        }
+
+        if !matches!(mnemonic.as_str(), "bc1t" | "bc1f" | "bc1tl" | "bc1fl") {
+            continue;
+        }
+
+        let message = match condition_flag(&statement.operands, 2) {
+            _ if flags_set.is_empty() => format!(
+                "`{}` is used before any floating-point comparison in this block",
+                mnemonic
+            ),
+            Some(flag) if !flags_set.contains(&flag) => format!(
+                "`{}` tests condition flag {}, but comparisons in this block only set flag {}",
+                mnemonic,
+                flag,
+                flags_set
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            _ => continue,
+        };
+
+        out.push(Diagnostic {
+            line: statement.line,
+            severity: Severity::Warning,
+            rule: "fp-condition-flag",
+            message,
+        });
+    }
+}
+
+// Every `%param` used in a macro body should be declared, and vice versa
+fn macro_params(statements: &[Statement], out: &mut Vec<Diagnostic>) {
+    for m in analysis::macros(statements) {
+        let mut used: Vec<String> = Vec::new();
+
+        for statement in &statements[m.body.clone()] {
+            let text = [statement.labels.join(" "), statement.operands.join(", ")].join(" ");
+            for param in analysis::macro_params(&text) {
+                if !m.params.contains(&param) && !used.contains(&param) {
+                    out.push(Diagnostic {
+                        line: statement.line,
+                        severity: Severity::Warning,
+                        rule: "macro-params",
+                        message: format!("`{}` is not a parameter of macro `{}`", param, m.name),
+                    });
+                }
+                used.push(param);
+            }
+        }
+
+        for param in m.params.iter().filter(|p| !used.contains(p)) {
+            out.push(Diagnostic {
+                line: m.line,
+                severity: Severity::Warning,
+                rule: "macro-params",
+                message: format!("parameter `{}` of macro `{}` is never used", param, m.name),
+            });
+        }
+    }
+}
+
+// Invisible or look-alike characters that sneak in when code is copied from
+// PDFs or slides, with the ASCII they should be
+static HIDDEN_CHARACTERS: &[(char, &str, &str)] = &[
+    ('\u{00a0}', "no-break space", " "),
+    ('\u{2002}', "en space", " "),
+    ('\u{2003}', "em space", " "),
+    ('\u{2009}', "thin space", " "),
+    ('\u{202f}', "narrow no-break space", " "),
+    ('\u{3000}', "ideographic space", " "),
+    ('\u{200b}', "zero width space", ""),
+    ('\u{200c}', "zero width non-joiner", ""),
+    ('\u{200d}', "zero width joiner", ""),
+    ('\u{2060}', "word joiner", ""),
+    ('\u{feff}', "byte order mark", ""),
+    ('\u{00ad}', "soft hyphen", ""),
+    ('\u{2212}', "minus sign", "-"),
+    ('\u{2013}', "en dash", "-"),
+    ('\u{ff0c}', "fullwidth comma", ","),
+    ('\u{ff04}', "fullwidth dollar sign", "$"),
+    ('\u{ff08}', "fullwidth left parenthesis", "("),
+    ('\u{ff09}', "fullwidth right parenthesis", ")"),
+    ('\u{0430}', "Cyrillic a", "a"),
+    ('\u{0435}', "Cyrillic e", "e"),
+    ('\u{043e}', "Cyrillic o", "o"),
+    ('\u{0440}', "Cyrillic er", "p"),
+    ('\u{0441}', "Cyrillic es", "c"),
+    ('\u{0445}', "Cyrillic ha", "x"),
+    ('\u{0443}', "Cyrillic u", "y"),
+    ('\u{0456}', "Cyrillic i", "i"),
+    ('\u{0458}', "Cyrillic je", "j"),
+    ('\u{0455}', "Cyrillic dze", "s"),
+    ('\u{0410}', "Cyrillic A", "A"),
+    ('\u{0412}', "Cyrillic Ve", "B"),
+    ('\u{0415}', "Cyrillic Ie", "E"),
+    ('\u{041c}', "Cyrillic Em", "M"),
+    ('\u{041e}', "Cyrillic O", "O"),
+    ('\u{0420}', "Cyrillic Er", "P"),
+    ('\u{0421}', "Cyrillic Es", "C"),
+    ('\u{0422}', "Cyrillic Te", "T"),
+    ('\u{03bf}', "Greek omicron", "o"),
+    ('\u{039f}', "Greek Omicron", "O"),
+];
+
+fn hidden_character(c: char) -> Option<&'static (char, &'static str, &'static str)> {
+    HIDDEN_CHARACTERS.iter().find(|(h, _, _)| *h == c)
+}
+
+// Calls `visit` with the column of each character before any comment, and
+// whether it's inside a string
+fn for_each_char<F: FnMut(usize, char, bool)>(line: &str, mut visit: F) {
+    let code = match analysis::comment_start(line) {
+        Some(hash) => &line[..hash],
+        None => line,
+    };
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (column, c) in code.chars().enumerate() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => visit(column + 1, c, true),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, c) => visit(column + 1, c, false),
+        }
+    }
+}
+
+// Calls `visit` with the column and character of everything outside strings and comments
+fn for_each_code_char<F: FnMut(usize, char)>(line: &str, mut visit: F) {
+    for_each_char(line, |column, c, in_string| {
+        if !in_string {
+            visit(column, c)
+        }
+    });
+}
+
+fn hidden_characters(contents: &str, out: &mut Vec<Diagnostic>) {
+    for (i, line) in contents.lines().enumerate() {
+        for_each_code_char(line, |column, c| {
+            if let Some((_, name, replacement)) = hidden_character(c) {
+                let fix = match *replacement {
+                    "" => String::from("remove it"),
+                    replacement => format!("use `{}`", replacement),
+                };
+                out.push(Diagnostic {
+                    line: i + 1,
+                    severity: Severity::Error,
+                    rule: "hidden-character",
+                    message: format!(
+                        "{} (U+{:04X}) in column {}, {}",
+                        name, c as u32, column, fix
+                    ),
+                });
+            }
+        });
+    }
+}
+
+/// Replaces hidden and look-alike characters in code with their ASCII
+/// equivalents, leaving strings and comments untouched.
+pub fn fix_hidden_characters(contents: &str) -> String {
+    let mut fixed = String::new();
+
+    for line in contents.split_inclusive('\n') {
+        let mut columns: Vec<usize> = Vec::new();
+        for_each_code_char(line, |column, c| {
+            if hidden_character(c).is_some() {
+                columns.push(column);
+            }
+        });
+
+        for (column, c) in line.chars().enumerate() {
+            match hidden_character(c) {
+                Some((_, _, replacement)) if columns.contains(&(column + 1)) => {
+                    fixed.push_str(replacement)
+                }
+                _ => fixed.push(c),
+            }
+        }
+    }
+
+    fixed
+}
+
+// Typographic punctuation word processors substitute for ASCII, with the
+// ASCII it stands in for
+static SMART_QUOTES: &[(char, &str, char)] = &[
+    ('\u{2018}', "left single quotation mark", '\''),
+    ('\u{2019}', "right single quotation mark", '\''),
+    ('\u{201a}', "single low quotation mark", '\''),
+    ('\u{201c}', "left double quotation mark", '"'),
+    ('\u{201d}', "right double quotation mark", '"'),
+    ('\u{201e}', "double low quotation mark", '"'),
+    ('\u{2013}', "en dash", '-'),
+    ('\u{2014}', "em dash", '-'),
+];
+
+fn smart_quote(c: char) -> Option<&'static (char, &'static str, char)> {
+    SMART_QUOTES.iter().find(|(q, _, _)| *q == c)
+}
+
+fn smart_quotes(contents: &str, out: &mut Vec<Diagnostic>) {
+    for (i, line) in contents.lines().enumerate() {
+        for_each_char(line, |column, c, in_string| {
+            let (_, name, ascii) = match smart_quote(c) {
+                // Already reported as a hidden character
+                Some(_) if !in_string && hidden_character(c).is_some() => return,
+                Some(quote) => quote,
+                None => return,
+            };
+            let (severity, place) = match in_string {
+                true => (Severity::Warning, "in a string"),
+                false => (Severity::Error, "in code"),
+            };
+            out.push(Diagnostic {
+                line: i + 1,
+                severity,
+                rule: "smart-quote",
+                message: format!(
+                    "{} (U+{:04X}) {} in column {}, use `{}` (fix with --fix smart-quotes)",
+                    name, c as u32, place, column, ascii
+                ),
+            });
+        });
+    }
+}
+
+/// Replaces curly quotes and dashes outside comments with their ASCII
+/// equivalents, so they can delimit strings again.
+pub fn fix_smart_quotes(contents: &str) -> String {
+    let mut fixed = String::new();
+
+    for line in contents.split_inclusive('\n') {
+        let mut columns: Vec<usize> = Vec::new();
+        for_each_char(line, |column, c, _| {
+            if smart_quote(c).is_some() {
+                columns.push(column);
+            }
+        });
+
+        for (column, c) in line.chars().enumerate() {
+            match smart_quote(c) {
+                Some((_, _, ascii)) if columns.contains(&(column + 1)) => fixed.push(*ascii),
+                _ => fixed.push(c),
+            }
+        }
+    }
+
+    fixed
+}
+
+pub type Fix = fn(&str) -> String;
+
+/// The fixes `mac-mips lint --fix` can apply, by name.
+pub static FIXES: &[(&str, Fix)] = &[
+    ("hidden-characters", fix_hidden_characters),
+    ("smart-quotes", fix_smart_quotes),
+];
+
+// Lines mixing tabs and spaces, or indented against the file's majority style
+fn mixed_indentation(contents: &str, out: &mut Vec<Diagnostic>) {
+    let health = IndentHealth::of(contents);
+    let minority = match (health.tabs, health.spaces) {
+        (0, _) | (_, 0) => None,
+        (tabs, spaces) if tabs >= spaces => Some((Indent::Spaces, "spaces", "tabs")),
+        _ => Some((Indent::Tabs, "tabs", "spaces")),
+    };
+
+    for (i, line) in contents.lines().enumerate() {
+        let message = match (analysis::indent_of(line), minority) {
+            (Indent::Mixed, _) => String::from("indentation mixes tabs and spaces"),
+            (indent, Some((odd, used, majority))) if indent == odd => format!(
+                "indented with {} but most of the file uses {}",
+                used, majority
+            ),
+            _ => continue,
+        };
+        out.push(Diagnostic {
+            line: i + 1,
+            severity: Severity::Warning,
+            rule: "mixed-indentation",
+            message,
+        });
+    }
+}
+
+// Number of elements in an initializer list, where `value:count` repeats a value
+fn initializer_len(operands: &[String]) -> i64 {
+    operands
+        .iter()
+        .filter(|op| !op.is_empty())
+        .map(|op| match op.split_once(':') {
+            Some((_, count)) => analysis::parse_int(count.trim()).unwrap_or(1),
+            None => 1,
+        })
+        .sum()
+}
+
+struct Array {
+    label: String,
+    line: usize,
+    len: i64,
+    element_size: i64,
+}
+
+fn arrays(statements: &[Statement]) -> Vec<Array> {
+    let mut arrays: Vec<Array> = Vec::new();
+    let mut open = false;
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Data) {
+        let size = statement
+            .mnemonic
+            .as_deref()
+            .and_then(analysis::element_size);
+        match (statement.labels.last(), size) {
+            (Some(label), Some(element_size)) => {
+                arrays.push(Array {
+                    label: label.clone(),
+                    line: statement.line,
+                    len: initializer_len(&statement.operands),
+                    element_size,
+                });
+                open = true;
+            }
+            // Initializers continued on the following lines
+            (None, Some(size)) if open && arrays.last().unwrap().element_size == size => {
+                arrays.last_mut().unwrap().len += initializer_len(&statement.operands);
+            }
+            (None, None) if statement.is_comment_only() => {}
+            _ => open = false,
+        }
+    }
+
+    arrays
+}
+
+// Whether a constant such as `ARR_SIZE` or `NUM_ARR` names the length of `label`
+fn is_size_of(name: &str, label: &str) -> bool {
+    let name = name.to_lowercase();
+    let label = label.to_lowercase();
+    ["_size", "_len", "_length", "_count"]
+        .iter()
+        .any(|suffix| name.strip_suffix(suffix) == Some(&label))
+        || ["size_", "len_", "num_", "n_"]
+            .iter()
+            .any(|prefix| name.strip_prefix(prefix) == Some(&label))
+}
+
+// The length a loop comparing against `bound` should have for `array`
+fn expected_bound(mnemonic: &str, len: i64) -> Option<i64> {
+    match mnemonic {
+        "blt" | "bge" | "beq" | "bne" | "slti" | "sltiu" => Some(len),
+        "ble" | "bgt" => Some(len - 1),
+        _ => None,
+    }
+}
+
+// `.word` arrays whose initializer length disagrees with a matching `.eqv`
+// size, or with the bound of a loop in a procedure that loads the array
+fn data_sizes(statements: &[Statement], out: &mut Vec<Diagnostic>) {
+    let arrays = arrays(statements);
+    let eqvs = analysis::eqvs(statements);
+
+    for array in &arrays {
+        for (name, value) in eqvs.iter().filter(|(n, _)| is_size_of(n, &array.label)) {
+            if *value != array.len && *value != array.len * array.element_size {
+                out.push(Diagnostic {
+                    line: array.line,
+                    severity: Severity::Warning,
+                    rule: "data-size",
+                    message: format!(
+                        "`{}` has {} elements, but `{}` is {}",
+                        array.label, array.len, name, value
+                    ),
+                });
+            }
+        }
+    }
+
+    let boundaries = analysis::procedure_labels(statements);
+    let mut used: Vec<&str> = Vec::new();
+    let mut registers: Vec<(&str, i64)> = Vec::new();
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Text) {
+        if statement.labels.iter().any(|l| boundaries.contains(l)) {
+            used.clear();
+            registers.clear();
+        }
+        let mnemonic = match statement.mnemonic.as_deref() {
+            Some(mnemonic) => mnemonic,
+            None => continue,
+        };
+        used.extend(analysis::references(statement));
+
+        let bound_operand = match statement.operands.last() {
+            Some(operand) => operand.as_str(),
+            None => continue,
+        };
+        if mnemonic == "li" && statement.operands.len() == 2 {
+            if let Some(value) = analysis::parse_int(bound_operand) {
+                registers.retain(|(r, _)| *r != statement.operands[0]);
+                registers.push((&statement.operands[0], value));
+            }
+            continue;
+        }
+
+        let operands = &statement.operands;
+        let bound = match mnemonic {
+            "slti" | "sltiu" if operands.len() == 3 => analysis::parse_int(bound_operand),
+            _ if operands.len() == 3 && mnemonic.starts_with('b') => {
+                analysis::parse_int(&operands[1])
+                    .or_else(|| eqvs.iter().find(|(n, _)| n == &operands[1]).map(|e| e.1))
+                    .or_else(|| {
+                        registers
+                            .iter()
+                            .find(|(r, _)| r == &operands[1])
+                            .map(|r| r.1)
+                    })
+            }
+            _ => None,
+        };
+        let bound = match bound {
+            Some(bound) => bound,
+            None => continue,
+        };
+
+        for array in arrays.iter().filter(|a| used.contains(&a.label.as_str())) {
+            match expected_bound(mnemonic, array.len) {
+                Some(expected) if (bound - expected).abs() == 1 => out.push(Diagnostic {
+                    line: statement.line,
+                    severity: Severity::Warning,
+                    rule: "data-size",
+                    message: format!(
+                        "`{}` compares against {}, but `{}` (line {}) has {} elements",
+                        mnemonic, bound, array.label, array.line, array.len
+                    ),
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Procedure<'a> {
+    name: &'a str,
+    line: usize,
+    instructions: usize,
+    // Internal labels, with the line and number of instructions before them
+    labels: Vec<(&'a str, usize, usize)>,
+}
+
+// Internal labels that split a procedure into pieces of at most `max`
+// instructions, where the labels allow it
+fn split_points<'a>(procedure: &Procedure<'a>, max: usize) -> Vec<(&'a str, usize)> {
+    let mut splits = Vec::new();
+    let mut start = 0;
+    let mut fits = None;
+
+    for &(label, line, offset) in &procedure.labels {
+        if offset - start > max {
+            if let Some((label, line, offset)) = fits.take() {
+                splits.push((label, line));
+                start = offset;
+            }
+        }
+        if offset > start {
+            fits = Some((label, line, offset));
+        }
+    }
+    if procedure.instructions - start > max {
+        splits.extend(fits.map(|(label, line, _)| (label, line)));
+    }
+
+    splits
+}
+
+// Procedures longer than `max_procedure_instructions` are hard to follow
+fn procedure_size(statements: &[Statement], config: &Config, out: &mut Vec<Diagnostic>) {
+    let max = config.max_procedure_instructions;
+    if max == 0 {
+        return;
+    }
+    let names = analysis::procedure_labels(statements);
+    let mut procedures: Vec<Procedure> = Vec::new();
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Text) {
+        for label in &statement.labels {
+            match (names.contains(label), procedures.last_mut()) {
+                (true, _) => procedures.push(Procedure {
+                    name: label,
+                    line: statement.line,
+                    instructions: 0,
+                    labels: Vec::new(),
+                }),
+                (false, Some(procedure)) => {
+                    let offset = procedure.instructions;
+                    procedure.labels.push((label, statement.line, offset));
+                }
+                (false, None) => {}
+            }
+        }
+        if let (true, Some(procedure)) = (statement.is_instruction(), procedures.last_mut()) {
+            procedure.instructions += 1;
+        }
+    }
+
+    for procedure in procedures.iter().filter(|p| p.instructions > max) {
+        let splits = split_points(procedure, max)
+            .into_iter()
+            .map(|(label, line)| format!("`{}` (line {})", label, line))
+            .collect::<Vec<String>>();
+        let advice = match splits.is_empty() {
+            true => String::from("it has no internal labels to split it at"),
+            false => format!("consider splitting it at {}", splits.join(", ")),
+        };
+        out.push(Diagnostic {
+            line: procedure.line,
+            severity: Severity::Warning,
+            rule: "procedure-size",
+            message: format!(
+                "`{}` has {} instructions, more than `max_procedure_instructions` ({}); {}",
+                procedure.name, procedure.instructions, max, advice
+            ),
+        });
+    }
+}
+
+// What rules check, with the parsed statements worked out only once a rule
+// asks for them
+struct Context<'a> {
+    contents: &'a str,
+    statements: OnceCell<Vec<Statement>>,
+    parse_time: Cell<Duration>,
+}
+
+impl Context<'_> {
+    fn statements(&self) -> &[Statement] {
+        self.statements.get_or_init(|| {
+            let start = Instant::now();
+            let statements = analysis::parse(self.contents);
+            self.parse_time.set(start.elapsed());
+            statements
+        })
+    }
+}
+
+struct Rule {
+    name: &'static str,
+    check: fn(&Context, &Config, &mut Vec<Diagnostic>),
+}
+
+fn is_at(operand: &str) -> bool {
+    matches!(operand, "$at" | "$1")
+}
+
+// Pseudo-instructions that the assembler expands using `$at` silently
+// overwrite any value the code put there itself
+fn at_clobbers(statements: &[Statement], out: &mut Vec<Diagnostic>) {
+    let boundaries = analysis::procedure_labels(statements);
+    let mut set_on: Option<usize> = None;
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Text) {
+        if statement.labels.iter().any(|l| boundaries.contains(l)) {
+            set_on = None;
+        }
+        let mnemonic = match &statement.mnemonic {
+            Some(mnemonic) if statement.is_instruction() => mnemonic.to_lowercase(),
+            _ => continue,
+        };
+
+        if let (Some(line), true) = (set_on, isa::clobbers_at(&mnemonic, &statement.operands)) {
+            out.push(Diagnostic {
+                line: statement.line,
+                severity: Severity::Warning,
+                rule: "at-clobber",
+                message: format!(
+                    "`{}` is expanded using `$at`, overwriting the value set on line {}",
+                    mnemonic, line
+                ),
+            });
+            set_on = None;
+        }
+        if isa::writes_first_operand(&mnemonic)
+            && statement.operands.first().is_some_and(|op| is_at(op))
+        {
+            set_on = Some(statement.line);
+        }
+    }
+}
+
+static RULES: &[Rule] = &[
+    Rule {
+        name: "forbidden-instruction",
+        check: |cx, config, out| forbidden_instructions(cx.statements(), config, out),
+    },
+    Rule {
+        name: "isa",
+        check: |cx, config, out| isa_subset(cx.statements(), config, out),
+    },
+    Rule {
+        name: "fp-condition-flag",
+        check: |cx, _, out| fp_condition_flags(cx.statements(), out),
+    },
+    Rule {
+        name: "macro-params",
+        check: |cx, _, out| macro_params(cx.statements(), out),
+    },
+    Rule {
+        name: "hidden-character",
+        check: |cx, _, out| hidden_characters(cx.contents, out),
+    },
+    Rule {
+        name: "smart-quote",
+        check: |cx, _, out| smart_quotes(cx.contents, out),
+    },
+    Rule {
+        name: "mixed-indentation",
+        check: |cx, _, out| mixed_indentation(cx.contents, out),
+    },
+    Rule {
+        name: "data-size",
+        check: |cx, _, out| data_sizes(cx.statements(), out),
+    },
+    Rule {
+        name: "at-clobber",
+        check: |cx, _, out| at_clobbers(cx.statements(), out),
+    },
+    Rule {
+        name: "procedure-size",
+        check: |cx, config, out| procedure_size(cx.statements(), config, out),
+    },
+];
+
+/// Runs the rules not in `lint_ignore`, also returning how long each took.
+/// Parsing the file, which only happens if a rule needs it, is timed as
+/// `parse`.
+pub fn lint_timed(
+    contents: &str,
+    config: &Config,
+) -> (Vec<Diagnostic>, Vec<(&'static str, Duration)>) {
+    let cx = Context {
+        contents,
+        statements: OnceCell::new(),
+        parse_time: Cell::new(Duration::ZERO),
+    };
+    let mut diagnostics = Vec::new();
+    let mut timings = Vec::new();
+
+    for rule in RULES {
+        if config.lint_ignore.iter().any(|r| r == rule.name) {
+            continue;
+        }
+        let parsed_before = cx.parse_time.get();
+        let start = Instant::now();
+        (rule.check)(&cx, config, &mut diagnostics);
+        let parsing = cx.parse_time.get() - parsed_before;
+        timings.push((rule.name, start.elapsed().saturating_sub(parsing)));
+    }
+    if cx.statements.get().is_some() {
+        timings.insert(0, ("parse", cx.parse_time.get()));
+    }
+
+    diagnostics.sort_by_key(|d| d.line);
+    (diagnostics, timings)
+}
+
+#[cfg(test)]
+pub fn lint(contents: &str, config: &Config) -> Vec<Diagnostic> {
+    lint_timed(contents, config).0
+}
@@ -0,0 +1,335 @@
+use crate::analysis::{self, Statement};
+use crate::isa;
+
+// Where each section starts when its directive gives no address, as in MARS
+static SEGMENT_STARTS: &[(&str, u32)] = &[
+    (".text", 0x0040_0000),
+    (".data", 0x1001_0000),
+    (".ktext", 0x8000_0180),
+    (".kdata", 0x9000_0000),
+];
+
+// R-type instructions taking `rd, rs, rt`, with their function codes
+static THREE_REGISTER: &[(&str, u32)] = &[
+    ("add", 0x20),
+    ("addu", 0x21),
+    ("sub", 0x22),
+    ("subu", 0x23),
+    ("and", 0x24),
+    ("or", 0x25),
+    ("xor", 0x26),
+    ("nor", 0x27),
+    ("slt", 0x2a),
+    ("sltu", 0x2b),
+];
+
+// I-type instructions taking `rt, rs, immediate`, with their opcodes
+static IMMEDIATE: &[(&str, u32)] = &[
+    ("addi", 0x08),
+    ("addiu", 0x09),
+    ("slti", 0x0a),
+    ("sltiu", 0x0b),
+    ("andi", 0x0c),
+    ("ori", 0x0d),
+    ("xori", 0x0e),
+];
+
+// Loads and stores taking `rt, offset(base)`, with their opcodes
+static MEMORY: &[(&str, u32)] = &[
+    ("lb", 0x20),
+    ("lh", 0x21),
+    ("lw", 0x23),
+    ("lbu", 0x24),
+    ("lhu", 0x25),
+    ("sb", 0x28),
+    ("sh", 0x29),
+    ("sw", 0x2b),
+];
+
+fn r_type(rs: u32, rt: u32, rd: u32, shamt: u32, funct: u32) -> u32 {
+    (rs << 21) | (rt << 16) | (rd << 11) | (shamt << 6) | funct
+}
+
+fn i_type(op: u32, rs: u32, rt: u32, imm: u32) -> u32 {
+    (op << 26) | (rs << 21) | (rt << 16) | (imm & 0xffff)
+}
+
+// What labels and `.eqv` constants stand for while encoding
+struct Symbols<'a> {
+    labels: &'a [(String, u32)],
+    eqvs: &'a [(String, i64)],
+    // Laying out, before the labels are known, when every label reads as 0
+    sizing: bool,
+}
+
+impl Symbols<'_> {
+    fn immediate(&self, operand: &str) -> Option<i64> {
+        if let Some((operator, symbol)) = analysis::relocation(operand) {
+            let address = self
+                .label(symbol)
+                .map(i64::from)
+                .or_else(|| self.immediate(symbol))?;
+            return Some(match operator {
+                // Rounded so adding the sign-extended `%lo` half gives the address
+                "hi" => ((address + 0x8000) >> 16) & 0xffff,
+                _ => address & 0xffff,
+            });
+        }
+        analysis::parse_int(operand)
+            .or_else(|| self.eqvs.iter().find(|(n, _)| n == operand).map(|e| e.1))
+    }
+
+    fn label(&self, operand: &str) -> Option<u32> {
+        match self.sizing {
+            true => Some(0),
+            false => self.labels.iter().find(|(l, _)| l == operand).map(|l| l.1),
+        }
+    }
+
+    fn branch(&self, op: u32, rs: u32, rt: u32, target: &str, address: u32) -> Option<u32> {
+        let target = self.label(target)?;
+        let offset = (target.wrapping_sub(address + 4) as i32) >> 2;
+        Some(i_type(op, rs, rt, offset as u32))
+    }
+}
+
+// The words an instruction assembles to at `address`, for the real
+// instructions and one- or two-word pseudo-instructions the listing knows
+fn encode(mnemonic: &str, ops: &[String], address: u32, symbols: &Symbols) -> Option<Vec<u32>> {
+    let reg = |i: usize| ops.get(i).and_then(|op| isa::register(op));
+    let imm = |i: usize| ops.get(i).and_then(|op| symbols.immediate(op));
+    let at = 1;
+
+    let three_register = THREE_REGISTER.iter().find(|(m, _)| *m == mnemonic);
+    let immediate = IMMEDIATE.iter().find(|(m, _)| *m == mnemonic);
+    let memory = MEMORY.iter().find(|(m, _)| *m == mnemonic);
+
+    let word = match (mnemonic, ops.len()) {
+        (_, 3) if three_register.is_some() => {
+            r_type(reg(1)?, reg(2)?, reg(0)?, 0, three_register?.1)
+        }
+        (_, 3) if immediate.is_some() => i_type(immediate?.1, reg(1)?, reg(0)?, imm(2)? as u32),
+        (_, 2) if memory.is_some() => {
+            let address = ops[1].as_str();
+            let (offset, base) = address.strip_suffix(')')?.rsplit_once('(')?;
+            let offset = match offset.trim() {
+                "" => 0,
+                offset => symbols.immediate(offset)?,
+            };
+            i_type(
+                memory?.1,
+                isa::register(base.trim())?,
+                reg(0)?,
+                offset as u32,
+            )
+        }
+        ("sll" | "srl" | "sra", 3) => {
+            let funct = match mnemonic {
+                "sll" => 0x00,
+                "srl" => 0x02,
+                _ => 0x03,
+            };
+            r_type(0, reg(1)?, reg(0)?, imm(2)? as u32 & 0x1f, funct)
+        }
+        ("sllv" | "srlv" | "srav", 3) => {
+            let funct = match mnemonic {
+                "sllv" => 0x04,
+                "srlv" => 0x06,
+                _ => 0x07,
+            };
+            r_type(reg(2)?, reg(1)?, reg(0)?, 0, funct)
+        }
+        ("mult" | "multu" | "div" | "divu", 2) => {
+            let funct = match mnemonic {
+                "mult" => 0x18,
+                "multu" => 0x19,
+                "div" => 0x1a,
+                _ => 0x1b,
+            };
+            r_type(reg(0)?, reg(1)?, 0, 0, funct)
+        }
+        ("mul", 3) => (0x1c << 26) | r_type(reg(1)?, reg(2)?, reg(0)?, 0, 0x02),
+        ("mfhi", 1) => r_type(0, 0, reg(0)?, 0, 0x10),
+        ("mflo", 1) => r_type(0, 0, reg(0)?, 0, 0x12),
+        ("mthi", 1) => r_type(reg(0)?, 0, 0, 0, 0x11),
+        ("mtlo", 1) => r_type(reg(0)?, 0, 0, 0, 0x13),
+        ("jr", 1) => r_type(reg(0)?, 0, 0, 0, 0x08),
+        ("jalr", 1) => r_type(reg(0)?, 0, 31, 0, 0x09),
+        ("jalr", 2) => r_type(reg(1)?, 0, reg(0)?, 0, 0x09),
+        ("syscall", 0) => 0x0c,
+        ("break", 0) => 0x0d,
+        ("nop", 0) => 0,
+        ("lui", 2) => i_type(0x0f, 0, reg(0)?, imm(1)? as u32),
+        ("beq" | "bne", 3) => {
+            let op = if mnemonic == "beq" { 0x04 } else { 0x05 };
+            symbols.branch(op, reg(0)?, reg(1)?, &ops[2], address)?
+        }
+        ("blez", 2) => symbols.branch(0x06, reg(0)?, 0, &ops[1], address)?,
+        ("bgtz", 2) => symbols.branch(0x07, reg(0)?, 0, &ops[1], address)?,
+        ("bltz", 2) => symbols.branch(0x01, reg(0)?, 0, &ops[1], address)?,
+        ("bgez", 2) => symbols.branch(0x01, reg(0)?, 1, &ops[1], address)?,
+        ("j" | "jal", 1) => {
+            let op = if mnemonic == "j" { 0x02 } else { 0x03 };
+            (op << 26) | ((symbols.label(&ops[0])? >> 2) & 0x03ff_ffff)
+        }
+
+        // Pseudo-instructions, expanded as MARS does
+        ("move", 2) => r_type(0, reg(1)?, reg(0)?, 0, 0x21),
+        ("not", 2) => r_type(reg(1)?, 0, reg(0)?, 0, 0x27),
+        ("neg", 2) => r_type(0, reg(1)?, reg(0)?, 0, 0x22),
+        ("negu", 2) => r_type(0, reg(1)?, reg(0)?, 0, 0x23),
+        ("b", 1) => symbols.branch(0x04, 0, 0, &ops[0], address)?,
+        ("beqz", 2) => symbols.branch(0x04, reg(0)?, 0, &ops[1], address)?,
+        ("bnez", 2) => symbols.branch(0x05, reg(0)?, 0, &ops[1], address)?,
+        ("li", 2) => {
+            let value = imm(1)?;
+            match value {
+                -0x8000..=0x7fff => i_type(0x09, 0, reg(0)?, value as u32),
+                0x8000..=0xffff => i_type(0x0d, 0, reg(0)?, value as u32),
+                _ => {
+                    let value = value as u32;
+                    return Some(vec![
+                        i_type(0x0f, 0, at, value >> 16),
+                        i_type(0x0d, at, reg(0)?, value),
+                    ]);
+                }
+            }
+        }
+        ("la", 2) => {
+            let target = symbols.label(&ops[1])?;
+            return Some(vec![
+                i_type(0x0f, 0, at, target >> 16),
+                i_type(0x0d, at, reg(0)?, target),
+            ]);
+        }
+        ("blt" | "bltu" | "bgt" | "bgtu" | "ble" | "bleu" | "bge" | "bgeu", 3) => {
+            let funct = if mnemonic.ends_with('u') { 0x2b } else { 0x2a };
+            let (rs, rt) = match &mnemonic[..3] {
+                "blt" | "bge" => (reg(0)?, reg(1)?),
+                _ => (reg(1)?, reg(0)?),
+            };
+            let op = match &mnemonic[..3] {
+                "blt" | "bgt" => 0x05,
+                _ => 0x04,
+            };
+            return Some(vec![
+                r_type(rs, rt, at, 0, funct),
+                symbols.branch(op, at, 0, &ops[2], address + 4)?,
+            ]);
+        }
+        _ => return None,
+    };
+    Some(vec![word])
+}
+
+struct Layout {
+    // The address of each statement that takes up space, by line
+    addresses: Vec<(usize, u32)>,
+    labels: Vec<(String, u32)>,
+}
+
+// Works out where each statement and label lands, sizing instructions the
+// listing can't encode as one word
+fn lay_out(statements: &[Statement], eqvs: &[(String, i64)]) -> Layout {
+    let symbols = Symbols {
+        labels: &[],
+        eqvs,
+        sizing: true,
+    };
+    let mut next: Vec<(&str, u32)> = SEGMENT_STARTS.to_vec();
+    let mut segment = 0;
+    let mut pending: Vec<String> = Vec::new();
+    let mut layout = Layout {
+        addresses: Vec::new(),
+        labels: Vec::new(),
+    };
+
+    for statement in statements {
+        pending.extend(statement.labels.iter().cloned());
+        let Some(mnemonic) = statement.mnemonic.as_deref() else {
+            continue;
+        };
+        if let Some(i) = next.iter().position(|(s, _)| *s == mnemonic) {
+            segment = i;
+            if let Some(address) = statement
+                .operands
+                .first()
+                .and_then(|a| analysis::parse_int(a))
+            {
+                next[i].1 = address as u32;
+            }
+            continue;
+        }
+
+        let (align, size) = match mnemonic {
+            ".align" => match statement
+                .operands
+                .first()
+                .and_then(|n| analysis::parse_int(n))
+            {
+                Some(n @ 0..=16) => (1 << n, 0),
+                _ => continue,
+            },
+            _ if mnemonic.starts_with('.') => {
+                match analysis::data_layout(mnemonic, &statement.operands) {
+                    Some((align, size)) => (align as u32, size as u32),
+                    None => continue,
+                }
+            }
+            _ => {
+                let words =
+                    encode(mnemonic, &statement.operands, 0, &symbols).map_or(1, |w| w.len());
+                (4, 4 * words as u32)
+            }
+        };
+
+        let address = next[segment].1.div_ceil(align) * align;
+        let labels = pending.drain(..).map(|label| (label, address));
+        layout.labels.extend(labels);
+        if mnemonic != ".align" {
+            layout.addresses.push((statement.line, address));
+        }
+        next[segment].1 = address + size;
+    }
+
+    layout
+}
+
+/// A listing of `contents` in the style of the MARS text segment view: each
+/// line with the address it assembles to and, for the instructions it knows
+/// how to encode, the machine code in hex.
+pub fn listing(contents: &str) -> String {
+    let statements = analysis::parse(contents);
+    let eqvs = analysis::eqvs(&statements);
+    let layout = lay_out(&statements, &eqvs);
+    let symbols = Symbols {
+        labels: &layout.labels,
+        eqvs: &eqvs,
+        sizing: false,
+    };
+
+    let mut out = String::new();
+    for (i, line) in contents.lines().enumerate() {
+        let address = layout
+            .addresses
+            .iter()
+            .find(|(l, _)| *l == i + 1)
+            .map(|a| a.1);
+        let statement = statements.iter().find(|s| s.line == i + 1);
+        let words = match (address, statement) {
+            (Some(address), Some(statement)) => statement
+                .mnemonic
+                .as_deref()
+                .filter(|m| !m.starts_with('.'))
+                .and_then(|m| encode(m, &statement.operands, address, &symbols))
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let address = address.map_or(String::new(), |a| format!("0x{:08x}", a));
+        let code: Vec<String> = words.iter().map(|w| format!("0x{:08x}", w)).collect();
+        let prefix = format!("{:<10}  {:<21}  ", address, code.join(" "));
+        out += format!("{}{}", prefix, line).trim_end();
+        out += "\n";
+    }
+    out
+}
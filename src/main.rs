@@ -1,4 +1,28 @@
+mod analysis;
+mod cli;
+mod config;
+mod diff;
+mod doctor;
+mod duplicates;
+mod emit;
+mod encoding;
+mod expand;
+mod extract;
+mod flatten;
 mod formatter;
+mod grade;
+mod isa;
+mod json;
+mod labels;
+mod lint;
+mod listing;
+mod outline;
+mod project;
+mod reorder;
+mod report;
+mod semdiff;
+mod sink;
+mod zip;
 
 #[cfg(test)]
 mod tests;
@@ -6,91 +30,1763 @@ mod tests;
 use std::env;
 use std::fs;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use cli::Arg;
+use json::Json;
+use sink::{FileSink, OutputSink, StreamSink};
+
+static FLAGS: &[cli::Flag] = &[
+    cli::Flag {
+        names: &["-h", "--help"],
+        value: None,
+        help: "See docs about tool",
+    },
+    cli::Flag {
+        names: &["-V", "--version"],
+        value: None,
+        help: "Print the version",
+    },
+    cli::Flag {
+        names: &["-w", "--write"],
+        value: None,
+        help: "Format files in place instead of printing them",
+    },
+    cli::Flag {
+        names: &["--stdout"],
+        value: None,
+        help: "Print the formatted files even when -w or -o is given",
+    },
+    cli::Flag {
+        names: &["-o", "--output"],
+        value: Some("DIR"),
+        help: "Write formatted files to DIR",
+    },
+    cli::Flag {
+        names: &["--config", "--config-path"],
+        value: Some("FILE"),
+        help: "Read options from FILE instead of the nearest macmips.toml",
+    },
+    cli::Flag {
+        names: &["--stdin"],
+        value: None,
+        help: "Read source from stdin and print the result (also -)",
+    },
+    cli::Flag {
+        names: &["--follow-includes"],
+        value: None,
+        help: "Also format the files the given ones .include, reporting missing ones",
+    },
+    cli::Flag {
+        names: &["--archive"],
+        value: Some("ZIP"),
+        help: "Format the .s and .asm files in ZIP, an uncompressed zip, into the zip -o names",
+    },
+    cli::Flag {
+        names: &["--files-from"],
+        value: Some("FILE"),
+        help: "Format the files listed one per line in FILE (- for stdin)",
+    },
+    cli::Flag {
+        names: &["--range-bytes"],
+        value: Some("START:END"),
+        help: "Print the edits touching bytes START..END as JSON",
+    },
+    cli::Flag {
+        names: &["--edits"],
+        value: None,
+        help: "Print the edits for the whole file as JSON",
+    },
+    cli::Flag {
+        names: &["--diff"],
+        value: None,
+        help: "Print a unified diff of the changes instead of writing them",
+    },
+    cli::Flag {
+        names: &["--check"],
+        value: None,
+        help: "Exit with status 1 if any input isn't formatted, without writing it",
+    },
+    cli::Flag {
+        names: &["--fail-on"],
+        value: Some("WHEN"),
+        help: "Exit with status 1 on any error, warning, change or never (default: change with --check, else never)",
+    },
+    cli::Flag {
+        names: &["-v", "--verbose"],
+        value: None,
+        help: "Note sections left unformatted",
+    },
+    cli::Flag {
+        names: &["--watch"],
+        value: None,
+        help: "Keep running and reformat the given files or directories on save",
+    },
+    cli::Flag {
+        names: &["--indent"],
+        value: Some("tab|N"),
+        help: "Indent with a tab or N spaces per level, overriding the config",
+    },
+    cli::Flag {
+        names: &["--encoding"],
+        value: Some("ENCODING"),
+        help: "Read and write utf-8, utf-8-bom or latin-1 (default utf-8)",
+    },
+    cli::Flag {
+        names: &["--report"],
+        value: Some("FILE"),
+        help: "Write a JSON summary of what formatting changed to FILE",
+    },
+    cli::Flag {
+        names: &["--dump-ast"],
+        value: None,
+        help: "Print the parsed sections and chunks as JSON",
+    },
+    cli::Flag {
+        names: &["--indent-report"],
+        value: None,
+        help: "Print indentation health before and after formatting",
+    },
+    cli::Flag {
+        names: &["--listing"],
+        value: None,
+        help: "Also write FILE.lst, listing each line's address and machine code",
+    },
+    cli::Flag {
+        names: &["--annotate-syscalls"],
+        value: None,
+        help: "Comment each syscall with the call and arguments it uses",
+    },
+    cli::Flag {
+        names: &["--annotate-offsets"],
+        value: None,
+        help: "Comment accesses like `lw $t0, arr+8` with the element they reach",
+    },
+    cli::Flag {
+        names: &["--annotate-data-offsets"],
+        value: None,
+        help: "Comment each data declaration with its byte offset in the section",
+    },
+    cli::Flag {
+        names: &["--organize"],
+        value: None,
+        help: "Also gather .globl lines, collapse blank-line runs and sort .data by label",
+    },
+];
+
+static LINT_FLAGS: &[cli::Flag] = &[
+    cli::Flag {
+        names: &["--config", "--config-path"],
+        value: Some("FILE"),
+        help: "Read options from FILE instead of the nearest macmips.toml",
+    },
+    cli::Flag {
+        names: &["--isa"],
+        value: Some("ISA"),
+        help: "Flag instructions outside mips1, mips32 or mips32r2",
+    },
+    cli::Flag {
+        names: &["--fix"],
+        value: None,
+        help: "Apply every fix in place, or only the one named after it",
+    },
+    cli::Flag {
+        names: &["--lint-timings"],
+        value: None,
+        help: "Print how long each rule took",
+    },
+    cli::Flag {
+        names: &["--fail-on"],
+        value: Some("WHEN"),
+        help: "Exit with status 1 on any error, warning, change or never (default: error)",
+    },
+];
+
+static EMIT_PROCEDURE_FLAGS: &[cli::Flag] = &[
+    cli::Flag {
+        names: &["--save"],
+        value: Some("REGS"),
+        help: "Save and restore these comma-separated registers",
+    },
+    cli::Flag {
+        names: &["--save-ra"],
+        value: None,
+        help: "Save and restore $ra",
+    },
+    cli::Flag {
+        names: &["--locals"],
+        value: Some("BYTES"),
+        help: "Reserve BYTES of stack for local variables",
+    },
+];
+
+static DOCTOR_FLAGS: &[cli::Flag] = &[cli::Flag {
+    names: &["--config", "--config-path"],
+    value: Some("FILE"),
+    help: "Diagnose FILE instead of the nearest macmips.toml",
+}];
+
+static CHECK_REORDER_FLAGS: &[cli::Flag] = &[cli::Flag {
+    names: &["--delay-slots"],
+    value: None,
+    help: "Allow moving an instruction into the delay slot after a branch",
+}];
+
+static DUPLICATES_FLAGS: &[cli::Flag] = &[cli::Flag {
+    names: &["--min-lines"],
+    value: Some("N"),
+    help: "Report only repeats of at least N instructions (default: 4)",
+}];
+
+static OUTLINE_FLAGS: &[cli::Flag] = &[cli::Flag {
+    names: &["--json"],
+    value: None,
+    help: "Print the symbols as JSON",
+}];
 
 fn help() {
-    println!("mac-mips v0.1.0\n");
-    println!("Usage: mac-mips [filename] [OPTIONS]\n");
+    println!("mac-mips v{}\n", env!("CARGO_PKG_VERSION"));
+    println!("Usage: mac-mips [OPTIONS] [--] [filename... | -]");
+    println!("       mac-mips config migrate [CONFIG FILE]");
+    println!("       mac-mips diff-fmt <OLD FILE> <NEW FILE>");
+    println!("       mac-mips compare-style <OLD CONFIG> <NEW CONFIG> <filename>");
+    println!("       mac-mips check-reorder <OLD FILE> <NEW FILE> [PROCEDURE] [--delay-slots]");
+    println!("       mac-mips extract <filename> <PROCEDURE>");
+    println!("       mac-mips flatten [filename]");
+    println!("       mac-mips duplicates [--min-lines N] <filename>");
+    println!("       mac-mips outline [--json] <filename>");
+    println!("       mac-mips doctor [--config <FILE>] [filename...]");
+    println!("       mac-mips grade <RUBRIC FILE> <filename>");
+    println!("       mac-mips preview-expand <filename>");
+    println!(
+        "       mac-mips emit-procedure <NAME> [--save REGS] [--save-ra] [--locals BYTES] < body"
+    );
+    println!("       mac-mips lint [filename...] [--config <FILE>] [--isa <ISA>]");
+    println!("                      [--fix [hidden-characters | smart-quotes]] [--lint-timings]");
+    println!("                      [--fail-on error|warning|change|never]\n");
     println!("Options:");
-    println!("\t-h\t        See docs about tool");
-    println!("\t-o <OUT DIR>\tOutput directory");
-    println!("");
+    print!("{}", cli::usage(FLAGS));
+    println!();
+    println!("Lint options:");
+    print!("{}", cli::usage(LINT_FLAGS));
+    println!();
+    println!("Emit-procedure options:");
+    print!("{}", cli::usage(EMIT_PROCEDURE_FLAGS));
+    println!();
+    println!("Doctor options:");
+    print!("{}", cli::usage(DOCTOR_FLAGS));
+    println!();
+    println!("Check-reorder options:");
+    print!("{}", cli::usage(CHECK_REORDER_FLAGS));
+    println!();
+    println!("Duplicates options:");
+    print!("{}", cli::usage(DUPLICATES_FLAGS));
+    println!();
+    println!("Outline options:");
+    print!("{}", cli::usage(OUTLINE_FLAGS));
+    println!();
+    println!("Options can also be set with MACMIPS_<OPTION> environment variables,");
+    println!("e.g. MACMIPS_COMMENT_GAP=4");
+    println!();
+    println!("Without a filename, format, lint and flatten work on the project");
+    println!("described by the nearest {}", project::MANIFEST_NAME);
+    println!();
     std::process::exit(0);
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+fn migrate_config(path: Option<&str>) {
+    let path = match config::config_path(path.map(Path::new), &config_dir(None)) {
+        Some(path) => path,
+        None => {
+            eprintln!(
+                "Error: Expected config file as cmd line arg or in ${}",
+                config::CONFIG_PATH_VAR
+            );
+            std::process::exit(1);
+        }
+    };
 
-    let mut file: Option<String> = None;
-    let mut output_dir: Option<&str> = None;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Couldn't read config file");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let mut i = 1;
-    while i < args.len() {
-        let arg: &str = args[i].as_str();
+    write_or_exit(
+        &mut FileSink::new(&path),
+        config::migrate(&contents).as_bytes(),
+        "migrated config",
+    );
 
-        if arg.starts_with('-') {
-            let arg_val: Option<&String> = args.get(i + 1);
+    println!(
+        "Migrated {} to config_version {}",
+        path.display(),
+        config::CONFIG_VERSION
+    );
+}
 
-            match (arg, arg_val) {
-                ("-h", _) => help(),
-                ("-o", Some(output)) => {
-                    output_dir = Some(output.as_str());
-                    i += 1;
-                }
-                (unknown, _) => {
-                    eprintln!("Error: Invalid args, {}", unknown);
+fn parse_range(range: &str) -> (usize, usize) {
+    let parsed = range
+        .split_once(':')
+        .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+
+    match parsed {
+        Some((start, end)) if start <= end => (start, end),
+        _ => {
+            eprintln!("Error: Invalid range, {} (expected START:END)", range);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_config_command(args: &[String]) {
+    match args.first().map(|a| a.as_str()) {
+        Some("migrate") => migrate_config(args.get(1).map(|a| a.as_str())),
+        Some(unknown) => {
+            eprintln!("Error: Unknown config command, {}", unknown);
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!("Error: Expected config command, e.g. 'mac-mips config migrate'");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_source(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// The project whose manifest is nearest the current directory, if any
+fn find_project() -> Option<project::Project> {
+    let path = project::Project::find(&config_dir(None))?;
+    match project::Project::load(&path) {
+        Ok(project) => Some(project),
+        Err(e) => {
+            eprintln!("Error: Invalid project manifest");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn project_files(project: &project::Project) -> Vec<String> {
+    match project.files(&read_source) {
+        Ok(files) => files.iter().map(|f| f.display().to_string()).collect(),
+        Err(e) => {
+            eprintln!("Error: Couldn't find the project's files");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_or_exit(path: &str, what: &str) -> String {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Couldn't read {}", what);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Writes `contents` to `sink`, exiting with an explanation if it can't
+fn write_to(sink: &mut dyn OutputSink, contents: &[u8], what: &str) -> Result<(), String> {
+    sink.write(contents).map_err(|e| {
+        let mut message = format!("Couldn't write {} to {}\n", what, sink.name());
+        if sink::is_locked(&e) {
+            message +=
+                "       Another program has it open, close it there (e.g. in MARS) and try again\n";
+        }
+        message + &e.to_string()
+    })
+}
+
+fn write_or_exit(sink: &mut dyn OutputSink, contents: &[u8], what: &str) {
+    if let Err(e) = write_to(sink, contents, what) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// The directory to look for a config file from when formatting `file`
+fn config_dir(file: Option<&str>) -> PathBuf {
+    let dir = match file.and_then(|f| Path::new(f).parent()) {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+fn load_config_file(path: Option<&Path>) -> config::Config {
+    match config::Config::load(path) {
+        Ok((config, warnings)) => {
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            config
+        }
+        Err(e) => {
+            eprintln!("Error: Couldn't load config");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load_config(config_path: Option<&str>, file: Option<&str>) -> config::Config {
+    let path = config::config_path(config_path.map(Path::new), &config_dir(file));
+    load_config_file(path.as_deref())
+}
+
+// Loads each config file once, however many of the files being formatted
+// share it
+struct ConfigCache<'a> {
+    explicit_path: Option<&'a str>,
+    annotate_syscalls: bool,
+    annotate_offsets: bool,
+    annotate_data_offsets: bool,
+    organize: bool,
+    indent: Option<config::IndentStyle>,
+    loaded: Vec<(Option<PathBuf>, config::Config)>,
+}
+
+impl ConfigCache<'_> {
+    fn get(&mut self, file: Option<&str>) -> config::Config {
+        let path = config::config_path(self.explicit_path.map(Path::new), &config_dir(file));
+        let mut config = match self.loaded.iter().find(|(p, _)| *p == path) {
+            Some((_, config)) => config.clone(),
+            None => {
+                let config = load_config_file(path.as_deref());
+                self.loaded.push((path, config.clone()));
+                config
+            }
+        };
+        config.annotate_syscalls |= self.annotate_syscalls;
+        config.annotate_offsets |= self.annotate_offsets;
+        config.annotate_data_offsets |= self.annotate_data_offsets;
+        if self.organize {
+            config.organize();
+        }
+        if let Some(indent) = self.indent {
+            config.indent = indent;
+        }
+        config
+    }
+}
+
+fn run_lint_command(args: &[String]) {
+    let mut files: Vec<String> = Vec::new();
+    let mut config_path: Option<&str> = None;
+    let mut isa: Option<isa::Isa> = None;
+    let mut fixes: Vec<&str> = Vec::new();
+    let mut timings = false;
+    let mut fail_on = cli::FailOn::Error;
+
+    let is_fix = |arg: &Arg| matches!(arg, Arg::Positional(name) if lint::FIXES.iter().any(|(n, _)| n == name));
+    let mut parsed = parse_args(args, LINT_FLAGS).into_iter().peekable();
+    while let Some(arg) = parsed.next() {
+        match arg {
+            Arg::Positional(file) => files.push(file.to_owned()),
+            Arg::Flag("--config", path) => config_path = path,
+            Arg::Flag("--isa", Some(name)) => {
+                isa = isa::Isa::parse(name);
+                if isa.is_none() {
+                    eprintln!(
+                        "Error: Unknown ISA, {} (expected mips1, mips32 or mips32r2)",
+                        name
+                    );
                     std::process::exit(1);
                 }
             }
-        } else {
-            file = Some(arg.to_owned());
+            // Every fix, unless the name of one follows
+            Arg::Flag("--fix", _) => match parsed.next_if(is_fix) {
+                Some(Arg::Positional(name)) => fixes.push(name),
+                _ => fixes.extend(lint::FIXES.iter().map(|(name, _)| *name)),
+            },
+            Arg::Flag("--lint-timings", _) => timings = true,
+            Arg::Flag("--fail-on", Some(name)) => fail_on = parse_fail_on(name),
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
         }
+    }
 
-        i += 1
+    let project = find_project();
+    let whole_project = files.is_empty();
+    if whole_project {
+        match &project {
+            Some(project) => files = project_files(project),
+            None => {
+                eprintln!("Error: Expected file as cmd line arg");
+                eprintln!("       or a {} to lint", project::MANIFEST_NAME);
+                std::process::exit(1);
+            }
+        }
     }
 
-    if let Some(filename) = file {
-        let path = Path::new(filename.as_str());
-        let file = fs::read_to_string(path);
+    let mut outcome = Outcome::default();
+    for file in &files {
+        outcome.add(lint_file(
+            file,
+            config_path,
+            isa,
+            &fixes,
+            timings,
+            project.as_ref(),
+        ));
+    }
+    if let (true, Some(project)) = (whole_project, &project) {
+        outcome.add(lint_project_symbols(project));
+    }
+    if outcome.fails(fail_on) {
+        std::process::exit(1);
+    }
+}
+
+// What a run found, which `--fail-on` decides whether to fail on
+#[derive(Debug, Default, Clone, Copy)]
+struct Outcome {
+    errors: bool,
+    warnings: bool,
+    changes: bool,
+}
+
+impl Outcome {
+    fn add(&mut self, other: Outcome) {
+        self.errors |= other.errors;
+        self.warnings |= other.warnings;
+        self.changes |= other.changes;
+    }
+
+    fn fails(&self, fail_on: cli::FailOn) -> bool {
+        match fail_on {
+            cli::FailOn::Never => false,
+            cli::FailOn::Error => self.errors,
+            cli::FailOn::Warning => self.errors || self.warnings,
+            cli::FailOn::Change => self.errors || self.warnings || self.changes,
+        }
+    }
+}
+
+// Parses `args` against `flags`, exiting with an explanation if they don't fit
+fn parse_args<'a>(args: &'a [String], flags: &[cli::Flag]) -> Vec<Arg<'a>> {
+    match cli::parse(args, flags) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-        if let Err(e) = file {
-            eprintln!("Error: Couldn't read file");
+fn parse_fail_on(name: &str) -> cli::FailOn {
+    match cli::FailOn::parse(name) {
+        Some(fail_on) => fail_on,
+        None => {
+            eprintln!("Error: Invalid --fail-on '{}'", name);
+            eprintln!("       Expected error, warning, change or never");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Checks the symbols shared between a project's sources, printing what's
+// wrong with them like lint diagnostics
+fn lint_project_symbols(project: &project::Project) -> Outcome {
+    let found = match project.check_symbols(&read_source) {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("Error: Couldn't check the project's symbols");
             eprintln!("{}", e);
             std::process::exit(1);
         }
+    };
+
+    let mut outcome = Outcome::default();
+    for (file, d) in found {
+        for d in project.lint_policy(vec![d]) {
+            println!(
+                "{}:{}: {}[{}]: {}",
+                file.display(),
+                d.line,
+                d.severity,
+                d.rule,
+                d.message
+            );
+            outcome.errors |= d.severity == lint::Severity::Error;
+            outcome.warnings |= d.severity == lint::Severity::Warning;
+        }
+    }
+    outcome
+}
+
+// Lints a file, printing its diagnostics, and returns what it found
+fn lint_file(
+    file: &str,
+    config_path: Option<&str>,
+    isa: Option<isa::Isa>,
+    fixes: &[&str],
+    timings: bool,
+    project: Option<&project::Project>,
+) -> Outcome {
+    let mut config = load_config(config_path, Some(file));
+    if let Some(project) = project {
+        project.apply(&mut config);
+    }
+    if let Some(isa) = isa {
+        config.isa = isa;
+    }
+
+    let mut contents = read_or_exit(file, "file");
+    let mut changes = false;
+    if !fixes.is_empty() {
+        let fixed = lint::FIXES
+            .iter()
+            .filter(|(name, _)| fixes.contains(name))
+            .fold(contents.clone(), |fixed, (_, fix)| fix(&fixed));
+        if fixed != contents {
+            write_or_exit(
+                &mut FileSink::new(Path::new(file)),
+                fixed.as_bytes(),
+                "fixes",
+            );
+            contents = fixed;
+            changes = true;
+        }
+    }
+
+    let (mut diagnostics, rule_timings) = lint::lint_timed(&contents, &config);
+    if timings {
+        for (rule, time) in rule_timings {
+            eprintln!(
+                "{}: {} took {:.3}ms",
+                file,
+                rule,
+                time.as_secs_f64() * 1000.0
+            );
+        }
+    }
+    if let Some(project) = project {
+        diagnostics = project.lint_policy(diagnostics);
+    }
+
+    for d in &diagnostics {
+        println!(
+            "{}:{}: {}[{}]: {}",
+            file, d.line, d.severity, d.rule, d.message
+        );
+    }
+
+    let has = |severity| diagnostics.iter().any(|d| d.severity == severity);
+    Outcome {
+        errors: has(lint::Severity::Error),
+        warnings: has(lint::Severity::Warning),
+        changes,
+    }
+}
+
+fn run_diff_fmt_command(args: &[String]) {
+    let (old, new) = match args {
+        [old, new] => (read_or_exit(old, "old file"), read_or_exit(new, "new file")),
+        _ => {
+            eprintln!("Error: Expected 'mac-mips diff-fmt <OLD FILE> <NEW FILE>'");
+            std::process::exit(1);
+        }
+    };
+
+    let changes = semdiff::semantic_diff(&old, &new);
+    if changes.is_empty() {
+        println!("No differences in code");
+        return;
+    }
+
+    for change in changes {
+        println!("{}", change);
+    }
+    std::process::exit(1);
+}
 
-        let contents = file.unwrap();
-        let formatted = formatter::format(contents);
+fn run_compare_style_command(args: &[String]) {
+    let (old_config, new_config, file) = match args {
+        [old_config, new_config, file] => (old_config, new_config, file),
+        _ => {
+            eprintln!(
+                "Error: Expected 'mac-mips compare-style <OLD CONFIG> <NEW CONFIG> <filename>'"
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let contents = read_or_exit(file, "file");
+    let format = |config_path: &str| {
+        let config = load_config(Some(config_path), Some(file));
+        match formatter::format_with_config(contents.clone(), &config) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("Error: Couldn't format {} with {}", file, config_path);
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+    let (old, new) = (format(old_config), format(new_config));
+    if old == new {
+        println!("No differences in style");
+        return;
+    }
 
-        if let Err(e) = formatted {
-            eprintln!("Error: Couldn't format file");
+    let name = |config_path: &str| format!("{} ({})", file, config_path);
+    print!(
+        "{}",
+        diff::unified(&old, &new, &name(old_config), &name(new_config), 3)
+    );
+    std::process::exit(1);
+}
+
+fn run_check_reorder_command(args: &[String]) {
+    let mut delay_slots = false;
+    let mut files: Vec<&str> = Vec::new();
+
+    for arg in parse_args(args, CHECK_REORDER_FLAGS) {
+        match arg {
+            Arg::Positional(file) => files.push(file),
+            Arg::Flag("--delay-slots", _) => delay_slots = true,
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
+        }
+    }
+    let (old, new, procedure) = match files[..] {
+        [old, new] => (old, new, None),
+        [old, new, procedure] => (old, new, Some(procedure)),
+        _ => {
+            eprintln!(
+                "Error: Expected 'mac-mips check-reorder <OLD FILE> <NEW FILE> [PROCEDURE] [--delay-slots]'"
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let (old, new) = (read_or_exit(old, "old file"), read_or_exit(new, "new file"));
+    let problems = match reorder::check(&old, &new, procedure, delay_slots) {
+        Ok(problems) => problems,
+        Err(e) => {
+            eprintln!("Error: Couldn't compare procedures");
             eprintln!("{}", e);
             std::process::exit(1);
         }
+    };
+    if problems.is_empty() {
+        println!("No reordering hazards");
+        return;
+    }
 
-        let formatted_content = formatted.unwrap();
+    for problem in problems {
+        println!("{}", problem);
+    }
+    std::process::exit(1);
+}
 
-        let out_path = match output_dir {
-            Some(outdir) => Path::new(outdir).join(path.file_name().unwrap()),
-            None => path.to_path_buf(),
-        };
+fn run_extract_command(args: &[String]) {
+    let (file, name) = match args {
+        [file, name] => (file, name),
+        _ => {
+            eprintln!("Error: Expected 'mac-mips extract <filename> <PROCEDURE>'");
+            std::process::exit(1);
+        }
+    };
+
+    let extracted = match extract::extract(&read_or_exit(file, "file"), name) {
+        Some(extracted) => extracted,
+        None => {
+            eprintln!("Error: No procedure named {} in {}", name, file);
+            std::process::exit(1);
+        }
+    };
+
+    match formatter::format_with_config(extracted, &load_config(None, Some(file))) {
+        Ok(formatted) => print!("{}", formatted),
+        Err(e) => {
+            eprintln!("Error: Couldn't format procedure");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_emit_procedure_command(args: &[String]) {
+    let mut name: Option<&str> = None;
+    let mut options = emit::ProcedureOptions::default();
+
+    for arg in parse_args(args, EMIT_PROCEDURE_FLAGS) {
+        match arg {
+            Arg::Positional(arg) if name.is_none() => name = Some(arg),
+            Arg::Positional(extra) => {
+                eprintln!("Error: Invalid args, {}", extra);
+                std::process::exit(1);
+            }
+            Arg::Flag("--save", Some(registers)) => options
+                .saved
+                .extend(registers.split(',').map(|r| r.trim().to_string())),
+            Arg::Flag("--save-ra", _) => options.save_ra = true,
+            Arg::Flag("--locals", Some(bytes)) => {
+                options.locals = match bytes.parse() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        eprintln!("Error: Invalid --locals '{}'", bytes);
+                        std::process::exit(1);
+                    }
+                };
+            }
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
+        }
+    }
+    let Some(name) = name else {
+        eprintln!(
+            "Error: Expected 'mac-mips emit-procedure <NAME> [--save REGS] [--save-ra] [--locals BYTES]'"
+        );
+        std::process::exit(1);
+    };
+
+    let mut body = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut body) {
+        eprintln!("Error: Couldn't read procedure body from stdin");
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let body: Vec<&str> = body.lines().collect();
+
+    match emit::procedure(name, &body, &options, &load_config(None, None)) {
+        Ok(procedure) => print!("{}", procedure),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-        let file = fs::File::create(out_path);
+fn run_flatten_command(args: &[String]) {
+    let project = find_project();
+    let file = match (args, &project) {
+        ([file], _) => file.clone(),
+        ([], Some(project)) => project.entry.display().to_string(),
+        _ => {
+            eprintln!("Error: Expected 'mac-mips flatten <filename>'");
+            std::process::exit(1);
+        }
+    };
+    let include_paths = match &project {
+        Some(project) => project.include_paths.clone(),
+        None => Vec::new(),
+    };
 
-        if let Err(e) = file {
-            eprintln!("Error: Couldn't edit file");
+    let flattened = match flatten::flatten(Path::new(&file), &include_paths, &read_source) {
+        Ok(flattened) => flattened,
+        Err(e) => {
+            eprintln!("Error: Couldn't flatten includes");
             eprintln!("{}", e);
             std::process::exit(1);
         }
+    };
 
-        if let Err(e) = file.unwrap().write_all(formatted_content.as_bytes()) {
-            eprintln!("Error: Couldn't write formatted code to file");
+    match formatter::format_with_config(flattened, &load_config(None, Some(&file))) {
+        Ok(formatted) => print!("{}", formatted),
+        Err(e) => {
+            eprintln!("Error: Couldn't format flattened file");
             eprintln!("{}", e);
             std::process::exit(1);
         }
+    }
+}
+
+fn run_duplicates_command(args: &[String]) {
+    let mut min_lines = 4;
+    let mut files: Vec<&str> = Vec::new();
+
+    for arg in parse_args(args, DUPLICATES_FLAGS) {
+        match arg {
+            Arg::Positional(file) => files.push(file),
+            Arg::Flag("--min-lines", Some(n)) => {
+                min_lines = match n.parse() {
+                    Ok(n) => n,
+                    Err(_) => {
+                        eprintln!("Error: Invalid value for --min-lines '{}'", n);
+                        std::process::exit(1);
+                    }
+                };
+            }
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
+        }
+    }
+    let file = match files[..] {
+        [file] => file,
+        _ => {
+            eprintln!("Error: Expected 'mac-mips duplicates [--min-lines N] <filename>'");
+            std::process::exit(1);
+        }
+    };
+
+    let statements = analysis::parse(&read_or_exit(file, "file"));
+    let duplicates = duplicates::find_duplicates(&statements, min_lines);
+
+    for d in &duplicates {
+        println!(
+            "{} instructions in {} (lines {}-{}) repeat in {} (lines {}-{})",
+            d.len,
+            d.first.procedure,
+            d.first.first_line,
+            d.first.last_line,
+            d.second.procedure,
+            d.second.first_line,
+            d.second.last_line
+        );
+    }
+    if duplicates.is_empty() {
+        println!("No duplicated code found");
+    } else {
+        println!("Consider moving repeated code into a shared procedure");
+    }
+}
+
+fn run_outline_command(args: &[String]) {
+    let mut json = false;
+    let mut files: Vec<&str> = Vec::new();
+
+    for arg in parse_args(args, OUTLINE_FLAGS) {
+        match arg {
+            Arg::Positional(file) => files.push(file),
+            Arg::Flag("--json", _) => json = true,
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
+        }
+    }
+    let file = match files[..] {
+        [file] => file,
+        _ => {
+            eprintln!("Error: Expected 'mac-mips outline [--json] <filename>'");
+            std::process::exit(1);
+        }
+    };
+
+    let symbols = outline::outline(&analysis::parse(&read_or_exit(file, "file")));
+    if json {
+        println!(
+            "{}",
+            Json::Array(symbols.iter().map(outline::Symbol::to_json).collect())
+        );
     } else {
+        print!("{}", outline::render(&symbols));
+    }
+}
+
+fn run_doctor_command(args: &[String]) {
+    let mut explicit_path: Option<&str> = None;
+    let mut files: Vec<&str> = Vec::new();
+
+    for arg in parse_args(args, DOCTOR_FLAGS) {
+        match arg {
+            Arg::Positional(file) => files.push(file),
+            Arg::Flag("--config", path) => explicit_path = path,
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
+        }
+    }
+
+    println!("mac-mips v{}", env!("CARGO_PKG_VERSION"));
+
+    let cwd = config_dir(None);
+    let path = config::config_path(explicit_path.map(Path::new), &cwd);
+    match (&path, explicit_path, env::var_os(config::CONFIG_PATH_VAR)) {
+        (None, _, _) => {
+            println!("Config: none found from {}, using defaults", cwd.display());
+            println!("  hint: create a macmips.toml at your project root to share options");
+        }
+        (Some(path), Some(_), _) => println!("Config: {} (from --config)", path.display()),
+        (Some(path), None, Some(_)) => println!(
+            "Config: {} (from ${})",
+            path.display(),
+            config::CONFIG_PATH_VAR
+        ),
+        (Some(path), None, None) => {
+            println!("Config: {} (nearest to {})", path.display(), cwd.display())
+        }
+    }
+
+    let config = match config::Config::load(path.as_deref()) {
+        Ok((config, warnings)) => {
+            for warning in warnings {
+                println!("  warning: {}", warning);
+            }
+            config
+        }
+        Err(e) => {
+            println!("  problem: {}", e);
+            println!(
+                "  hint: fix the option above, or run 'mac-mips config migrate' if it was renamed"
+            );
+            config::Config::default()
+        }
+    };
+
+    let project = find_project();
+    if let Some(project) = &project {
+        println!(
+            "Project: {} (entry {})",
+            project.root.join(project::MANIFEST_NAME).display(),
+            project.entry.display()
+        );
+    }
+
+    println!("Options:");
+    for key in config::OPTIONS {
+        if let Some(value) = config.get(key) {
+            println!("  {} = {}", key, value);
+        }
+    }
+    for (name, _) in env::vars() {
+        if name.starts_with("MACMIPS_") && name != config::CONFIG_PATH_VAR {
+            println!("  (${} overrides the config file)", name);
+        }
+    }
+
+    for file in files {
+        println!("{}:", file);
+
+        let file_config =
+            config::config_path(explicit_path.map(Path::new), &config_dir(Some(file)));
+        if file_config != path {
+            match &file_config {
+                Some(p) => println!("  uses config {}", p.display()),
+                None => println!("  uses the default config"),
+            }
+        }
+
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!("  can't read: {}", e);
+                println!("  hint: check the path, and that the file is UTF-8 (see --encoding)");
+                continue;
+            }
+        };
+
+        match fs::metadata(file).map(|m| m.permissions().readonly()) {
+            Ok(false) => println!("  writable"),
+            _ => {
+                println!("  not writable, so 'mac-mips -w' can't format it in place");
+                println!("  hint: use -o <DIR> to write the result elsewhere");
+            }
+        }
+
+        let (dialect, evidence) = doctor::detect_dialect(&analysis::parse(&contents));
+        match evidence.is_empty() {
+            true => println!("  dialect: {}", dialect),
+            false => println!("  dialect: {} ({})", dialect, evidence.join(", ")),
+        }
+        if dialect == doctor::Dialect::Gnu {
+            println!("  hint: GNU as sections such as .section and .rodata are left as written");
+        }
+        match project.as_ref().and_then(|p| p.dialect) {
+            Some(expected) if dialect != expected && dialect != doctor::Dialect::Generic => {
+                println!("  hint: the project manifest says {}", expected)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn run_grade_command(args: &[String]) {
+    let (rubric_path, file) = match args {
+        [rubric_path, file] => (rubric_path, file),
+        _ => {
+            eprintln!("Error: Expected 'mac-mips grade <RUBRIC FILE> <filename>'");
+            std::process::exit(1);
+        }
+    };
+
+    let rubric = match grade::Rubric::parse(&read_or_exit(rubric_path, "rubric")) {
+        Ok(rubric) => rubric,
+        Err(e) => {
+            eprintln!("Error: Invalid rubric");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let config = load_config(None, Some(file));
+    let statements = analysis::parse(&read_or_exit(file, "file"));
+    let checks = grade::grade(&rubric, &statements, &config.instructions);
+
+    for check in &checks {
+        let status = if check.passed { "pass" } else { "fail" };
+        println!("[{}] {}: {}", status, check.name, check.detail);
+    }
+    println!(
+        "Score: {}/{}",
+        checks.iter().filter(|c| c.passed).count(),
+        checks.len()
+    );
+}
+
+struct FormatOptions<'a> {
+    output_dir: Option<&'a str>,
+    range_bytes: Option<(usize, usize)>,
+    print_edits: bool,
+    print_diff: bool,
+    check: bool,
+    encoding: Option<encoding::Encoding>,
+    dump_ast: bool,
+    indent_report: bool,
+    listing: bool,
+    report: bool,
+    verbose: bool,
+    write: bool,
+}
+
+// Formats one file, or stdin when `file` is `None`, producing whichever
+// output the options ask for. Returns what it found, or why it couldn't
+fn format_file(
+    file: Option<&str>,
+    options: &FormatOptions,
+    config: &config::Config,
+    reports: &mut Vec<Json>,
+) -> Result<Outcome, String> {
+    let bytes = if let Some(filename) = file {
+        match fs::read(filename) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Couldn't read file {}\n{}", filename, e)),
+        }
+    } else {
+        let mut bytes = Vec::new();
+        if let Err(e) = std::io::stdin().read_to_end(&mut bytes) {
+            return Err(format!("Couldn't read stdin\n{}", e));
+        }
+        bytes
+    };
+    let original = bytes.clone();
+    let contents = match options
+        .encoding
+        .unwrap_or(encoding::Encoding::Utf8)
+        .decode(bytes)
+    {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Couldn't decode input\n{}", e)),
+    };
+
+    if options.verbose {
+        for (line, directive) in formatter::verbatim_sections(&contents) {
+            eprintln!(
+                "Note: {}:{}: `{}` section left as written",
+                file.unwrap_or("<stdin>"),
+                line,
+                directive
+            );
+        }
+    }
+
+    // Oversized input is passed through untouched rather than formatted
+    let exceeded = config.exceeded_limit(&contents);
+    if let Some(reason) = &exceeded {
+        eprintln!(
+            "Warning: Not formatting {}, {}",
+            file.unwrap_or("<stdin>"),
+            reason
+        );
+    }
+
+    // So is a file whose protected region can't be found, as an error for
+    // that file alone
+    let unprotected = formatter::protected_regions(&contents, config).err();
+    if let Some(e) = &unprotected {
+        eprintln!(
+            "Error: Not formatting {}, it is missing its protected region",
+            file.unwrap_or("<stdin>")
+        );
+        eprintln!("{}", e);
+    }
+    let untouched = exceeded.is_some() || unprotected.is_some();
+
+    if options.dump_ast {
+        if !untouched {
+            println!("{}", formatter::dump_ast(&contents, config));
+        }
+        return Ok(Outcome {
+            errors: unprotected.is_some(),
+            warnings: exceeded.is_some(),
+            ..Outcome::default()
+        });
+    }
+
+    let formatted_content = match untouched {
+        true => contents.clone(),
+        false => match formatter::format_with_config(contents.clone(), config) {
+            Ok(formatted) => formatted,
+            Err(e) => return Err(format!("Couldn't format file\n{}", e)),
+        },
+    };
+
+    let (lf, crlf) = encoding::line_endings(&contents);
+    let use_crlf = match config.line_endings {
+        config::LineEndings::Auto => crlf > lf,
+        config::LineEndings::Lf => false,
+        config::LineEndings::Crlf => true,
+    };
+    if options.verbose && lf > 0 && crlf > 0 && !untouched {
+        eprintln!(
+            "Note: {} mixes line endings ({} LF, {} CRLF), writing {}",
+            file.unwrap_or("<stdin>"),
+            lf,
+            crlf,
+            if use_crlf { "CRLF" } else { "LF" }
+        );
+    }
+    let formatted_content = match (use_crlf, untouched) {
+        (true, false) => encoding::to_crlf(&formatted_content),
+        _ => formatted_content,
+    };
+
+    let changed = formatted_content != contents;
+    let outcome = Outcome {
+        errors: unprotected.is_some(),
+        warnings: exceeded.is_some(),
+        changes: changed,
+    };
+    if options.check {
+        let name = file.unwrap_or("<stdin>");
+        match options.print_diff {
+            true => print!(
+                "{}",
+                diff::unified(&contents, &formatted_content, name, name, 3)
+            ),
+            false if changed => eprintln!("Would reformat {}", name),
+            false => {}
+        }
+        return Ok(outcome);
+    }
+
+    if options.indent_report {
+        eprintln!(
+            "Indentation before: {}",
+            analysis::IndentHealth::of(&contents)
+        );
+        eprintln!(
+            "Indentation after:  {}",
+            analysis::IndentHealth::of(&formatted_content)
+        );
+    }
+
+    if options.report {
+        reports.push(report::file_report(
+            file.unwrap_or("<stdin>"),
+            &contents,
+            &formatted_content,
+            &formatter::chunk_counts(&contents, config),
+        ));
+    }
+
+    if let (true, Some(file)) = (options.listing, file) {
+        let path = match options.output_dir {
+            Some(outdir) => Path::new(outdir).join(Path::new(file).file_name().unwrap()),
+            None => PathBuf::from(file),
+        };
+        let listing = listing::listing(&formatted_content);
+        let mut sink = FileSink::new(&path.with_extension("lst"));
+        write_to(&mut sink, listing.as_bytes(), "listing")?;
+    }
+
+    if options.print_edits || options.range_bytes.is_some() {
+        let (start, end) = options.range_bytes.unwrap_or((0, contents.len()));
+        if end > contents.len() {
+            return Err(format!(
+                "Range ends past the end of the input ({} bytes)",
+                contents.len()
+            ));
+        }
+
+        let edits = diff::text_edits(&contents, &formatted_content)
+            .into_iter()
+            .filter(|edit| edit.overlaps(start, end))
+            .map(|edit| {
+                Json::object(vec![
+                    ("start", edit.start.into()),
+                    ("end", edit.end.into()),
+                    ("text", edit.text.into()),
+                ])
+            })
+            .collect();
+        println!("{}", Json::Array(edits));
+        return Ok(outcome);
+    }
+
+    if options.print_diff {
+        let name = file.unwrap_or("<stdin>");
+        print!(
+            "{}",
+            diff::unified(&contents, &formatted_content, name, name, 3)
+        );
+        return Ok(outcome);
+    }
+
+    let output = match options.encoding {
+        Some(encoding) => match encoding.encode(&formatted_content) {
+            Ok(output) => output,
+            Err(errors) => {
+                let mut message = format!("Couldn't encode output as {}", encoding);
+                for error in errors {
+                    message += &format!("\n{}", error);
+                }
+                return Err(message);
+            }
+        },
+        None => formatted_content.into_bytes(),
+    };
+
+    let path = match file {
+        Some(file) if options.write || options.output_dir.is_some() => Path::new(file),
+        _ => {
+            write_to(&mut StreamSink::stdout(), &output, "formatted code")?;
+            return Ok(outcome);
+        }
+    };
+    let out_path = match options.output_dir {
+        Some(outdir) => Path::new(outdir).join(path.file_name().unwrap()),
+        None if output == original => return Ok(outcome),
+        None => path.to_path_buf(),
+    };
+
+    write_to(&mut FileSink::new(&out_path), &output, "formatted code")?;
+    Ok(outcome)
+}
+
+// Formats the assembly files in a zip archive into a new one at `out`,
+// copying everything else across as it is. Entries that can't be formatted
+// are copied as they are too, with an explanation. With `check`, only says
+// which entries would change
+fn format_archive(
+    archive: &str,
+    out: Option<&str>,
+    check: bool,
+    configs: &mut ConfigCache,
+) -> Outcome {
+    if out.is_none() && !check {
+        eprintln!("Error: Expected -o <ZIP> to write the formatted archive to");
+        std::process::exit(1);
+    }
+    let bytes = match fs::read(archive) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Error: Couldn't read archive {}", archive);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut entries = match zip::read(&bytes) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error: Couldn't read archive {}", archive);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let config = configs.get(Some(archive));
+    let mut outcome = Outcome::default();
+    for entry in &mut entries {
+        let is_source = matches!(
+            Path::new(&entry.name).extension().and_then(|e| e.to_str()),
+            Some("s" | "asm")
+        );
+        if !is_source {
+            continue;
+        }
+        let name = format!("{}:{}", archive, entry.name);
+        let Ok(contents) = String::from_utf8(entry.contents.clone()) else {
+            eprintln!("Error: Not formatting {}, it isn't UTF-8", name);
+            outcome.errors = true;
+            continue;
+        };
+        if let Some(reason) = config.exceeded_limit(&contents) {
+            eprintln!("Warning: Not formatting {}, {}", name, reason);
+            outcome.warnings = true;
+            continue;
+        }
+        if let Err(e) = formatter::protected_regions(&contents, &config) {
+            eprintln!(
+                "Error: Not formatting {}, it is missing its protected region",
+                name
+            );
+            eprintln!("{}", e);
+            outcome.errors = true;
+            continue;
+        }
+        let formatted = match formatter::format_with_config(contents.clone(), &config) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("Error: Couldn't format {}", name);
+                eprintln!("{}", e);
+                outcome.errors = true;
+                continue;
+            }
+        };
+        let (lf, crlf) = encoding::line_endings(&contents);
+        let formatted = match config.line_endings {
+            config::LineEndings::Crlf => encoding::to_crlf(&formatted),
+            config::LineEndings::Auto if crlf > lf => encoding::to_crlf(&formatted),
+            _ => formatted,
+        };
+
+        if formatted != contents {
+            outcome.changes = true;
+            if check {
+                eprintln!("Would reformat {}", name);
+            }
+        }
+        entry.contents = formatted.into_bytes();
+    }
+
+    if let (Some(out), false) = (out, check) {
+        write_or_exit(
+            &mut FileSink::new(Path::new(out)),
+            &zip::write(&entries),
+            "formatted archive",
+        );
+    }
+    outcome
+}
+
+// The given files, and the assembly files anywhere under the given directories
+fn source_files(paths: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+
+    while let Some(path) = pending.pop() {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        if let Ok(entries) = fs::read_dir(&path) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_source =
+                    matches!(path.extension().and_then(|e| e.to_str()), Some("s" | "asm"));
+                if path.is_dir() || is_source {
+                    pending.push(path);
+                }
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Polls for changes rather than using OS file events, which would need a
+// platform-specific dependency
+fn watch_files(paths: &[String], options: &FormatOptions, configs: &mut ConfigCache) -> ! {
+    let mut seen: Vec<(PathBuf, Option<SystemTime>)> = source_files(paths)
+        .into_iter()
+        .map(|path| {
+            let time = modified(&path);
+            (path, time)
+        })
+        .collect();
+    println!("Watching {} files for changes", seen.len());
+
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+
+        for path in source_files(paths) {
+            let time = modified(&path);
+            if time.is_none() || seen.iter().any(|(p, t)| *p == path && *t == time) {
+                continue;
+            }
+
+            // A file deleted or caught mid-save is reported, and tried
+            // again when it next changes
+            let config = configs.get(path.to_str());
+            match format_file(path.to_str(), options, &config, &mut Vec::new()) {
+                Ok(_) => println!("Formatted {}", path.display()),
+                Err(e) => eprintln!("Error: {}", e),
+            }
+
+            seen.retain(|(p, _)| *p != path);
+            let time = modified(&path);
+            seen.push((path, time));
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(|a| a.as_str()) {
+        Some("config") => return run_config_command(&args[2..]),
+        Some("grade") => return run_grade_command(&args[2..]),
+        Some("lint") => return run_lint_command(&args[2..]),
+        Some("diff-fmt") => return run_diff_fmt_command(&args[2..]),
+        Some("compare-style") => return run_compare_style_command(&args[2..]),
+        Some("check-reorder") => return run_check_reorder_command(&args[2..]),
+        Some("extract") => return run_extract_command(&args[2..]),
+        Some("flatten") => return run_flatten_command(&args[2..]),
+        Some("duplicates") => return run_duplicates_command(&args[2..]),
+        Some("outline") => return run_outline_command(&args[2..]),
+        Some("doctor") => return run_doctor_command(&args[2..]),
+        Some("emit-procedure") => return run_emit_procedure_command(&args[2..]),
+        Some("preview-expand") => {
+            return match args.get(2) {
+                Some(file) => print!("{}", expand::expand(&read_or_exit(file, "file"))),
+                None => {
+                    eprintln!("Error: Expected file as cmd line arg");
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let mut files: Vec<String> = Vec::new();
+    let mut files_from: Option<&str> = None;
+    let mut archive: Option<&str> = None;
+    let mut output_dir: Option<&str> = None;
+    let mut config_path: Option<&str> = None;
+    let mut use_stdin = false;
+    let mut range_bytes: Option<(usize, usize)> = None;
+    let mut print_edits = false;
+    let mut print_diff = false;
+    let mut check = false;
+    let mut fail_on: Option<cli::FailOn> = None;
+    let mut watch = false;
+    let mut stdout = false;
+    let mut listing = false;
+    let mut follow_includes = false;
+    let mut write = false;
+    let mut verbose = false;
+    let mut encoding: Option<encoding::Encoding> = None;
+    let mut dump_ast = false;
+    let mut annotate_syscalls = false;
+    let mut annotate_offsets = false;
+    let mut annotate_data_offsets = false;
+    let mut organize = false;
+    let mut indent_report = false;
+    let mut indent: Option<config::IndentStyle> = None;
+    let mut report_path: Option<&str> = None;
+
+    let parsed = parse_args(&args[1..], FLAGS);
+
+    for arg in parsed {
+        match arg {
+            Arg::Positional("-") => use_stdin = true,
+            Arg::Positional(file) => files.push(file.to_owned()),
+            Arg::Flag("--help", _) => help(),
+            Arg::Flag("--version", _) => {
+                println!("mac-mips v{}", env!("CARGO_PKG_VERSION"));
+                std::process::exit(0);
+            }
+            Arg::Flag("--output", dir) => output_dir = dir,
+            Arg::Flag("--report", path) => report_path = path,
+            Arg::Flag("--config", path) => config_path = path,
+            Arg::Flag("--stdin", _) => use_stdin = true,
+            Arg::Flag("--files-from", list) => files_from = list,
+            Arg::Flag("--archive", path) => archive = path,
+            Arg::Flag("--follow-includes", _) => follow_includes = true,
+            Arg::Flag("--edits", _) => print_edits = true,
+            Arg::Flag("--diff", _) => print_diff = true,
+            Arg::Flag("--check", _) => check = true,
+            Arg::Flag("--fail-on", Some(name)) => fail_on = Some(parse_fail_on(name)),
+            Arg::Flag("--watch", _) => watch = true,
+            Arg::Flag("--write", _) => write = true,
+            Arg::Flag("--stdout", _) => stdout = true,
+            Arg::Flag("--verbose", _) => verbose = true,
+            Arg::Flag("--encoding", Some(name)) => {
+                encoding = match encoding::Encoding::parse(name) {
+                    Some(encoding) => Some(encoding),
+                    None => {
+                        eprintln!("Error: Unknown encoding '{}'", name);
+                        eprintln!("       Expected utf-8, utf-8-bom or latin-1");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            Arg::Flag("--dump-ast", _) => dump_ast = true,
+            Arg::Flag("--annotate-syscalls", _) => annotate_syscalls = true,
+            Arg::Flag("--annotate-offsets", _) => annotate_offsets = true,
+            Arg::Flag("--annotate-data-offsets", _) => annotate_data_offsets = true,
+            Arg::Flag("--organize", _) => organize = true,
+            Arg::Flag("--indent-report", _) => indent_report = true,
+            Arg::Flag("--listing", _) => listing = true,
+            Arg::Flag("--indent", Some(style)) => {
+                indent = config::IndentStyle::parse(style);
+                if indent.is_none() {
+                    eprintln!(
+                        "Error: Invalid indent '{}' (expected tab or a number)",
+                        style
+                    );
+                    std::process::exit(1);
+                }
+            }
+            Arg::Flag("--range-bytes", Some(range)) => range_bytes = Some(parse_range(range)),
+            Arg::Flag(name, _) => unreachable!("{} isn't handled", name),
+        }
+    }
+
+    let mut configs = ConfigCache {
+        explicit_path: config_path,
+        annotate_syscalls,
+        annotate_offsets,
+        annotate_data_offsets,
+        organize,
+        indent,
+        loaded: Vec::new(),
+    };
+
+    if let Some(archive) = archive {
+        if !files.is_empty() || files_from.is_some() || use_stdin {
+            eprintln!("Error: Can't format an archive and other files together");
+            std::process::exit(1);
+        }
+        let outcome = format_archive(archive, output_dir, check, &mut configs);
+        let fail_on = fail_on.unwrap_or(match check {
+            true => cli::FailOn::Change,
+            false => cli::FailOn::Never,
+        });
+        if outcome.fails(fail_on) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut files: Vec<Option<String>> = files.into_iter().map(Some).collect();
+    if let Some(list) = files_from {
+        let contents = match list {
+            "-" => {
+                let mut contents = String::new();
+                if let Err(e) = std::io::stdin().read_to_string(&mut contents) {
+                    eprintln!("Error: Couldn't read file list from stdin");
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                contents
+            }
+            path => read_or_exit(path, "file list"),
+        };
+        files.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(|l| Some(l.to_string())),
+        );
+    }
+    if use_stdin {
+        if listing {
+            eprintln!("Error: Can't write a listing for stdin");
+            std::process::exit(1);
+        }
+        if files_from == Some("-") {
+            eprintln!("Error: Can't read both source and a file list from stdin");
+            std::process::exit(1);
+        }
+        files.push(None);
+    }
+    if files.is_empty() && files_from.is_none() {
+        if let Some(project) = find_project() {
+            files = project_files(&project).into_iter().map(Some).collect();
+        }
+    }
+    if files.is_empty() && files_from.is_none() {
         eprintln!("Error: Expected file as cmd line arg");
         eprintln!("       To see how to use this tool, use 'mac-mips -h'");
         std::process::exit(1);
     }
+
+    if stdout {
+        if watch {
+            eprintln!("Error: Can't watch files while printing them to stdout");
+            std::process::exit(1);
+        }
+        write = false;
+        output_dir = None;
+    }
+    let mut missing_includes = false;
+    if follow_includes {
+        let include_paths = find_project()
+            .map(|project| project.include_paths)
+            .unwrap_or_default();
+        let mut followed: Vec<Option<String>> = Vec::new();
+        for file in files {
+            let Some(file) = file else {
+                followed.push(None);
+                continue;
+            };
+            let (found, missing) =
+                flatten::follow_includes(Path::new(&file), &include_paths, &read_source);
+            for message in missing {
+                eprintln!("Error: {}", message);
+                missing_includes = true;
+            }
+            for path in found.into_iter().map(|p| Some(p.display().to_string())) {
+                if !followed.contains(&path) {
+                    followed.push(path);
+                }
+            }
+        }
+        files = followed;
+    }
+
+    let options = FormatOptions {
+        output_dir,
+        range_bytes,
+        print_edits,
+        print_diff,
+        check,
+        encoding,
+        dump_ast,
+        indent_report,
+        listing,
+        report: report_path.is_some(),
+        verbose,
+        write: write || watch,
+    };
+    if watch {
+        if use_stdin {
+            eprintln!("Error: Can't watch stdin");
+            std::process::exit(1);
+        }
+        let paths: Vec<String> = files.into_iter().flatten().collect();
+        watch_files(&paths, &options, &mut configs);
+    }
+
+    let mut reports = Vec::new();
+    let mut outcome = Outcome {
+        errors: missing_includes,
+        ..Outcome::default()
+    };
+    for file in &files {
+        let config = configs.get(file.as_deref());
+        match format_file(file.as_deref(), &options, &config, &mut reports) {
+            Ok(file_outcome) => outcome.add(file_outcome),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(report_path) = report_path {
+        let report = Json::object(vec![("files", Json::Array(reports))]);
+        let mut sink = FileSink::new(Path::new(report_path));
+        write_or_exit(&mut sink, (report.to_string() + "\n").as_bytes(), "report");
+    }
+    let fail_on = fail_on.unwrap_or(match check {
+        true => cli::FailOn::Change,
+        false => cli::FailOn::Never,
+    });
+    if outcome.fails(fail_on) {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use crate::analysis::{self, Segment, Statement};
+use crate::json::Json;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Section,
+    Procedure,
+    Label,
+    Data,
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolKind::Section => write!(f, "section"),
+            SymbolKind::Procedure => write!(f, "procedure"),
+            SymbolKind::Label => write!(f, "label"),
+            SymbolKind::Data => write!(f, "data"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line: usize,
+    pub children: Vec<Symbol>,
+}
+
+impl Symbol {
+    fn new(name: &str, kind: SymbolKind, line: usize) -> Self {
+        Symbol {
+            name: name.to_string(),
+            kind,
+            line,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", Json::from(self.name.as_str())),
+            ("kind", Json::from(self.kind.to_string())),
+            ("line", Json::from(self.line)),
+            (
+                "children",
+                Json::Array(self.children.iter().map(Symbol::to_json).collect()),
+            ),
+        ])
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut Vec<String>) {
+        out.push(format!(
+            "{}{} {} (line {})",
+            "  ".repeat(depth),
+            self.kind,
+            self.name,
+            self.line
+        ));
+        for child in &self.children {
+            child.write_tree(depth + 1, out);
+        }
+    }
+}
+
+// The symbol that labels in `statement` should be nested under
+fn parent<'a>(sections: &'a mut [Symbol], statement: &Statement) -> &'a mut Symbol {
+    let section = sections.last_mut().unwrap();
+    match section.children.last() {
+        Some(last) if last.kind == SymbolKind::Procedure && statement.segment == Segment::Text => {
+            section.children.last_mut().unwrap()
+        }
+        _ => section,
+    }
+}
+
+/// Builds a tree of the file's sections, the procedures and data items in
+/// each, and the loop and branch labels inside each procedure.
+pub fn outline(statements: &[Statement]) -> Vec<Symbol> {
+    let procedures = analysis::procedure_labels(statements);
+    let mut sections: Vec<Symbol> = Vec::new();
+
+    for statement in statements {
+        if let Some(directive @ (".text" | ".data" | ".ktext" | ".kdata")) =
+            statement.mnemonic.as_deref()
+        {
+            sections.push(Symbol::new(directive, SymbolKind::Section, statement.line));
+        } else if sections.is_empty() && !statement.labels.is_empty() {
+            sections.push(Symbol::new(".text", SymbolKind::Section, statement.line));
+        }
+
+        for label in &statement.labels {
+            let symbol = match statement.segment {
+                Segment::Data => Symbol::new(label, SymbolKind::Data, statement.line),
+                Segment::Text if procedures.contains(label) => {
+                    sections.last_mut().unwrap().children.push(Symbol::new(
+                        label,
+                        SymbolKind::Procedure,
+                        statement.line,
+                    ));
+                    continue;
+                }
+                Segment::Text => Symbol::new(label, SymbolKind::Label, statement.line),
+            };
+            parent(&mut sections, statement).children.push(symbol);
+        }
+    }
+
+    sections
+}
+
+pub fn render(symbols: &[Symbol]) -> String {
+    let mut out = Vec::new();
+    for symbol in symbols {
+        symbol.write_tree(0, &mut out);
+    }
+    out.join("\n") + "\n"
+}
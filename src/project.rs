@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+
+use crate::analysis;
+use crate::config::{self, Config, Value};
+use crate::doctor::Dialect;
+use crate::flatten;
+use crate::isa::Isa;
+use crate::lint::{Diagnostic, Severity};
+
+pub static MANIFEST_NAME: &str = "macmips-project.toml";
+
+/// A multi-file program described by a `macmips-project.toml`, so commands
+/// run without a file work on the whole project.
+#[derive(Debug, PartialEq)]
+pub struct Project {
+    pub root: PathBuf,
+    pub entry: PathBuf,
+    // Other files assembled alongside the entry, sharing `.globl` symbols
+    pub sources: Vec<PathBuf>,
+    pub include_paths: Vec<PathBuf>,
+    pub dialect: Option<Dialect>,
+    pub isa: Option<Isa>,
+    pub lint_ignore: Vec<String>,
+    pub deny_warnings: bool,
+}
+
+fn as_str(value: &Value, key: &str) -> Result<String, String> {
+    match value {
+        Value::Str(s) => Ok(s.clone()),
+        _ => Err(format!("`{}` expects a string", key)),
+    }
+}
+
+impl Project {
+    /// Parses a manifest whose relative paths are from `root`.
+    pub fn parse(contents: &str, root: &Path) -> Result<Self, String> {
+        let mut entry: Option<PathBuf> = None;
+        let mut project = Project {
+            root: root.to_path_buf(),
+            entry: PathBuf::new(),
+            sources: Vec::new(),
+            include_paths: Vec::new(),
+            dialect: None,
+            isa: None,
+            lint_ignore: Vec::new(),
+            deny_warnings: false,
+        };
+
+        for (key, value) in config::parse_toml(contents)? {
+            match key.as_str() {
+                "entry" => entry = Some(root.join(as_str(&value, &key)?)),
+                "sources" => {
+                    project.sources = value
+                        .as_str_list(&key)?
+                        .iter()
+                        .map(|p| root.join(p))
+                        .collect()
+                }
+                "include_paths" => {
+                    project.include_paths = value
+                        .as_str_list(&key)?
+                        .iter()
+                        .map(|p| root.join(p))
+                        .collect()
+                }
+                "dialect" => {
+                    project.dialect = Some(
+                        Dialect::parse(&as_str(&value, &key)?)
+                            .ok_or("`dialect` expects one of \"mars\", \"gnu\" or \"generic\"")?,
+                    )
+                }
+                "isa" => {
+                    project.isa = Some(
+                        Isa::parse(&as_str(&value, &key)?)
+                            .ok_or("`isa` expects one of \"mips1\", \"mips32\" or \"mips32r2\"")?,
+                    )
+                }
+                "lint.ignore" => project.lint_ignore = value.as_str_list(&key)?,
+                "lint.deny_warnings" => project.deny_warnings = value.as_bool(&key)?,
+                _ => return Err(format!("unknown manifest key `{}`", key)),
+            }
+        }
+
+        project.entry = entry.ok_or("missing `entry`, the file the program starts in")?;
+        Ok(project)
+    }
+
+    /// The nearest manifest in `dir` or its parents.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(MANIFEST_NAME))
+            .find(|path| path.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        let root = path.parent().unwrap_or(Path::new("."));
+        Project::parse(&contents, root).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The entry file, the other sources, and every file they include.
+    pub fn files<F>(&self, read: &F) -> Result<Vec<PathBuf>, String>
+    where
+        F: Fn(&Path) -> Result<String, String>,
+    {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for file in self.units(read)?.into_iter().flatten() {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    // The files of each separately assembled source, starting with the entry
+    fn units<F>(&self, read: &F) -> Result<Vec<Vec<PathBuf>>, String>
+    where
+        F: Fn(&Path) -> Result<String, String>,
+    {
+        std::iter::once(&self.entry)
+            .chain(&self.sources)
+            .map(|source| flatten::included_files(source, &self.include_paths, read))
+            .collect()
+    }
+
+    /// Checks the symbols the sources share: each `.globl` symbol is defined
+    /// once, each `jal` to another source calls a `.globl` symbol, and exactly
+    /// one source defines `main`.
+    pub fn check_symbols<F>(&self, read: &F) -> Result<Vec<(PathBuf, Diagnostic)>, String>
+    where
+        F: Fn(&Path) -> Result<String, String>,
+    {
+        let mut units = Vec::new();
+        for unit in self.units(read)? {
+            let mut statements = Vec::new();
+            for file in unit {
+                let contents = read(&file)?;
+                statements.extend(
+                    analysis::parse(&contents)
+                        .into_iter()
+                        .map(|s| (file.clone(), s)),
+                );
+            }
+            units.push(statements);
+        }
+
+        let globals: Vec<(usize, &PathBuf, usize, &str)> = units
+            .iter()
+            .enumerate()
+            .flat_map(|(u, unit)| unit.iter().map(move |(file, s)| (u, file, s)))
+            .filter(|(_, _, s)| s.mnemonic.as_deref() == Some(".globl"))
+            .flat_map(|(u, file, s)| {
+                s.operands
+                    .iter()
+                    .map(move |op| (u, file, s.line, op.as_str()))
+            })
+            .collect();
+        let definitions = |name: &str| -> Vec<(usize, &PathBuf, usize)> {
+            units
+                .iter()
+                .enumerate()
+                .flat_map(|(u, unit)| unit.iter().map(move |(file, s)| (u, file, s)))
+                .filter(|(_, _, s)| {
+                    s.labels.iter().any(|l| l == name)
+                        || (s.mnemonic.as_deref() == Some(".extern")
+                            && s.operands
+                                .first()
+                                .and_then(|op| op.split_whitespace().next())
+                                == Some(name))
+                })
+                .map(|(u, file, s)| (u, file, s.line))
+                .collect()
+        };
+        let error = |file: &PathBuf, line, rule, message| {
+            let diagnostic = Diagnostic {
+                line,
+                severity: Severity::Error,
+                rule,
+                message,
+            };
+            (file.clone(), diagnostic)
+        };
+        let mut out = Vec::new();
+
+        let mut checked: Vec<&str> = vec!["main"];
+        for (_, file, line, name) in &globals {
+            let defined = definitions(name);
+            if defined.is_empty() {
+                let message = format!("`.globl {}` but no file defines {}", name, name);
+                out.push(error(file, *line, "globals", message));
+            }
+            if checked.contains(name) {
+                continue;
+            }
+            checked.push(name);
+            let Some((_, first, first_line)) = defined.first() else {
+                continue;
+            };
+            for (_, file, line) in &defined[1..] {
+                let message = format!(
+                    "global {} is also defined at {}:{}",
+                    name,
+                    first.display(),
+                    first_line
+                );
+                out.push(error(file, *line, "globals", message));
+            }
+        }
+
+        for (u, unit) in units.iter().enumerate() {
+            for (file, s) in unit {
+                let (Some("jal"), Some(target)) = (s.mnemonic.as_deref(), s.operands.first())
+                else {
+                    continue;
+                };
+                let defined = definitions(target);
+                if defined.iter().any(|(v, _, _)| *v == u) {
+                    continue;
+                }
+                let message = match defined.first() {
+                    None => format!("`jal {}` calls {}, which no file defines", target, target),
+                    Some((v, _, _)) if globals.iter().any(|g| g.0 == *v && g.3 == target) => {
+                        continue
+                    }
+                    Some((_, other, _)) => format!(
+                        "`jal {}` calls {} in {}, which doesn't declare it `.globl`",
+                        target,
+                        target,
+                        other.display()
+                    ),
+                };
+                out.push(error(file, s.line, "globals", message));
+            }
+        }
+
+        let mains = definitions("main");
+        match mains.split_first() {
+            None => out.push(error(
+                &self.entry,
+                1,
+                "entry-point",
+                String::from("no file defines `main`"),
+            )),
+            Some(((_, first, first_line), rest)) => {
+                for (_, file, line) in rest {
+                    let message = format!(
+                        "`main` is also defined at {}:{}",
+                        first.display(),
+                        first_line
+                    );
+                    out.push(error(file, *line, "entry-point", message));
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Overrides the options the manifest sets for every file.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(isa) = self.isa {
+            config.isa = isa;
+        }
+        config.lint_ignore.extend(self.lint_ignore.iter().cloned());
+    }
+
+    /// Applies the lint policy: drops ignored rules, and promotes warnings to
+    /// errors with `deny_warnings`.
+    pub fn lint_policy(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter(|d| !self.lint_ignore.iter().any(|rule| rule == d.rule))
+            .map(|d| match self.deny_warnings {
+                true => Diagnostic {
+                    severity: Severity::Error,
+                    ..d
+                },
+                false => d,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,245 @@
+use crate::analysis::{self, Segment, Statement};
+use crate::isa;
+
+// Something an instruction reads or writes, which fixes its order against
+// other instructions touching the same thing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resource {
+    Register(u32),
+    Float(u32),
+    HiLo,
+    Memory,
+}
+
+impl Resource {
+    fn name(&self) -> String {
+        match self {
+            Resource::Register(n) => format!("${}", isa::register_name(*n).unwrap_or("?")),
+            Resource::Float(n) => format!("$f{}", n),
+            Resource::HiLo => String::from("hi/lo"),
+            Resource::Memory => String::from("memory"),
+        }
+    }
+}
+
+struct Instruction {
+    line: usize,
+    code: String,
+    // Labels and branches seen before it, so it can't move past either
+    block: usize,
+    reads: Vec<Resource>,
+    writes: Vec<Resource>,
+}
+
+fn resources(operand: &str) -> Vec<Resource> {
+    operand
+        .split(['(', ')', '+', '-'])
+        .map(str::trim)
+        .filter_map(|name| match isa::register(name) {
+            Some(n) => Some(Resource::Register(n)),
+            None => name
+                .strip_prefix("$f")
+                .and_then(|n| n.parse().ok())
+                .map(Resource::Float),
+        })
+        .collect()
+}
+
+fn is_branch(mnemonic: &str) -> bool {
+    (mnemonic.starts_with('b') && mnemonic != "break")
+        || matches!(mnemonic, "j" | "jal" | "jr" | "jalr" | "eret")
+}
+
+// What an instruction reads and writes, assuming any load or store may touch
+// the same memory as any other
+fn accesses(mnemonic: &str, operands: &[String]) -> (Vec<Resource>, Vec<Resource>) {
+    let all = || {
+        operands
+            .iter()
+            .flat_map(|op| resources(op))
+            .collect::<Vec<_>>()
+    };
+    let (mut reads, mut writes) = match (mnemonic, operands.len()) {
+        ("syscall", _) => {
+            let args = [2, 4, 5, 6, 7].map(Resource::Register);
+            (args.to_vec(), vec![Resource::Register(2)])
+        }
+        ("jal", _) => (Vec::new(), vec![Resource::Register(31)]),
+        ("jalr", _) => (all(), vec![Resource::Register(31)]),
+        ("mult" | "multu" | "div" | "divu" | "madd" | "maddu" | "msub" | "msubu", 2) => {
+            (all(), vec![Resource::HiLo])
+        }
+        ("mthi" | "mtlo", _) => (all(), vec![Resource::HiLo]),
+        ("mfhi" | "mflo", _) => (vec![Resource::HiLo], all()),
+        _ if isa::writes_first_operand(mnemonic) && !operands.is_empty() => {
+            let rest = operands[1..].iter().flat_map(|op| resources(op)).collect();
+            (rest, resources(&operands[0]))
+        }
+        _ => (all(), Vec::new()),
+    };
+
+    if mnemonic == "syscall" {
+        reads.push(Resource::Memory);
+        writes.push(Resource::Memory);
+    } else if isa::is_memory(mnemonic) {
+        match isa::writes_first_operand(mnemonic) {
+            true => reads.push(Resource::Memory),
+            false => writes.push(Resource::Memory),
+        }
+    }
+    writes.retain(|r| *r != Resource::Register(0));
+    (reads, writes)
+}
+
+fn instructions(statements: &[Statement], delay_slots: bool) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut block = 0;
+    let mut in_delay_slot = false;
+
+    for statement in statements.iter().filter(|s| s.segment == Segment::Text) {
+        if !statement.labels.is_empty() {
+            block += 1;
+        }
+        let Some(mnemonic) = statement.mnemonic.as_deref() else {
+            continue;
+        };
+        if statement.is_directive() {
+            continue;
+        }
+
+        // A `nop` does nothing to keep in order, though it can fill a delay slot
+        if mnemonic != "nop" {
+            let (reads, writes) = accesses(mnemonic, &statement.operands);
+            instructions.push(Instruction {
+                line: statement.line,
+                code: format!("{} {}", mnemonic, statement.operands.join(", "))
+                    .trim_end()
+                    .to_string(),
+                block,
+                reads,
+                writes,
+            });
+        }
+
+        if in_delay_slot || (is_branch(mnemonic) && !delay_slots) {
+            block += 1;
+            in_delay_slot = false;
+        } else if is_branch(mnemonic) {
+            in_delay_slot = true;
+        }
+    }
+
+    instructions
+}
+
+// Why `later` has to stay after `earlier`, if it does
+fn dependency(earlier: &Instruction, later: &Instruction) -> Option<String> {
+    let shared = |a: &[Resource], b: &[Resource]| a.iter().find(|r| b.contains(r)).copied();
+
+    if let Some(r) = shared(&earlier.writes, &later.reads) {
+        return Some(format!("which it reads {} from", r.name()));
+    }
+    if let Some(r) = shared(&earlier.reads, &later.writes) {
+        return Some(format!("which reads the {} it overwrites", r.name()));
+    }
+    shared(&earlier.writes, &later.writes).map(|r| format!("which also writes {}", r.name()))
+}
+
+// The statements of the procedure labelled `name`, up to the next procedure
+fn procedure<'a>(statements: &'a [Statement], name: &str) -> Option<&'a [Statement]> {
+    let start = statements
+        .iter()
+        .position(|s| s.segment == Segment::Text && s.labels.iter().any(|l| l == name))?;
+    let mut boundaries = analysis::procedure_labels(statements);
+    boundaries.retain(|l| l != name);
+    let end = statements[(start + 1)..]
+        .iter()
+        .position(|s| s.labels.iter().any(|l| boundaries.contains(l)))
+        .map_or(statements.len(), |i| start + 1 + i);
+    Some(&statements[start..end])
+}
+
+/// Checks that `new` runs the same instructions as `old`, reordered only in
+/// ways that keep every read of a register or memory seeing the same write.
+/// Instructions can't move past a label or branch, except into the delay
+/// slot after a branch when `delay_slots` is set. Only the procedure called
+/// `name` is compared, if given.
+pub fn check(
+    old: &str,
+    new: &str,
+    name: Option<&str>,
+    delay_slots: bool,
+) -> Result<Vec<String>, String> {
+    let statements = |contents: &str, which: &str| {
+        let statements = analysis::parse(contents);
+        let statements = match name {
+            Some(name) => procedure(&statements, name).ok_or(format!(
+                "no procedure named {} in the {} version",
+                name, which
+            ))?,
+            None => &statements,
+        };
+        Ok::<_, String>(instructions(statements, delay_slots))
+    };
+    let old = statements(old, "old")?;
+    let new = statements(new, "new")?;
+    let mut problems = Vec::new();
+
+    // Where each old instruction went, pairing repeated instructions in order
+    let mut taken = vec![false; new.len()];
+    let moved: Vec<Option<usize>> = old
+        .iter()
+        .map(|instruction| {
+            let at = (0..new.len()).find(|&i| !taken[i] && new[i].code == instruction.code)?;
+            taken[at] = true;
+            Some(at)
+        })
+        .collect();
+
+    for (instruction, at) in old.iter().zip(&moved) {
+        if at.is_none() {
+            problems.push(format!(
+                "`{}` (line {}) is missing from the new version",
+                instruction.code, instruction.line
+            ));
+        }
+    }
+    for (instruction, taken) in new.iter().zip(&taken) {
+        if !taken {
+            problems.push(format!(
+                "`{}` (line {}) isn't in the old version",
+                instruction.code, instruction.line
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return Ok(problems);
+    }
+    let moved: Vec<usize> = moved.into_iter().flatten().collect();
+
+    for (i, instruction) in old.iter().enumerate() {
+        let to = &new[moved[i]];
+        if to.block != instruction.block {
+            problems.push(format!(
+                "`{}` moved from line {} to line {}, past a label or branch",
+                instruction.code, instruction.line, to.line
+            ));
+        }
+    }
+
+    for (i, earlier) in old.iter().enumerate() {
+        for (j, later) in old.iter().enumerate().skip(i + 1) {
+            if later.block != earlier.block || moved[j] > moved[i] {
+                continue;
+            }
+            if let Some(reason) = dependency(earlier, later) {
+                problems.push(format!(
+                    "`{}` (line {}) now runs before `{}` (line {}), {}",
+                    later.code, new[moved[j]].line, earlier.code, new[moved[i]].line, reason
+                ));
+            }
+        }
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,174 @@
+use crate::analysis::comment_start;
+use crate::diff;
+use crate::json::Json;
+
+fn split_comment(line: &str) -> (&str, &str) {
+    match comment_start(line) {
+        Some(hash) => (&line[..hash], line[(hash + 1)..].trim()),
+        None => (line, ""),
+    }
+}
+
+// Names the kind of change that turned `old` into `new`
+fn classify(old: &str, new: &str) -> &'static str {
+    if old.trim() == new.trim() {
+        return "indentation";
+    }
+
+    let (old_code, old_comment) = split_comment(old);
+    let (new_code, new_comment) = split_comment(new);
+
+    match (
+        old_code.trim() == new_code.trim(),
+        old_comment == new_comment,
+    ) {
+        (true, true) => "comment-alignment",
+        (true, false) => "annotation",
+        (false, true) if squash(old_code) == squash(new_code) => "spacing",
+        _ => "rewrite",
+    }
+}
+
+fn count(rules: &mut Vec<(&'static str, usize)>, rule: &'static str) {
+    match rules.iter_mut().find(|(r, _)| *r == rule) {
+        Some((_, n)) => *n += 1,
+        None => rules.push((rule, 1)),
+    }
+}
+
+fn squash(code: &str) -> String {
+    code.split_whitespace().collect()
+}
+
+fn leading_space(line: &str) -> &str {
+    &line[..(line.len() - line.trim_start().len())]
+}
+
+// `label: code` that became `label:` followed by `code`
+fn is_label_split(old: &str, label: &str, code: Option<&&str>) -> bool {
+    let (old_label, old_code) = match old.trim().split_once(':') {
+        Some(split) => split,
+        None => return false,
+    };
+    let old_code = squash(split_comment(old_code).0);
+    label.trim() == format!("{}:", old_label.trim())
+        && !old_code.is_empty()
+        && matches!(code, Some(code) if squash(split_comment(code).0) == old_code)
+}
+
+// The transformations that turned one line into another
+fn line_transformations(old: &str, new: &str, out: &mut Vec<(&'static str, usize)>) {
+    let (old_code, old_comment) = split_comment(old);
+    let (new_code, new_comment) = split_comment(new);
+    let tight_commas = |code: &str| {
+        code.split(',')
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join(",")
+    };
+
+    if leading_space(old) != leading_space(new) {
+        count(out, "indent");
+    }
+    if old_code.trim() != new_code.trim() {
+        match squash(old_code) == squash(new_code) {
+            true if tight_commas(old_code.trim()) == tight_commas(new_code.trim()) => {
+                count(out, "comma-spacing")
+            }
+            true => count(out, "spacing"),
+            false => count(out, "rewrite"),
+        }
+    } else if old_comment == new_comment && old.trim() != new.trim() {
+        count(out, "comment-align");
+    }
+    if old_comment != new_comment {
+        count(out, "annotation");
+    }
+}
+
+/// Names each transformation that turned `before` into `after`, with how many
+/// lines it applied to.
+pub fn transformations(before: &str, after: &str) -> Vec<(&'static str, usize)> {
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    let mut applied = Vec::new();
+
+    for hunk in diff::hunks(&old, &new) {
+        let old = &old[hunk.old_start..(hunk.old_start + hunk.old_len)];
+        let new = &new[hunk.new_start..(hunk.new_start + hunk.new_len)];
+        let (mut o, mut n) = (0, 0);
+
+        while o < old.len() || n < new.len() {
+            match (old.get(o), new.get(n)) {
+                (Some(ol), Some(nl)) if is_label_split(ol, nl, new.get(n + 1)) => {
+                    count(&mut applied, "label-split");
+                    line_transformations(ol.split_once(':').unwrap().1, new[n + 1], &mut applied);
+                    o += 1;
+                    n += 2;
+                }
+                (Some(ol), _) if ol.trim().is_empty() => {
+                    count(&mut applied, "blank-lines");
+                    o += 1;
+                }
+                (_, Some(nl)) if nl.trim().is_empty() => {
+                    count(&mut applied, "blank-lines");
+                    n += 1;
+                }
+                (Some(ol), Some(nl)) => {
+                    line_transformations(ol, nl, &mut applied);
+                    o += 1;
+                    n += 1;
+                }
+                _ => {
+                    count(&mut applied, "rewrite");
+                    o += 1;
+                    n += 1;
+                }
+            }
+        }
+    }
+
+    applied
+}
+
+/// Whether a transformation changes more than whitespace and layout.
+pub fn is_structural(transformation: &str) -> bool {
+    matches!(transformation, "label-split" | "annotation" | "rewrite")
+}
+
+/// Summarises what formatting changed in one file: how many lines, which
+/// kinds of change and transformations, and the chunks the formatter split it
+/// into.
+pub fn file_report(file: &str, before: &str, after: &str, chunks: &[(&str, usize)]) -> Json {
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    let mut rules = Vec::new();
+    let mut touched = 0;
+
+    for hunk in diff::hunks(&old, &new) {
+        touched += hunk.old_len.max(hunk.new_len);
+        for i in 0..hunk.old_len.max(hunk.new_len) {
+            let rule = match (old.get(hunk.old_start + i), new.get(hunk.new_start + i)) {
+                (Some(o), Some(n)) if i < hunk.old_len && i < hunk.new_len => classify(o, n),
+                (Some(o), _) if i < hunk.old_len && o.trim().is_empty() => "blank-lines",
+                (_, Some(n)) if i < hunk.new_len && n.trim().is_empty() => "blank-lines",
+                _ => "rewrite",
+            };
+            count(&mut rules, rule);
+        }
+    }
+
+    let object = |counts: Vec<(&str, usize)>| {
+        Json::object(counts.into_iter().map(|(k, n)| (k, n.into())).collect())
+    };
+    let transformations = transformations(before, after);
+    let structural = transformations.iter().any(|(t, _)| is_structural(t));
+    Json::object(vec![
+        ("file", file.into()),
+        ("lines_touched", touched.into()),
+        ("rules", object(rules)),
+        ("transformations", object(transformations)),
+        ("structural", structural.into()),
+        ("chunks", object(chunks.to_vec())),
+    ])
+}
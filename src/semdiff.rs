@@ -0,0 +1,120 @@
+use crate::analysis::{self, Statement};
+use crate::diff;
+
+// A label and the statements up to the next one, or the statements before
+// the first label
+struct Block {
+    name: String,
+    line: usize,
+    items: Vec<(usize, String)>,
+}
+
+// Whitespace- and comment-insensitive text of a statement's code
+fn normalise(statement: &Statement) -> Option<String> {
+    let mnemonic = statement.mnemonic.as_ref()?;
+    match statement.operands.is_empty() {
+        true => Some(mnemonic.to_string()),
+        false => Some(format!("{} {}", mnemonic, statement.operands.join(", "))),
+    }
+}
+
+fn blocks(contents: &str) -> Vec<Block> {
+    let mut blocks = vec![Block {
+        name: String::from("(top of file)"),
+        line: 1,
+        items: Vec::new(),
+    }];
+
+    for statement in analysis::parse(contents) {
+        for label in &statement.labels {
+            blocks.push(Block {
+                name: label.clone(),
+                line: statement.line,
+                items: Vec::new(),
+            });
+        }
+        if let Some(code) = normalise(&statement) {
+            blocks
+                .last_mut()
+                .unwrap()
+                .items
+                .push((statement.line, code));
+        }
+    }
+
+    blocks
+}
+
+fn diff_block(old: &Block, new: &Block, out: &mut Vec<String>) {
+    let old_code: Vec<&String> = old.items.iter().map(|(_, code)| code).collect();
+    let new_code: Vec<&String> = new.items.iter().map(|(_, code)| code).collect();
+    let mut changes = Vec::new();
+
+    for hunk in diff::hunks(&old_code, &new_code) {
+        let paired = hunk.old_len.min(hunk.new_len);
+        for i in 0..hunk.old_len.max(hunk.new_len) {
+            let old_item = old.items.get(hunk.old_start + i);
+            let new_item = new.items.get(hunk.new_start + i);
+            changes.push(match (old_item, new_item) {
+                (Some((ol, oc)), Some((nl, nc))) if i < paired => {
+                    format!("  modified line {} -> {}: `{}` -> `{}`", ol, nl, oc, nc)
+                }
+                (Some((ol, oc)), _) if i < hunk.old_len => {
+                    format!("  removed line {}: `{}`", ol, oc)
+                }
+                (_, Some((nl, nc))) => format!("  added line {}: `{}`", nl, nc),
+                _ => continue,
+            });
+        }
+    }
+
+    if !changes.is_empty() {
+        out.push(format!("{}:", new.name));
+        out.extend(changes);
+    }
+}
+
+/// Compares two files by their code alone, describing changed instructions
+/// per label and any added, removed or reordered labels.
+pub fn semantic_diff(old: &str, new: &str) -> Vec<String> {
+    let old_blocks = blocks(old);
+    let new_blocks = blocks(new);
+    let mut out = Vec::new();
+
+    for new_block in &new_blocks {
+        match old_blocks.iter().find(|b| b.name == new_block.name) {
+            Some(old_block) => diff_block(old_block, new_block, &mut out),
+            None => out.push(format!(
+                "added label {} (new line {})",
+                new_block.name, new_block.line
+            )),
+        }
+    }
+
+    for old_block in &old_blocks {
+        if !new_blocks.iter().any(|b| b.name == old_block.name) {
+            out.push(format!(
+                "removed label {} (old line {})",
+                old_block.name, old_block.line
+            ));
+        }
+    }
+
+    let old_order: Vec<&String> = old_blocks
+        .iter()
+        .map(|b| &b.name)
+        .filter(|name| new_blocks.iter().any(|b| &&b.name == name))
+        .collect();
+    let new_order: Vec<&String> = new_blocks
+        .iter()
+        .map(|b| &b.name)
+        .filter(|name| old_order.contains(name))
+        .collect();
+    for hunk in diff::hunks(&old_order, &new_order) {
+        for name in &new_order[hunk.new_start..(hunk.new_start + hunk.new_len)] {
+            out.push(format!("moved label {}", name));
+        }
+    }
+
+    out
+}
@@ -0,0 +1,150 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Somewhere output goes, like a file, stdout or a buffer, so every command
+/// writes through the same path.
+pub trait OutputSink {
+    /// What the output is written to, for error messages.
+    fn name(&self) -> String;
+
+    /// Writes all of `contents`, replacing anything written before for sinks
+    /// that hold a whole file.
+    fn write(&mut self, contents: &[u8]) -> io::Result<()>;
+}
+
+// Windows refuses to write a file another program, like MARS, has open
+// without sharing it
+pub fn is_locked(e: &io::Error) -> bool {
+    cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33))
+}
+
+/// Runs `attempt` until it succeeds, fails with an error `locked` doesn't
+/// count as a lock, or has been retried ten times, `delay` apart.
+pub fn retry_while_locked(
+    mut attempt: impl FnMut() -> io::Result<()>,
+    locked: impl Fn(&io::Error) -> bool,
+    delay: Duration,
+) -> io::Result<()> {
+    let mut retries = 0;
+    loop {
+        match attempt() {
+            Err(e) if locked(&e) && retries < 10 => {
+                retries += 1;
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A file, replaced all at once so it's never left half written, unless it
+/// has other hard links to keep. Symlinks are followed, and the file keeps
+/// its permissions. Writing retries for a moment while another program has
+/// the file locked.
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: &Path) -> Self {
+        FileSink {
+            path: path.to_path_buf(),
+        }
+    }
+
+    fn replace(&self, contents: &[u8]) -> io::Result<()> {
+        // Replace what a symlink points to rather than the link itself
+        let path = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let metadata = fs::metadata(&path).ok();
+        if metadata.as_ref().is_some_and(has_other_links) {
+            // Renaming over it would cut it off from its other names
+            return fs::write(&path, contents);
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+        let written = fs::write(&temp, contents)
+            .and_then(|_| match &metadata {
+                Some(metadata) => copy_attributes(metadata, &temp),
+                None => Ok(()),
+            })
+            .and_then(|_| fs::rename(&temp, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        written
+    }
+}
+
+#[cfg(unix)]
+fn has_other_links(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn has_other_links(_: &fs::Metadata) -> bool {
+    false
+}
+
+// Gives the temporary file the permissions and, where allowed, the owner of
+// the file it replaces
+fn copy_attributes(metadata: &fs::Metadata, temp: &Path) -> io::Result<()> {
+    fs::set_permissions(temp, metadata.permissions())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Only root can give a file away, and anyone else's copy staying
+        // theirs is the best that can be done
+        let _ = std::os::unix::fs::chown(temp, Some(metadata.uid()), Some(metadata.gid()));
+    }
+    Ok(())
+}
+
+impl OutputSink for FileSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&mut self, contents: &[u8]) -> io::Result<()> {
+        retry_while_locked(
+            || self.replace(contents),
+            is_locked,
+            Duration::from_millis(100),
+        )
+    }
+}
+
+/// Any stream, like stdout, a socket or an in-memory `Vec<u8>`.
+pub struct StreamSink<W: Write> {
+    name: String,
+    pub stream: W,
+}
+
+impl<W: Write> StreamSink<W> {
+    pub fn new(name: &str, stream: W) -> Self {
+        StreamSink {
+            name: name.to_string(),
+            stream,
+        }
+    }
+}
+
+impl StreamSink<io::Stdout> {
+    pub fn stdout() -> Self {
+        StreamSink::new("stdout", io::stdout())
+    }
+}
+
+impl<W: Write> OutputSink for StreamSink<W> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn write(&mut self, contents: &[u8]) -> io::Result<()> {
+        self.stream.write_all(contents)?;
+        self.stream.flush()
+    }
+}
@@ -0,0 +1,61 @@
+use crate::cli::{parse, Arg, FailOn, Flag};
+
+static FLAGS: &[Flag] = &[
+    Flag {
+        names: &["-o", "--output"],
+        value: Some("DIR"),
+        help: "",
+    },
+    Flag {
+        names: &["-w", "--write"],
+        value: None,
+        help: "",
+    },
+];
+
+fn args(line: &str) -> Vec<String> {
+    line.split(' ').map(String::from).collect()
+}
+
+#[test]
+fn flags_and_positionals() {
+    let line = args("a.s -o out -w --output=dir -o=x - -- -w b.s");
+    assert_eq!(
+        parse(&line, FLAGS),
+        Ok(vec![
+            Arg::Positional("a.s"),
+            Arg::Flag("--output", Some("out")),
+            Arg::Flag("--write", None),
+            Arg::Flag("--output", Some("dir")),
+            Arg::Flag("--output", Some("x")),
+            Arg::Positional("-"),
+            Arg::Positional("-w"),
+            Arg::Positional("b.s"),
+        ])
+    );
+}
+
+#[test]
+fn invalid_flags() {
+    assert_eq!(
+        parse(&args("--out dir"), FLAGS),
+        Err(String::from(
+            "Unknown option '--out'\n       Valid options are -o, --output, -w, --write"
+        ))
+    );
+    assert_eq!(
+        parse(&args("a.s -o"), FLAGS),
+        Err(String::from("Option '-o' expects a value, e.g. -o <DIR>"))
+    );
+    assert_eq!(
+        parse(&args("--write=yes"), FLAGS),
+        Err(String::from("Option '--write' doesn't take a value"))
+    );
+}
+
+#[test]
+fn fail_on() {
+    assert_eq!(FailOn::parse("warning"), Some(FailOn::Warning));
+    assert_eq!(FailOn::parse("never"), Some(FailOn::Never));
+    assert_eq!(FailOn::parse("always"), None);
+}
@@ -0,0 +1,193 @@
+use std::fs;
+
+use crate::config::{self, config_path, discover, parse_toml, Config, Value};
+use crate::formatter;
+
+fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn toml_values() {
+    let input = "# comment\na = 1\nb = \"x # y\"  # trailing\n[t]\nc = [true, 'z']\n";
+    assert_eq!(
+        parse_toml(input),
+        Ok(vec![
+            (String::from("a"), Value::Int(1)),
+            (String::from("b"), Value::Str(String::from("x # y"))),
+            (
+                String::from("t.c"),
+                Value::Array(vec![Value::Bool(true), Value::Str(String::from("z"))])
+            ),
+        ])
+    );
+    assert!(parse_toml("a 1").is_err());
+    assert!(parse_toml("a = \"open").is_err());
+}
+
+#[test]
+fn options_from_env() {
+    let mut config = Config::default();
+    let vars = env(&[
+        ("MACMIPS_COMMENT_GAP", "4"),
+        ("MACMIPS_CONFIG", "ignored.toml"),
+        ("PATH", "/bin"),
+    ]);
+    assert_eq!(config.apply_env(vars), Ok(vec![]));
+    assert_eq!(config.comment_gap, 4);
+
+    let bad = env(&[("MACMIPS_COMMENT_GAP", "wide")]);
+    assert!(config.apply_env(bad).is_err());
+}
+
+#[test]
+fn comment_gap_option() {
+    let mut config = Config::default();
+    config.apply_toml("comment_gap = 1").unwrap();
+
+    let input = "li $v0, 1 # a\nsyscall # b";
+    let expected = "li $v0, 1 # a\nsyscall   # b\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn config_version() {
+    let mut config = Config::default();
+    assert_eq!(config.apply_toml("config_version = 1"), Ok(vec![]));
+    assert!(config.apply_toml("config_version = 999").is_err());
+}
+
+#[test]
+fn renamed_options() {
+    let input = "# style\nblank_lines_around_globl = 2  # roomy\n";
+    let expected = "config_version = 2\n# style\n# renamed from `blank_lines_around_globl` in config_version 2\nblank_lines_around_globals = 2  # roomy\n";
+    let migrated = config::migrate(input);
+    assert_eq!(migrated, expected);
+    assert_eq!(config::migrate(&migrated), expected);
+
+    let mut config = Config::default();
+    assert_eq!(
+        config.apply_toml(input),
+        Ok(vec![String::from(
+            "`blank_lines_around_globl` has been renamed to `blank_lines_around_globals`, run 'mac-mips config migrate' to update"
+        )])
+    );
+    assert_eq!(config.blank_lines_around_globals, 2);
+
+    let mut config = Config::default();
+    let vars = env(&[("MACMIPS_BLANK_LINES_AROUND_GLOBL", "3")]);
+    assert_eq!(
+        config.apply_env(vars),
+        Ok(vec![String::from(
+            "environment: `MACMIPS_BLANK_LINES_AROUND_GLOBL` has been renamed to `MACMIPS_BLANK_LINES_AROUND_GLOBALS`"
+        )])
+    );
+    assert_eq!(config.blank_lines_around_globals, 3);
+}
+
+#[test]
+fn unknown_options_warn() {
+    let mut config = Config::default();
+    assert_eq!(
+        config.apply_toml("coment_gap = 3\nindent_everything = true"),
+        Ok(vec![
+            String::from("unknown option `coment_gap`, did you mean `comment_gap`?"),
+            String::from("unknown option `indent_everything`"),
+        ])
+    );
+    assert_eq!(config, Config::default());
+}
+
+#[test]
+fn comment_columns_across_sources() {
+    let mut config = Config::default();
+    config.apply_toml("comment_column = 40").unwrap();
+    assert_eq!(config.check_comment_columns(), Ok(()));
+    config
+        .apply_env(env(&[("MACMIPS_COMMENT_COLUMN_MAX", "60")]))
+        .unwrap();
+    assert_eq!(
+        config.check_comment_columns(),
+        Err(String::from("`comment_column` and `comment_column_max` can't be used together, a fixed comment column is already the furthest comments go"))
+    );
+
+    config.apply_toml("comment_column = 0").unwrap();
+    assert_eq!(config.check_comment_columns(), Ok(()));
+}
+
+#[test]
+fn options_round_trip() {
+    let defaults = Config::default();
+    for option in config::OPTIONS {
+        let value = defaults
+            .get(option)
+            .unwrap_or_else(|| panic!("`{}` has no value", option));
+        let mut config = Config::default();
+        if let Err(e) = config.set(option, &value) {
+            panic!("`{}` can't be set to its own default: {}", option, e);
+        }
+        assert_eq!(config, defaults, "`{}` changed setting its default", option);
+    }
+    assert!(Config::default()
+        .set("not_an_option", &Value::Int(1))
+        .is_err());
+    assert_eq!(defaults.get("not_an_option"), None);
+}
+
+#[test]
+fn size_limits() {
+    let config = Config {
+        max_file_size: 20,
+        max_line_length: 8,
+        max_lines: 2,
+        ..Config::default()
+    };
+    assert_eq!(config.exceeded_limit("main:\nnop\n"), None);
+    assert_eq!(
+        config.exceeded_limit("main:\nli $v0, 10\n"),
+        Some(String::from(
+            "line 2 is 10 characters, over `max_line_length` (8)"
+        ))
+    );
+    assert_eq!(
+        config.exceeded_limit("a:\nb:\nc:\n"),
+        Some(String::from("3 lines is over `max_lines` (2)"))
+    );
+    assert_eq!(
+        config.exceeded_limit("# a long comment line here\n"),
+        Some(String::from("27 bytes is over `max_file_size` (20)"))
+    );
+
+    let unlimited = Config {
+        max_line_length: 0,
+        ..Config::default()
+    };
+    assert_eq!(unlimited.exceeded_limit(&"a".repeat(5000)), None);
+}
+
+#[test]
+fn discover_config_file() {
+    let dir = std::env::temp_dir().join(format!("macmips-discover-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    assert_eq!(discover(&dir), None);
+
+    fs::write(dir.join(".macmips.toml"), "comment_gap = 3\n").unwrap();
+    assert_eq!(discover(&dir), Some(dir.join(".macmips.toml")));
+    fs::write(dir.join("macmips.toml"), "comment_gap = 4\n").unwrap();
+    assert_eq!(discover(&dir), Some(dir.join("macmips.toml")));
+
+    let nested = dir.join("a").join("b");
+    fs::create_dir_all(&nested).unwrap();
+    assert_eq!(config_path(None, &nested), Some(dir.join("macmips.toml")));
+    assert_eq!(
+        config_path(Some(&dir.join("other.toml")), &nested),
+        Some(dir.join("other.toml"))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
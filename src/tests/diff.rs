@@ -0,0 +1,109 @@
+use crate::diff::{hunks, text_edits, unified, Hunk, TextEdit};
+
+#[test]
+fn line_hunks() {
+    let old = ["a", "b", "c", "d"];
+    let new = ["a", "x", "c", "d", "e"];
+    assert_eq!(
+        hunks(&old, &new),
+        vec![
+            Hunk {
+                old_start: 1,
+                old_len: 1,
+                new_start: 1,
+                new_len: 1
+            },
+            Hunk {
+                old_start: 4,
+                old_len: 0,
+                new_start: 4,
+                new_len: 1
+            },
+        ]
+    );
+    assert_eq!(hunks(&old, &old), vec![]);
+}
+
+#[test]
+fn byte_edits() {
+    let old = "main:\nli $v0,1\nsyscall";
+    let new = "main:\n\tli $v0, 1\n\tsyscall\n";
+    let edits = text_edits(old, new);
+    assert_eq!(
+        edits,
+        vec![TextEdit {
+            start: 6,
+            end: 22,
+            text: String::from("\tli $v0, 1\n\tsyscall\n")
+        }]
+    );
+    assert!(edits[0].overlaps(8, 9));
+    assert!(!edits[0].overlaps(0, 5));
+}
+
+#[test]
+fn minimal_edits() {
+    let old = "main:\nli $v0,1\n\n\nsyscall\n";
+    let new = "main:\n\tli $v0, 1\n\n\tsyscall\n";
+    assert_eq!(
+        text_edits(old, new),
+        vec![
+            TextEdit {
+                start: 6,
+                end: 16,
+                text: String::from("\tli $v0, 1\n")
+            },
+            TextEdit {
+                start: 17,
+                end: 25,
+                text: String::from("\tsyscall\n")
+            },
+        ]
+    );
+    assert_eq!(text_edits(new, new), vec![]);
+}
+
+#[test]
+fn unified_diff() {
+    let old = "1\n2\n3\n4\n5\n6\n7\n8";
+    let new = "1\nx\n3\n4\n5\n6\n7\n8\n9\n";
+    let expected = "--- a.s\n+++ a.s\n@@ -1,3 +1,3 @@\n 1\n-2\n+x\n 3\n@@ -8,1 +8,2 @@\n 8\n+9\n";
+    assert_eq!(unified(old, new, "a.s", "a.s", 1), expected);
+    assert_eq!(
+        unified(old, new, "a.s", "a.s", 3).matches("@@ -").count(),
+        1
+    );
+    assert_eq!(unified(old, old, "a.s", "a.s", 3), "");
+}
+
+#[test]
+fn longest_common_lines() {
+    // Every pair of short strings over two letters, checked against the
+    // length of their longest common subsequence found the slow way
+    let strings: Vec<Vec<u8>> = (0..64u32)
+        .flat_map(|bits| {
+            (0..=5).map(move |len| (0..len).map(|i| b'a' + (bits >> i & 1) as u8).collect())
+        })
+        .collect();
+    for old in &strings {
+        for new in &strings {
+            let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for i in 0..old.len() {
+                for j in 0..new.len() {
+                    lcs[i + 1][j + 1] = match old[i] == new[j] {
+                        true => lcs[i][j] + 1,
+                        false => lcs[i][j + 1].max(lcs[i + 1][j]),
+                    };
+                }
+            }
+            let removed: usize = hunks(old, new).iter().map(|h| h.old_len).sum();
+            assert_eq!(
+                old.len() - removed,
+                lcs[old.len()][new.len()],
+                "{:?} {:?}",
+                old,
+                new
+            );
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::analysis::parse;
+use crate::doctor::{detect_dialect, Dialect};
+
+#[test]
+fn dialects() {
+    assert_eq!(
+        detect_dialect(&parse("main:\nli $v0, 10\nsyscall\n")),
+        (Dialect::Generic, vec![])
+    );
+    assert_eq!(
+        detect_dialect(&parse(".eqv N 4\n.macro done\n.end_macro\n")),
+        (
+            Dialect::Mars,
+            vec![
+                String::from("`.eqv` on line 1"),
+                String::from("`.macro` on line 2"),
+                String::from("`.end_macro` on line 3"),
+            ]
+        )
+    );
+    assert_eq!(
+        detect_dialect(&parse(".set noreorder\nlui $t0, %hi(msg)\n")).0,
+        Dialect::Gnu
+    );
+}
@@ -0,0 +1,25 @@
+use crate::analysis::parse;
+use crate::duplicates::{find_duplicates, Duplicate, Occurrence};
+
+#[test]
+fn duplicates_across_procedures() {
+    let input = "main:\njal a\njal b\nli $v0, 10\nsyscall\na:\nli $t0, 1\nadd $t1, $t0, $t0 # double\nsw $t1, 0($sp)\njr $ra\nb:\nli $s0, 1\n\nadd $s1, $s0, $s0\nsw $s1, 0($sp)\njr $ra\n";
+    let duplicates = find_duplicates(&parse(input), 3);
+    assert_eq!(
+        duplicates,
+        vec![Duplicate {
+            len: 4,
+            first: Occurrence {
+                procedure: String::from("a"),
+                first_line: 7,
+                last_line: 10,
+            },
+            second: Occurrence {
+                procedure: String::from("b"),
+                first_line: 12,
+                last_line: 16,
+            },
+        }]
+    );
+    assert!(find_duplicates(&parse(input), 5).is_empty());
+}
@@ -0,0 +1,35 @@
+use crate::config::Config;
+use crate::emit::{procedure, ProcedureOptions};
+
+#[test]
+fn procedure_frames() {
+    let options = ProcedureOptions {
+        saved: vec![String::from("$s0")],
+        save_ra: true,
+        locals: 4,
+    };
+    let expected = "twice:\n\taddi $sp, $sp, -12\n\tsw $ra, 8($sp)\n\tsw $s0, 4($sp)\n\tmove $s0, $a0\n\tjal helper\n\tlw $ra, 8($sp)\n\tlw $s0, 4($sp)\n\taddi $sp, $sp, 12\n\tjr $ra\n";
+    assert_eq!(
+        procedure(
+            "twice",
+            &["move $s0,$a0", "jal helper"],
+            &options,
+            &Config::default()
+        ),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn leaf_procedures() {
+    let expected = "id:\n\tmove $v0, $a0\n\tjr $ra\n";
+    assert_eq!(
+        procedure(
+            "id",
+            &["move $v0, $a0"],
+            &ProcedureOptions::default(),
+            &Config::default()
+        ),
+        Ok(String::from(expected))
+    );
+}
@@ -0,0 +1,42 @@
+use crate::encoding::{line_endings, to_crlf, Encoding};
+
+#[test]
+fn encodings() {
+    assert_eq!(Encoding::parse("UTF-8-BOM"), Some(Encoding::Utf8Bom));
+    assert_eq!(Encoding::parse("ascii"), None);
+
+    let text = "\u{feff}li $v0, 1  # café\n";
+    assert_eq!(
+        Encoding::Utf8.encode(text),
+        Ok(b"li $v0, 1  # caf\xc3\xa9\n".to_vec())
+    );
+    assert_eq!(
+        Encoding::Utf8Bom.encode(text),
+        Ok(b"\xef\xbb\xbfli $v0, 1  # caf\xc3\xa9\n".to_vec())
+    );
+    assert_eq!(
+        Encoding::Latin1.encode(text),
+        Ok(b"li $v0, 1  # caf\xe9\n".to_vec())
+    );
+    assert_eq!(
+        Encoding::Latin1.encode("syscall\nli $a0, 1  # → done"),
+        Err(vec![String::from(
+            "line 2: `→` (U+2192) can't be encoded in Latin-1"
+        )])
+    );
+    assert_eq!(
+        Encoding::Latin1.decode(b"# caf\xe9".to_vec()),
+        Ok(String::from("# café"))
+    );
+    assert_eq!(
+        Encoding::Utf8.decode(b"# caf\xe9".to_vec()),
+        Err(String::from("invalid UTF-8 at byte 5"))
+    );
+}
+
+#[test]
+fn mixed_line_endings() {
+    assert_eq!(line_endings(".text\r\nmain:\r\nsyscall\n"), (1, 2));
+    assert_eq!(line_endings("no newline"), (0, 0));
+    assert_eq!(to_crlf(".text\n\nmain:\n"), ".text\r\n\r\nmain:\r\n");
+}
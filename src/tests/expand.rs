@@ -0,0 +1,15 @@
+use crate::expand::expand;
+
+#[test]
+fn eqv_substitution() {
+    let input = ".eqv SIZE 40\nli $a0, SIZE  # SIZE bytes\nla $a1, \"SIZE\"\nli $a2, SIZE_2";
+    let expected = ".eqv SIZE 40\nli $a0, 40  # SIZE bytes\nla $a1, \"SIZE\"\nli $a2, SIZE_2\n";
+    assert_eq!(expand(input), expected);
+}
+
+#[test]
+fn macro_invocations() {
+    let input = ".eqv EXIT 10\n.macro done\nli $v0, EXIT\nsyscall\n.end_macro\n.macro print (%x)\nloop: move $a0, %x\nb loop\n.end_macro\nmain:\n\tprint($t0)\n\tdone";
+    let expected = ".eqv EXIT 10\n.macro done\nli $v0, EXIT\nsyscall\n.end_macro\n.macro print (%x)\nloop: move $a0, %x\nb loop\n.end_macro\nmain:\n\t# >>> print(%x = $t0)\nloop_M1: move $a0, $t0\nb loop_M1\n\t# <<< print\n\t# >>> done()\nli $v0, 10\nsyscall\n\t# <<< done\n";
+    assert_eq!(expand(input), expected);
+}
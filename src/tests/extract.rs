@@ -0,0 +1,9 @@
+use crate::extract::extract;
+
+#[test]
+fn extract_procedure() {
+    let input = ".data\nshared: .word 1\nmsg: .asciiz \"hi\"\nbuf: .space 4\n.text\nmain:\nlw $t0, shared\njal greet\nli $v0, 10\nsyscall\n\n# Prints msg\ngreet:\nla $a0, msg\nlw $t0, shared\nloop:\nla $t1, buf\nb loop\njr $ra\n\n# Next procedure\nhelper:\njr $ra\n.globl helper";
+    let expected = ".data\nmsg: .asciiz \"hi\"\nbuf: .space 4\n.text\n# Prints msg\ngreet:\nla $a0, msg\nlw $t0, shared\nloop:\nla $t1, buf\nb loop\njr $ra\n";
+    assert_eq!(extract(input, "greet"), Some(String::from(expected)));
+    assert_eq!(extract(input, "missing"), None);
+}
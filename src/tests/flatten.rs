@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use crate::flatten::{flatten, follow_includes};
+
+fn read(path: &Path) -> Result<String, String> {
+    match path.to_str() {
+        Some("main.s") => Ok(String::from(
+            ".globl main\n.include \"lib/print.s\"\nmain:\njal print\n",
+        )),
+        Some("lib/print.s") => Ok(String::from(
+            ".globl main, print\nprint:\n.include \"util.s\"\njr $ra\n",
+        )),
+        Some("lib/util.s") => Ok(String::from("# util\n")),
+        Some("cycle.s") => Ok(String::from(".include \"cycle.s\"\n")),
+        _ => Err(format!("{}: not found", path.display())),
+    }
+}
+
+#[test]
+fn flatten_includes() {
+    let expected = ".globl main\n# >>> include \"lib/print.s\"\n.globl print\nprint:\n# >>> include \"util.s\"\n# util\n# <<< include \"util.s\"\njr $ra\n# <<< include \"lib/print.s\"\nmain:\njal print\n";
+    assert_eq!(
+        flatten(Path::new("main.s"), &[], &read),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn flatten_errors() {
+    assert_eq!(
+        flatten(Path::new("cycle.s"), &[], &read),
+        Err(String::from("include cycle: cycle.s -> cycle.s"))
+    );
+    assert_eq!(
+        flatten(Path::new("missing.s"), &[], &read),
+        Err(String::from("missing.s: not found"))
+    );
+}
+
+#[test]
+fn follow_missing_includes() {
+    let read = |path: &Path| match path.to_str() {
+        Some("main.s") => Ok(String::from(
+            ".include \"lib/print.s\"\n.include \"gone.s\"\n",
+        )),
+        _ => read(path),
+    };
+    let (files, missing) = follow_includes(Path::new("main.s"), &[], &read);
+    assert_eq!(
+        files,
+        vec![
+            Path::new("main.s"),
+            Path::new("lib/print.s"),
+            Path::new("lib/util.s")
+        ]
+    );
+    assert_eq!(
+        missing,
+        vec![String::from(
+            "main.s:2: couldn't find included file \"gone.s\""
+        )]
+    );
+}
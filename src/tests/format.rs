@@ -1,4 +1,9 @@
+use crate::config::{
+    AddressLiterals, BlankComments, BlankLineRuns, CommentOverflow, Config, DataListContinuation,
+    GloblStyle, HexCase, IndentStyle, LabelCase, OperatorSpacing, VersionHeader,
+};
 use crate::formatter;
+use crate::json::Json;
 
 #[test]
 fn empty_file() {
@@ -232,3 +237,820 @@ fn directive_modifiers() {
         Ok(String::from(expected2))
     );
 }
+
+#[test]
+fn unknown_sections_verbatim() {
+    let input = ".text\nmain:\nli $v0,1\n.sdata\n  small:   .word 1 #  x\n\n.text\nfoo:\nnop\n.section .rodata, \"a\"\n\tmsg:  .asciiz \"hi\"";
+    let expected = ".text\n\nmain:\n\tli $v0, 1\n\n.sdata\n  small:   .word 1 #  x\n\n.text\n\nfoo:\n\tnop\n\n.section .rodata, \"a\"\n\tmsg:  .asciiz \"hi\"\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+    assert_eq!(
+        formatter::verbatim_sections(input),
+        vec![
+            (4, String::from(".sdata")),
+            (10, String::from(".section .rodata, \"a\""))
+        ]
+    );
+}
+
+fn chunk_ids(ast: &Json) -> Vec<String> {
+    let mut ids = Vec::new();
+    if let Json::Object(fields) = ast {
+        if let Json::Array(sections) = &fields[0].1 {
+            for section in sections {
+                if let Json::Object(fields) = section {
+                    if let Json::Array(chunks) = &fields[1].1 {
+                        for chunk in chunks {
+                            if let Json::Object(fields) = chunk {
+                                ids.push(fields[0].1.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    ids
+}
+
+#[test]
+fn stable_chunk_ids() {
+    let config = Config::default();
+    let messy = chunk_ids(&formatter::dump_ast(
+        "main:\nli $v0 ,1\n\nfoo:\nsyscall",
+        &config,
+    ));
+    let tidy = chunk_ids(&formatter::dump_ast(
+        "main:\n\tli $v0, 1\n\nfoo:\n\tsyscall\n",
+        &config,
+    ));
+    assert_eq!(messy.len(), 4);
+    assert_eq!(messy, tidy);
+
+    let edited = chunk_ids(&formatter::dump_ast(
+        "main:\nli $v0, 4\n\nfoo:\nsyscall",
+        &config,
+    ));
+    assert_ne!(messy[1], edited[1]);
+    assert_eq!(messy[2..], edited[2..]);
+}
+
+#[test]
+fn syscall_annotations() {
+    let config = Config {
+        annotate_syscalls: true,
+        ..Config::default()
+    };
+
+    let input = "main:\nli $v0, 4\nla $a0, msg\nsyscall\nli $v0, 1\nsyscall  # print_int(old)\nli $v0, 10\nsyscall  # bye\nfoo:\nsyscall";
+    let expected = "main:\n\tli $v0, 4\n\tla $a0, msg\n\tsyscall      # print_string(msg)\n\tli $v0, 1\n\tsyscall      # print_int(msg)\n\tli $v0, 10\n\tsyscall      # bye\n\nfoo:\n\tsyscall\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn syscall_annotations_reset() {
+    let config = Config {
+        annotate_syscalls: true,
+        ..Config::default()
+    };
+
+    let input = "main:\nli $v0, 5\nsyscall\nsyscall\nli $v0, 1\nli $a0, 2\nloop:\nsyscall\nli $v0, 11\njal f\nsyscall";
+    let expected = "main:\n\tli $v0, 5\n\tsyscall    # read_int()\n\tsyscall\n\tli $v0, 1\n\tli $a0, 2\n\nloop:\n\tsyscall\n\tli $v0, 11\n\tjal f\n\tsyscall\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn offset_annotations() {
+    let config = Config {
+        annotate_offsets: true,
+        ..Config::default()
+    };
+
+    let input = ".eqv W 4\n.data\narr: .word 1, 2, 3, 4\n.text\nmain:\nlw $t0, arr+8\nsw $t0, arr + W*3($t1)  # arr[0] (byte offset 0)\nlb $t0, arr+2  # low byte\nlb $t0, arr-2";
+    let expected = ".eqv W 4\n\n.data\n\narr: .word 1, 2, 3, 4\n\n.text\n\nmain:\n\tlw $t0, arr+8           # arr[2] (byte offset 8)\n\tsw $t0, arr + W*3($t1)  # arr[3] (byte offset 12)\n\tlb $t0, arr+2           # low byte\n\tlb $t0, arr-2           # byte offset -2\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn data_offset_annotations() {
+    let config = Config {
+        annotate_data_offsets: true,
+        ..Config::default()
+    };
+
+    let input = ".data\nmsg: .asciiz \"hi\\n\"\narr: .word 1,2,3  # nums\nbuf: .space 10\nh: .half 1\nd: .double 1.5";
+    let expected = ".data\n\nmsg: .asciiz \"hi\\n\"  # offset 0\narr: .word 1, 2, 3   # nums\nbuf: .space 10       # offset 16\nh: .half 1           # offset 26\nd: .double 1.5       # offset 32\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn procedure_comment_boundaries() {
+    let config = Config {
+        procedure_comments: vec![String::from("---- function * ----")],
+        ..Config::default()
+    };
+
+    let input = "# ---- function main ----\n# entry point\nli $v0, 1\nsyscall\n# ---- function exit ----\nli $v0, 10\n# not a boundary\nsyscall";
+    let expected = "# ---- function main ----\n\t# entry point\n\tli $v0, 1\n\tsyscall\n\n# ---- function exit ----\n\tli $v0, 10\n\t# not a boundary\n\tsyscall\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn constant_tables() {
+    let input = ".eqv SIZE 40  # bytes\n.eqv N 4 # count\n.eqv LONG_NAME 4*3\n# W = 4\n# HEIGHT = 10\n.text\nmain:\nli $a0, SIZE";
+    let expected = ".eqv SIZE      40   # bytes\n.eqv N         4    # count\n.eqv LONG_NAME 4*3\n\n# W      = 4\n# HEIGHT = 10\n\n.text\n\nmain:\n\tli $a0, SIZE\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+    assert_eq!(
+        formatter::format(String::from(expected)),
+        Ok(String::from(expected))
+    );
+
+    let config = Config {
+        align_constants: false,
+        ..Config::default()
+    };
+    assert_eq!(
+        formatter::format_with_config(String::from(".eqv SIZE 40\n.eqv N 4\n"), &config),
+        Ok(String::from(".eqv SIZE 40\n.eqv N 4\n"))
+    );
+}
+
+#[test]
+fn comment_column_overflow() {
+    let input = "main:\nli $v0, 1  # one\nla $a0, a_really_long_label_name_here  # long one that goes on\nsyscall # go";
+    let cases = [
+        (
+            CommentOverflow::Inline,
+            "main:\n\tli $v0, 1  # one\n\tla $a0, a_really_long_label_name_here  # long one that goes on\n\tsyscall    # go\n",
+        ),
+        (
+            CommentOverflow::Above,
+            "main:\n\tli $v0, 1  # one\n\t# long one that goes on\n\tla $a0, a_really_long_label_name_here\n\tsyscall    # go\n",
+        ),
+        (
+            CommentOverflow::Wrap,
+            "main:\n\tli $v0, 1  # one\n\t# long one\n\t# that goes\n\t# on\n\tla $a0, a_really_long_label_name_here\n\tsyscall    # go\n",
+        ),
+    ];
+
+    for (comment_overflow, expected) in cases {
+        let config = Config {
+            comment_column_max: 20,
+            comment_overflow,
+            ..Config::default()
+        };
+        let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            formatter::format_with_config(formatted.clone(), &config),
+            Ok(formatted)
+        );
+    }
+}
+
+#[test]
+fn space_indentation() {
+    let config = Config {
+        indent: IndentStyle::Spaces(4),
+        ..Config::default()
+    };
+
+    let input = "main:\n\tli $v0, 10  # exit\n  syscall";
+    let expected = "main:\n    li $v0, 10  # exit\n    syscall\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn fixed_comment_column() {
+    let config = Config {
+        comment_column: 32,
+        ..Config::default()
+    };
+
+    let input = ".data\nx: .word 1 # data\n.text\nmain:\nli $v0, 1  # one\nla $a0, a_really_long_label_name_here_and_more  # too long\nsyscall # go";
+    let expected = ".data\n\nx: .word 1                      # data\n\n.text\n\nmain:\n\tli $v0, 1               # one\n\tla $a0, a_really_long_label_name_here_and_more  # too long\n\tsyscall                 # go\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn long_trailing_comments_move_above() {
+    let config = Config {
+        trailing_comment_max: 12,
+        ..Config::default()
+    };
+
+    let input = ".data\nx: .word 1  # a data word that is long\n.text\nmain:\nli $v0, 1  # one\nla $a0, msg  # the message to print\nsyscall # go";
+    let expected = ".data\n\n# a data word that is long\nx: .word 1\n\n.text\n\nmain:\n\tli $v0, 1    # one\n\t# the message to print\n\tla $a0, msg\n\tsyscall      # go\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn blank_comment_lines() {
+    let input = "# Title\n#\n#  \n# body\n#\nmain:\nli $v0, 1";
+    let cases = [
+        (
+            BlankComments::Keep,
+            "# Title\n#\n#\n# body\n#\nmain:\n\tli $v0, 1\n",
+        ),
+        (
+            BlankComments::Collapse,
+            "# Title\n#\n# body\n#\nmain:\n\tli $v0, 1\n",
+        ),
+        (
+            BlankComments::Blank,
+            "# Title\n\n# body\n\nmain:\n\tli $v0, 1\n",
+        ),
+    ];
+
+    for (blank_comments, expected) in cases {
+        let config = Config {
+            blank_comments,
+            ..Config::default()
+        };
+        let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            formatter::format_with_config(formatted.clone(), &config),
+            Ok(formatted)
+        );
+    }
+}
+
+#[test]
+fn comment_disparity_threshold() {
+    let input = "main:\nli $v0, 1  # one\nla $a0, label";
+    let pushed = "main:\n\tli $v0, 1      # one\n\tla $a0, label\n";
+    let kept = "main:\n\tli $v0, 1  # one\n\tla $a0, label\n";
+
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(pushed))
+    );
+    let config = Config {
+        comment_disparity: 4,
+        ..Config::default()
+    };
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(kept))
+    );
+}
+
+#[test]
+fn blank_line_policy() {
+    let input = ".text\n.globl main\nmain:\nli $t0, 1\n\n\n\nli $t1, 2\nend:\nnop\n\n\n";
+    let config = Config {
+        blank_line_runs: BlankLineRuns::Preserve,
+        blank_lines_after_directive: 0,
+        blank_lines_around_globals: 2,
+        blank_lines_between_chunks: 2,
+        ..Config::default()
+    };
+    let expected =
+        ".text\n.globl main\n\n\nmain:\n\tli $t0, 1\n\n\n\n\tli $t1, 2\n\n\nend:\n\tnop\n";
+
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn protected_region() {
+    let config = Config {
+        protected_region: vec![String::from("#! begin"), String::from("#! end")],
+        ..Config::default()
+    };
+    let input = "#! begin\n# ID:   12345  \n#! end\n\n.text\nmain:\nli $t0 ,1";
+    let expected = "#! begin\n# ID:   12345  \n#! end\n\n.text\n\nmain:\n\tli $t0, 1\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+
+    assert!(formatter::protected_regions(input, &config).is_ok());
+    assert!(formatter::protected_regions("#! begin\nli $t0, 1", &config).is_err());
+    assert!(formatter::protected_regions("li $t0, 1", &config).is_err());
+}
+
+#[test]
+fn max_width_wraps() {
+    let config = Config {
+        max_width: 40,
+        comment_overflow: CommentOverflow::Above,
+        ..Config::default()
+    };
+    let input = ".data\nprimes: .word 2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37 # primes\n";
+    let expected =
+        ".data\n\n# primes\nprimes: .word 2, 3, 5, 7, 11, 13, 17, 19\n.word 23, 29, 31, 37\n";
+
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn sort_data() {
+    let config = Config {
+        sort_data: true,
+        ..Config::default()
+    };
+    let input = ".data\nzeta: .word 1\n.align 2\nalpha:\n.word 2, 3\nBeta: .asciiz \"b\"\n\nc: .byte 1\nb: .byte 2\n";
+    let expected = ".data\n\n.align 2\nalpha:\n.word 2, 3\nBeta: .asciiz \"b\"\nzeta: .word 1\n\nb: .byte 2\nc: .byte 1\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn section_addresses() {
+    let input = "  .data   0X1001aBcD\nx: .word 1\n.text   0x00400000\nmain:\nli $t0, 1";
+    let cases = [
+        (HexCase::Preserve, ".data 0X1001aBcD"),
+        (HexCase::Lower, ".data 0x1001abcd"),
+        (HexCase::Upper, ".data 0x1001ABCD"),
+    ];
+
+    for (hex_case, data) in cases {
+        let config = Config {
+            hex_case,
+            ..Config::default()
+        };
+        let expected = format!(
+            "{}\n\nx: .word 1\n\n.text 0x00400000\n\nmain:\n\tli $t0, 1\n",
+            data
+        );
+        assert_eq!(
+            formatter::format_with_config(String::from(input), &config),
+            Ok(expected)
+        );
+    }
+}
+
+#[test]
+fn numeric_literals() {
+    let input = ".data 268500992\nx: .word 0XFF, -0XaB, 0B101, 10\ns: .asciiz \"0XFF\"\n.text\nmain: la $a0, 268500992\nlw $t0, 0X1001000A\nlw $t1, 4($sp)\n";
+    let cases = [
+        (
+            HexCase::Lower,
+            AddressLiterals::Hex,
+            ".data 0x10010000\n\nx: .word 0xff, -0xab, 0b101, 10\ns: .asciiz \"0XFF\"\n\n.text\n\nmain:\n\tla $a0, 0x10010000\n\tlw $t0, 0x1001000a\n\tlw $t1, 4($sp)\n",
+        ),
+        (
+            HexCase::Upper,
+            AddressLiterals::Decimal,
+            ".data 268500992\n\nx: .word 0xFF, -0xAB, 0b101, 10\ns: .asciiz \"0XFF\"\n\n.text\n\nmain:\n\tla $a0, 268500992\n\tlw $t0, 268501002\n\tlw $t1, 4($sp)\n",
+        ),
+    ];
+
+    for (hex_case, address_literals, expected) in cases {
+        let config = Config {
+            hex_case,
+            address_literals,
+            ..Config::default()
+        };
+        let formatted = formatter::format_with_config(String::from(input), &config);
+        assert_eq!(formatted, Ok(String::from(expected)));
+        assert_eq!(
+            formatter::format_with_config(formatted.clone().unwrap(), &config),
+            formatted
+        );
+    }
+}
+
+#[test]
+fn address_operands() {
+    let input = ".text\nmain: j 4194304\nlw $t1, arr + 4\nlw $t2, 4($sp)\nla $a0, 0x10010000\njal 0x00400010\n";
+    let cases = [
+        (
+            AddressLiterals::Hex,
+            ".text\n\nmain:\n\tj 0x00400000\n\tlw $t1, arr + 4\n\tlw $t2, 4($sp)\n\tla $a0, 0x10010000\n\tjal 0x00400010\n",
+        ),
+        (
+            AddressLiterals::Decimal,
+            ".text\n\nmain:\n\tj 4194304\n\tlw $t1, arr + 4\n\tlw $t2, 4($sp)\n\tla $a0, 268500992\n\tjal 4194320\n",
+        ),
+    ];
+
+    for (address_literals, expected) in cases {
+        let config = Config {
+            address_literals,
+            ..Config::default()
+        };
+        assert_eq!(
+            formatter::format_with_config(String::from(input), &config),
+            Ok(String::from(expected))
+        );
+    }
+}
+
+#[test]
+fn operator_spacing() {
+    let input = ".eqv SIZE - 1\n.data\nx: .word - 2, 3 +4\nf: .float 1.5e-3\n.text\nmain: lw $t0, - 4($sp)\nla $a0, x+ 4\nli $t1, SIZE*2\n";
+    let cases = [
+        (
+            OperatorSpacing::Tight,
+            ".eqv SIZE -1\n\n.data\n\nx: .word -2, 3+4\nf: .float 1.5e-3\n\n.text\n\nmain:\n\tlw $t0, -4($sp)\n\tla $a0, x+4\n\tli $t1, SIZE*2\n",
+        ),
+        (
+            OperatorSpacing::Spaced,
+            ".eqv SIZE -1\n\n.data\n\nx: .word -2, 3 + 4\nf: .float 1.5e-3\n\n.text\n\nmain:\n\tlw $t0, -4($sp)\n\tla $a0, x + 4\n\tli $t1, SIZE * 2\n",
+        ),
+    ];
+
+    for (operator_spacing, expected) in cases {
+        let config = Config {
+            operator_spacing,
+            ..Config::default()
+        };
+        let formatted = formatter::format_with_config(String::from(input), &config);
+        assert_eq!(formatted, Ok(String::from(expected)));
+        assert_eq!(
+            formatter::format_with_config(formatted.clone().unwrap(), &config),
+            formatted
+        );
+    }
+}
+
+#[test]
+fn relocation_operands() {
+    let input =
+        ".text\nmain: lui $t0,%hi ( sym )\naddiu $t0, $t0, %lo( sym+4 )\nlw $t1, %lo(sym) ($t0)\n";
+    let expected = ".text\n\nmain:\n\tlui $t0, %hi(sym)\n\taddiu $t0, $t0, %lo(sym+4)\n\tlw $t1, %lo(sym)($t0)\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &Config::default()),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn keep_together() {
+    let input = ".text\nmain:\nla $a0, msg\n\nli $a1, 3\n\njal print\n\nli $v0, 10\n\nsyscall\n\nli $t0, 1\n\nli $v0, 1\n# exit\nsyscall\n";
+    let config = Config {
+        keep_together: vec![
+            String::from("li $v0 + syscall"),
+            String::from("* $a0|$a1|$a2|$a3 + jal"),
+        ],
+        ..Config::default()
+    };
+    let expected = ".text\n\nmain:\n\tla $a0, msg\n\tli $a1, 3\n\tjal print\n\n\tli $v0, 10\n\tsyscall\n\n\tli $t0, 1\n\n\tli $v0, 1\n\t# exit\n\tsyscall\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn multiple_labels() {
+    let input = ".text\nloop: top:  li $t0, 0\na: b: c: # all\n  jr $ra\nli $t1, ':'\n";
+    let expected =
+        ".text\n\nloop:\ntop:\n\tli $t0, 0\n\na:\nb:\nc:  # all\n\tjr $ra\n\tli $t1, ':'\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &Config::default()),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn globl_styles() {
+    let input = ".text\n.globl main,helper ,  print # api\n# entry\nmain:\n  jr $ra\n\n.globl other\n\nhelper: jr $ra\n.globl main\n";
+    let cases = [
+        (
+            GloblStyle::Preserve,
+            ".text\n\n.globl main, helper, print  # api\n\n# entry\nmain:\n\tjr $ra\n\n.globl other\n\nhelper:\n\tjr $ra\n\n.globl main\n",
+        ),
+        (
+            GloblStyle::Joined,
+            ".text\n\n.globl main, helper, print, other  # api\n\n# entry\nmain:\n\tjr $ra\n\nhelper:\n\tjr $ra\n",
+        ),
+        (
+            GloblStyle::Split,
+            ".text\n\n.globl main  # api\n.globl helper\n.globl print\n.globl other\n\n# entry\nmain:\n\tjr $ra\n\nhelper:\n\tjr $ra\n",
+        ),
+    ];
+
+    for (globl_style, expected) in cases {
+        let config = Config {
+            globl_style,
+            ..Config::default()
+        };
+        assert_eq!(
+            formatter::format_with_config(String::from(input), &config),
+            Ok(String::from(expected))
+        );
+    }
+}
+
+#[test]
+fn extern_declarations() {
+    let input = ".data\n.extern buf 64\nx: .word 1\n.text\nmain:\nli $t0, 1\n.extern count 4\n.extern other 8\njr $ra\n";
+    let expected = ".data\n\n.extern buf 64\n\nx: .word 1\n\n.text\n\nmain:\n\tli $t0, 1\n\n.extern count 4\n.extern other 8\n\n\tjr $ra\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn comments_only_files() {
+    let cases = [
+        ("", ""),
+        ("\n\n  \n", ""),
+        (
+            "\n  #  Title  \n\n\n#\n# body\n\n",
+            "# Title\n\n#\n# body\n",
+        ),
+    ];
+    for (input, expected) in cases {
+        let formatted = formatter::format(String::from(input));
+        assert_eq!(formatted, Ok(String::from(expected)));
+        assert_eq!(formatter::format(String::from(expected)), formatted);
+    }
+
+    let config = Config {
+        protected_region: vec![String::from("#! begin"), String::from("#! end")],
+        ..Config::default()
+    };
+    let header = "#! begin\n#   ID: 12345  \n#! end\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(header), &config),
+        Ok(String::from(header))
+    );
+}
+
+#[test]
+fn unformatted_regions() {
+    let input = ".data\n# macmips: off\ntable:  .word  1,   2\n        .word 10,  20\n\n# macmips: on\nx:   .word 3\n.text\nmain:\nli $t0 ,1   # macmips: skip\n# macmips: skip\nli   $t1,2\nli $t2 ,3\n";
+    let expected = ".data\n\n# macmips: off\ntable:  .word  1,   2\n        .word 10,  20\n\n# macmips: on\nx: .word 3\n\n.text\n\nmain:\nli $t0 ,1   # macmips: skip\n# macmips: skip\nli   $t1,2\n\tli $t2, 3\n";
+
+    let formatted = formatter::format(String::from(input));
+    assert_eq!(formatted, Ok(String::from(expected)));
+    assert_eq!(formatter::format(String::from(expected)), formatted);
+}
+
+#[test]
+fn labels_in_unformatted_regions_keep_names() {
+    let config = Config {
+        label_case: LabelCase::SnakeCase,
+        ..Config::default()
+    };
+    let input = ".text\nmain:\nj MyLabel\nb OtherLabel\n# macmips: off\nMyLabel:  nop\n# macmips: on\nOtherLabel:\njr $ra\n";
+    let expected = ".text\n\nmain:\n\tj MyLabel\n\tb other_label\n# macmips: off\nMyLabel:  nop\n# macmips: on\n\nother_label:\n\tjr $ra\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn hand_aligned_tables() {
+    let input = ".data\nsmall:  .word   1,   2,  300\nbig:    .word  10,  20,   30\nx:   .word 3\ny: .word    4\n";
+    let kept = ".data\n\nsmall:  .word   1,   2,  300\nbig:    .word  10,  20,   30\nx: .word 3\ny: .word 4\n";
+    let collapsed =
+        ".data\n\nsmall: .word 1, 2, 300\nbig: .word 10, 20, 30\nx: .word 3\ny: .word 4\n";
+
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(kept))
+    );
+    let config = Config {
+        preserve_tables: false,
+        ..Config::default()
+    };
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(collapsed))
+    );
+}
+
+#[test]
+fn hand_aligned_tables_normalized() {
+    let config = Config {
+        hex_case: HexCase::Upper,
+        ..Config::default()
+    };
+
+    let input = ".data\nmasks:  .word   0xff,  0x0f  # low\nmore:   .word  0xabc,   0x1\n";
+    let expected = ".data\n\nmasks:  .word   0xFF,  0x0F  # low\nmore:   .word  0xABC,   0x1\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn kernel_sections() {
+    let input = ".kdata\nsaved:   .word 0\n.ktext 0x80000180\nhandler:\nmove $k0,$at\neret";
+    let expected =
+        ".kdata\n\nsaved: .word 0\n\n.ktext 0x80000180\n\nhandler:\n\tmove $k0, $at\n\teret\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn macro_blocks() {
+    let input =
+        ".macro add3(%d,%a ,  %b)\nadd %d,%a,%b\n.end_macro\n.text\nmain:\nadd3($t0, $t1, $t2)";
+    let expected = ".macro add3 (%d, %a, %b)\n\tadd %d, %a, %b\n.end_macro\n\n.text\n\nmain:\n\tadd3($t0, $t1, $t2)\n";
+    let formatted = formatter::format(String::from(input));
+    assert_eq!(formatted, Ok(String::from(expected)));
+    assert_eq!(formatter::format(formatted.clone().unwrap()), formatted);
+}
+
+#[test]
+fn eqv_constants() {
+    let input =
+        ".text\n# entry point\nmain:\nli $v0,EXIT\n\n.eqv EXIT 10\n\nsyscall\n.eqv PRINT_INT 1";
+    let expected = ".text\n\n.eqv EXIT      10\n.eqv PRINT_INT 1\n\n# entry point\nmain:\n\tli $v0, EXIT\n\n\tsyscall\n";
+    let formatted = formatter::format(String::from(input));
+    assert_eq!(formatted, Ok(String::from(expected)));
+    assert_eq!(formatter::format(formatted.clone().unwrap()), formatted);
+}
+
+#[test]
+fn include_lines() {
+    let input = ".include   \"lib.s\"\n.text\nmain:\n.include \"body.s\"\nli $v0,10";
+    let expected = ".include \"lib.s\"\n\n.text\n\nmain:\n.include \"body.s\"\n\n\tli $v0, 10\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn matrix_rows() {
+    let config = Config {
+        align_matrices: true,
+        ..Config::default()
+    };
+
+    let input = ".data\nm: .word 1,2,10\n.word 100,-2,3   # row 2\n.word 7, 8, 9\nv: .word 1, 2\nw: .word 3";
+    let expected = ".data\n\nm: .word   1,  2, 10\n   .word 100, -2,  3  # row 2\n   .word   7,  8,  9\nv: .word 1, 2\nw: .word 3\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn set_directives() {
+    let input = ".text\nmain:\n    .set noat\n\n    move $at,$t0\n    .set at\n    jr $ra";
+    let expected = ".text\n\nmain:\n.set noat\n\tmove $at, $t0\n\n.set at\n\tjr $ra\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn char_literals() {
+    let input =
+        ".text\nmain:\nli $t0,','\nbeq $t1, '(' , skip\nli $t2,'\\n'\nli $t3, '\\''\nli $t4,'\\\\'";
+    let expected = ".text\n\nmain:\n\tli $t0, ','\n\tbeq $t1, '(', skip\n\tli $t2, '\\n'\n\tli $t3, '\\''\n\tli $t4, '\\\\'\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn hash_in_literals() {
+    let input = ".data\nhash: .asciiz \"#\"   # a hash\n.text\nmain:\nli $t0,'#'\nloop: li $t1,'#' # another\n";
+    let expected = ".data\n\nhash: .asciiz \"#\"  # a hash\n\n.text\n\nmain:\n\tli $t0, '#'\n\nloop:\n\tli $t1, '#'  # another\n";
+    assert_eq!(
+        formatter::format(String::from(input)),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn aligned_data_lists() {
+    let config = Config {
+        data_list_continuation: DataListContinuation::Aligned,
+        data_list_width: 30,
+        ..Config::default()
+    };
+
+    let input =
+        ".data\nnums: .word 1,2,3,4,5,6,7,8,9,10,11,12,13   # the numbers\nchars: .byte ',', 'a'";
+    let expected = ".data\n\nnums: .word 1, 2, 3, 4, 5, 6,  # the numbers\n            7, 8, 9, 10, 11,\n            12, 13\nchars: .byte ',', 'a'\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn data_list_directive_in_string() {
+    let config = Config {
+        data_list_width: 30,
+        ..Config::default()
+    };
+
+    let input = ".data\nmsg: .asciiz \"use .word 1, 2, 3, 4, 5, 6, 7\"";
+    let expected = ".data\n\nmsg: .asciiz \"use .word 1, 2, 3, 4, 5, 6, 7\"\n";
+    assert_eq!(
+        formatter::format_with_config(String::from(input), &config),
+        Ok(String::from(expected))
+    );
+}
+
+#[test]
+fn organize() {
+    let mut config = Config::default();
+    config.organize();
+
+    let input = ".data\nb: .word 2\na: .word 1\n.text\nmain:\nli $v0, 10\n\n\n\nsyscall\n.globl main\nhelper:\njr $ra\n.globl helper";
+    let expected = ".data\n\na: .word 1\nb: .word 2\n\n.text\n\n.globl main\n.globl helper\n\nmain:\n\tli $v0, 10\n\n\tsyscall\n\nhelper:\n\tjr $ra\n";
+    let formatted = formatter::format_with_config(String::from(input), &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted)
+    );
+}
+
+#[test]
+fn version_headers() {
+    let config = Config {
+        version_header: VersionHeader::Top,
+        ..Config::default()
+    };
+
+    let formatted =
+        formatter::format_with_config(String::from(".text\nmain:\nli $v0,10"), &config).unwrap();
+    let (header, rest) = formatted.split_once('\n').unwrap();
+    assert!(header.starts_with("# formatted with mac-mips v"));
+    assert_eq!(rest, ".text\n\nmain:\n\tli $v0, 10\n");
+    assert_eq!(
+        formatter::format_with_config(formatted.clone(), &config),
+        Ok(formatted.clone())
+    );
+
+    let config = Config {
+        version_header: VersionHeader::Bottom,
+        ..config
+    };
+    let moved = formatter::format_with_config(formatted, &config).unwrap();
+    assert!(moved.starts_with(".text\n"));
+    assert!(moved
+        .lines()
+        .last()
+        .unwrap()
+        .starts_with("# formatted with"));
+}
@@ -0,0 +1,47 @@
+use crate::analysis;
+use crate::grade::{grade, Rubric};
+use crate::isa::InstructionSet;
+
+#[test]
+fn parse_rubric() {
+    let input = "required_labels = [\"main\"]\nforbidden_instructions = [\"mul\"]\nmax_pseudo_instructions = 2\n";
+    assert_eq!(
+        Rubric::parse(input),
+        Ok(Rubric {
+            required_labels: vec![String::from("main")],
+            forbidden_instructions: vec![String::from("mul")],
+            max_pseudo_instructions: Some(2),
+            min_comment_coverage: None,
+        })
+    );
+    assert!(Rubric::parse("bonus_points = 3").is_err());
+}
+
+#[test]
+fn score_breakdown() {
+    let rubric = Rubric {
+        required_labels: vec![String::from("main"), String::from("helper")],
+        forbidden_instructions: vec![String::from("mul")],
+        max_pseudo_instructions: Some(1),
+        min_comment_coverage: Some(50),
+    };
+    let source = "main:\n# load\nli $t0, 2\nli $t1, 3\nmul $t2, $t0, $t1  # multiply\nsyscall";
+    let checks = grade(
+        &rubric,
+        &analysis::parse(source),
+        &InstructionSet::standard(),
+    );
+
+    let results: Vec<(&str, bool)> = checks.iter().map(|c| (c.name.as_str(), c.passed)).collect();
+    assert_eq!(
+        results,
+        vec![
+            ("required label `main`", true),
+            ("required label `helper`", false),
+            ("forbidden instruction `mul`", false),
+            ("pseudo-instructions", false),
+            ("comment coverage", true),
+        ]
+    );
+    assert_eq!(checks[2].detail, "used on line 5");
+}
@@ -0,0 +1,34 @@
+use crate::config::LabelCase;
+use crate::labels::{rename_labels, to_case};
+
+#[test]
+fn label_cases() {
+    let cases = [
+        ("printInt", "print_int", "printint"),
+        ("HTTPServer", "http_server", "httpserver"),
+        ("loop2End", "loop2_end", "loop2end"),
+        ("Loop_end.1", "loop_end.1", "loop_end.1"),
+    ];
+    for (label, snake, lower) in cases {
+        assert_eq!(to_case(label, LabelCase::SnakeCase), snake);
+        assert_eq!(to_case(label, LabelCase::Lowercase), lower);
+        assert_eq!(to_case(label, LabelCase::Preserve), label);
+    }
+}
+
+#[test]
+fn rename_references() {
+    let input = ".globl doThing\n.data\nmyArray: .word 1\nmsg: .asciiz \"myArray\"\n.text\nmain:\nla $a0, myArray  # myArray\nlw $t0, myArray+4($t1)\nprintLoop: beq $t0, $zero, printLoop\njal doThing\ndoThing:\nb printLoop\nprint_loop:\n";
+    let expected = ".globl doThing\n.data\nmy_array: .word 1\nmsg: .asciiz \"myArray\"\n.text\nmain:\nla $a0, my_array  # myArray\nlw $t0, my_array+4($t1)\nprintLoop: beq $t0, $zero, printLoop\njal doThing\ndoThing:\nb printLoop\nprint_loop:\n";
+    assert_eq!(rename_labels(input, LabelCase::SnakeCase, &[]), expected);
+}
+
+#[test]
+fn keep_labels_in_kept_lines() {
+    let input = ".text\nmain:\nj MyLabel\nb OtherLabel\nMyLabel:\nOtherLabel:\n";
+    let expected = ".text\nmain:\nj MyLabel\nb other_label\nMyLabel:\nother_label:\n";
+    assert_eq!(
+        rename_labels(input, LabelCase::SnakeCase, &[(4, 4)]),
+        expected
+    );
+}
@@ -0,0 +1,217 @@
+use crate::config::Config;
+use crate::isa::Isa;
+use crate::lint::{fix_hidden_characters, fix_smart_quotes, lint, lint_timed, Severity};
+
+fn rules(source: &str, config: &Config) -> Vec<(usize, &'static str)> {
+    lint(source, config)
+        .into_iter()
+        .map(|d| (d.line, d.rule))
+        .collect()
+}
+
+#[test]
+fn forbidden_instructions() {
+    let mut config = Config::default();
+    config
+        .apply_toml("forbidden_instructions = [\"mul\", \".include\"]")
+        .unwrap();
+
+    let source = ".include \"macros.asm\"\nmain:\nMUL $t0, $t1, $t2\nmult $t1, $t2  # mul";
+    assert_eq!(
+        rules(source, &config),
+        vec![(1, "forbidden-instruction"), (3, "forbidden-instruction")]
+    );
+
+    let diagnostics = lint(source, &config);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert!(diagnostics[0].message.starts_with("directive `.include`"));
+}
+
+#[test]
+fn custom_instruction_tables() {
+    let mut config = Config::default();
+    config
+        .instructions
+        .extend_from_toml("pseudo = [\"print_int\"]\nmips32r2 = [\"madd\"]")
+        .unwrap();
+    config.isa = Isa::Mips32;
+
+    let source = "main:\nprint_int $t0\nmadd $t0, $t1";
+    assert_eq!(rules(source, &config), vec![(3, "isa")]);
+    assert!(config.instructions.get("PRINT_INT").unwrap().pseudo);
+    assert_eq!(
+        config.instructions.extend_from_toml("mips64 = [\"dadd\"]"),
+        Err(String::from(
+            "unknown key `mips64`, expected an ISA name or `pseudo`"
+        ))
+    );
+}
+
+#[test]
+fn isa_subset() {
+    let source = "main:\nrotr $t0, $t1, 4\nmovz $t0, $t1, $t2\nli $t0, 1\naddu $t0, $t0, $t1";
+    let mut config = Config::default();
+    assert_eq!(rules(source, &config), vec![]);
+
+    config.apply_toml("isa = \"mips32\"").unwrap();
+    assert_eq!(rules(source, &config), vec![(2, "isa")]);
+
+    config.apply_toml("isa = \"mips1\"").unwrap();
+    assert_eq!(rules(source, &config), vec![(2, "isa"), (3, "isa")]);
+    assert!(config.apply_toml("isa = \"mips64\"").is_err());
+}
+
+#[test]
+fn fp_condition_flags() {
+    let config = Config::default();
+    let source = "main:\nbc1t done\nc.lt.s $f0, $f1\nbc1f done\nc.eq.d 2, $f2, $f4\nbc1t 1, done\ndone:\nbc1t 2, main";
+    assert_eq!(
+        rules(source, &config),
+        vec![
+            (2, "fp-condition-flag"),
+            (6, "fp-condition-flag"),
+            (8, "fp-condition-flag")
+        ]
+    );
+
+    let diagnostics = lint(source, &config);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(
+        diagnostics[1].message,
+        "`bc1t` tests condition flag 1, but comparisons in this block only set flag 0, 2"
+    );
+}
+
+#[test]
+fn macro_params() {
+    let config = Config::default();
+    let source = ".macro print (%value, %unused)\nli $v0, 1\nmove $a0, %value\nadd $a0, $a0, %typo\nsyscall\n.end_macro\n.macro done\nli $v0, 10\n.end_macro";
+    let diagnostics = lint(source, &config);
+    let messages: Vec<(usize, &str)> = diagnostics
+        .iter()
+        .map(|d| (d.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (1, "parameter `%unused` of macro `print` is never used"),
+            (4, "`%typo` is not a parameter of macro `print`"),
+        ]
+    );
+}
+
+#[test]
+fn hidden_characters() {
+    let config = Config::default();
+    let source = "li\u{a0}$t0, 1\nl\u{0430} $a0, msg  # \u{a0}ok\nla $a0, \"\u{200b}\"\nadd $t0,\u{200b} $t1, $t2";
+    assert_eq!(
+        rules(source, &config),
+        vec![
+            (1, "hidden-character"),
+            (2, "hidden-character"),
+            (4, "hidden-character")
+        ]
+    );
+    assert_eq!(
+        lint(source, &config)[0].message,
+        "no-break space (U+00A0) in column 3, use ` `"
+    );
+    assert_eq!(
+        fix_hidden_characters(source),
+        "li $t0, 1\nla $a0, msg  # \u{a0}ok\nla $a0, \"\u{200b}\"\nadd $t0, $t1, $t2"
+    );
+}
+
+#[test]
+fn smart_quotes() {
+    let config = Config::default();
+    let source = "msg: .asciiz \u{201c}hi\u{201d}\nq: .asciiz \"it\u{2019}s\"  # \u{201c}ok\u{201d}\nli $t0, \u{2018}a\u{2019}";
+    let diagnostics = lint(source, &config);
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|d| (d.line, d.severity))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, Severity::Error),
+            (1, Severity::Error),
+            (2, Severity::Warning),
+            (3, Severity::Error),
+            (3, Severity::Error)
+        ]
+    );
+    assert_eq!(
+        fix_smart_quotes(source),
+        "msg: .asciiz \"hi\"\nq: .asciiz \"it's\"  # \u{201c}ok\u{201d}\nli $t0, 'a'"
+    );
+}
+
+#[test]
+fn mixed_indentation() {
+    let config = Config::default();
+    let source = "main:\n\tli $v0, 1\n\tli $a0, 2\n    syscall\n \tjr $ra";
+    let diagnostics = lint(source, &config);
+    let messages: Vec<(usize, &str)> = diagnostics
+        .iter()
+        .map(|d| (d.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (4, "indented with spaces but most of the file uses tabs"),
+            (5, "indentation mixes tabs and spaces"),
+        ]
+    );
+}
+
+#[test]
+fn data_sizes() {
+    let source = ".eqv ARR_SIZE 5\n.eqv BUF_LEN 16\n.data\narr: .word 1, 2, 3,\n  .word 4\nbuf: .word 0:4\n.text\nmain:\nla $a0, arr\nli $t1, 4\nloop:\nble $t0, 4, loop\nble $t0, BUF_LEN, loop\nblt $t0, $t1, loop\nbge $t0, 5, loop\nslti $t2, $t0, 4\n";
+    assert_eq!(
+        rules(source, &Config::default()),
+        vec![(4, "data-size"), (12, "data-size"), (15, "data-size")]
+    );
+}
+
+#[test]
+fn procedure_size() {
+    let config = Config {
+        max_procedure_instructions: 3,
+        ..Config::default()
+    };
+    let source = "main:\nli $t0, 1\nli $t1, 2\nloop:\naddi $t0, $t0, 1\nblt $t0, $t1, loop\ndone:\nli $v0, 10\nsyscall\nshort:\njr $ra\n";
+    let diagnostics = lint(source, &config);
+    assert_eq!(rules(source, &config), vec![(1, "procedure-size")]);
+    assert!(diagnostics[0]
+        .message
+        .ends_with("consider splitting it at `loop` (line 4), `done` (line 7)"));
+
+    assert!(rules(source, &Config::default()).is_empty());
+}
+
+#[test]
+fn at_clobbers() {
+    let source = ".data\nx: .word 1\n.text\nmain:\nli $at, 5\nla $t0, x\nadd $t1, $at, $t0\nli $1, 3\nli $t2, 7\naddi $t2, $t2, 0x10000\nlw $t3, x\nlw $t4, %lo(x)($t0)\nfoo:\nmove $at, $t0\njr $ra\n";
+    assert_eq!(
+        rules(source, &Config::default()),
+        vec![(6, "at-clobber"), (10, "at-clobber")]
+    );
+}
+
+#[test]
+fn lint_ignore_and_timings() {
+    let source = "main:\n\tli $t0, 1\n  li $t1, 2\n";
+    assert_eq!(
+        rules(source, &Config::default()),
+        vec![(3, "mixed-indentation")]
+    );
+
+    let config = Config {
+        lint_ignore: vec![String::from("mixed-indentation")],
+        ..Config::default()
+    };
+    let (diagnostics, timings) = lint_timed(source, &config);
+    assert!(diagnostics.is_empty());
+    assert_eq!(timings[0].0, "parse");
+    assert!(!timings.iter().any(|(rule, _)| *rule == "mixed-indentation"));
+}
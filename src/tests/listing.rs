@@ -0,0 +1,33 @@
+use crate::listing::listing;
+
+#[test]
+fn addresses_and_encodings() {
+    let source = ".data\nmsg: .asciiz \"hi\"\nnums: .word 1, 2\n.text\nmain:\nla $a0, msg\nloop: addi $t0, $t0, -1\nbgtz $t0, loop\nfoo $t0\n";
+    let expected = [
+        "                                   .data",
+        "0x10010000                         msg: .asciiz \"hi\"",
+        "0x10010004                         nums: .word 1, 2",
+        "                                   .text",
+        "                                   main:",
+        "0x00400000  0x3c011001 0x34240000  la $a0, msg",
+        "0x00400008  0x2108ffff             loop: addi $t0, $t0, -1",
+        "0x0040000c  0x1d00fffe             bgtz $t0, loop",
+        "0x00400010                         foo $t0",
+    ];
+    assert_eq!(listing(source), expected.join("\n") + "\n");
+}
+
+#[test]
+fn relocations() {
+    let source =
+        ".data\n.space 0x8000\nsym: .word 1\n.text\nlui $t0, %hi(sym)\nlw $t1, %lo(sym)($t0)\n";
+    let expected = [
+        "                                   .data",
+        "0x10010000                         .space 0x8000",
+        "0x10018000                         sym: .word 1",
+        "                                   .text",
+        "0x00400000  0x3c081002             lui $t0, %hi(sym)",
+        "0x00400004  0x8d098000             lw $t1, %lo(sym)($t0)",
+    ];
+    assert_eq!(listing(source), expected.join("\n") + "\n");
+}
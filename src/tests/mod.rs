@@ -1 +1,22 @@
+mod cli;
+mod config;
+mod diff;
+mod doctor;
+mod duplicates;
+mod emit;
+mod encoding;
+mod expand;
+mod extract;
+mod flatten;
 mod format;
+mod grade;
+mod labels;
+mod lint;
+mod listing;
+mod outline;
+mod project;
+mod reorder;
+mod report;
+mod semdiff;
+mod sink;
+mod zip;
@@ -0,0 +1,9 @@
+use crate::analysis::parse;
+use crate::outline::{outline, render};
+
+#[test]
+fn outline_tree() {
+    let input = "init: li $t0, 0\n.data\nlist: .word 1, 2\nmsg:\n.asciiz \"hi\"\n.text\n.globl main\nmain:\njal sum\nsum:\nloop:\nbnez $t0, loop\ndone: jr $ra\n";
+    let expected = "section .text (line 1)\n  label init (line 1)\nsection .data (line 2)\n  data list (line 3)\n  data msg (line 4)\nsection .text (line 6)\n  procedure main (line 8)\n  procedure sum (line 10)\n    label loop (line 11)\n    label done (line 13)\n";
+    assert_eq!(render(&outline(&parse(input))), expected);
+}
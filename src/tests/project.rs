@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+
+use crate::doctor::Dialect;
+use crate::isa::Isa;
+use crate::lint::{Diagnostic, Severity};
+use crate::project::Project;
+
+fn read(path: &Path) -> Result<String, String> {
+    match path.to_str() {
+        Some("proj/src/main.s") => Ok(String::from(".include \"util.s\"\nmain:\n.extern buf 4\n")),
+        Some("proj/lib/util.s") => Ok(String::from(".include \"io.s\"\nutil:\n")),
+        Some("proj/lib/io.s") => Ok(String::new()),
+        _ => Err(format!("{}: not found", path.display())),
+    }
+}
+
+#[test]
+fn parse_manifest() {
+    let manifest = "entry = \"src/main.s\"\ninclude_paths = [\"lib\"]\ndialect = \"mars\"\nisa = \"mips32\"\n\n[lint]\nignore = [\"isa\"]\ndeny_warnings = true\n";
+    let project = Project::parse(manifest, Path::new("proj")).unwrap();
+    assert_eq!(
+        project,
+        Project {
+            root: PathBuf::from("proj"),
+            entry: PathBuf::from("proj/src/main.s"),
+            sources: Vec::new(),
+            include_paths: vec![PathBuf::from("proj/lib")],
+            dialect: Some(Dialect::Mars),
+            isa: Some(Isa::Mips32),
+            lint_ignore: vec![String::from("isa")],
+            deny_warnings: true,
+        }
+    );
+    assert_eq!(
+        project.files(&read),
+        Ok(vec![
+            PathBuf::from("proj/src/main.s"),
+            PathBuf::from("proj/lib/util.s"),
+            PathBuf::from("proj/lib/io.s"),
+        ])
+    );
+
+    assert_eq!(
+        Project::parse("include_paths = []", Path::new("proj")),
+        Err(String::from(
+            "missing `entry`, the file the program starts in"
+        ))
+    );
+    assert_eq!(
+        Project::parse("entry = \"a.s\"\nentrypoint = \"b.s\"", Path::new("proj")),
+        Err(String::from("unknown manifest key `entrypoint`"))
+    );
+}
+
+#[test]
+fn lint_policy() {
+    let project = Project::parse(
+        "entry = \"main.s\"\n[lint]\nignore = [\"isa\"]\ndeny_warnings = true",
+        Path::new("."),
+    )
+    .unwrap();
+    let diagnostic = |rule, severity| Diagnostic {
+        line: 1,
+        severity,
+        rule,
+        message: String::new(),
+    };
+
+    assert_eq!(
+        project.lint_policy(vec![
+            diagnostic("isa", Severity::Error),
+            diagnostic("mixed-indentation", Severity::Warning),
+        ]),
+        vec![diagnostic("mixed-indentation", Severity::Error)]
+    );
+}
+
+#[test]
+fn shared_symbols() {
+    let read = |path: &Path| -> Result<String, String> {
+        match path.to_str() {
+            Some("main.s") => Ok(String::from(
+                ".globl main\nmain:\njal util\njal helper\njal missing\n.globl buf\n",
+            )),
+            Some("util.s") => Ok(String::from(
+                ".globl util\n.globl ghost\nutil:\njr $ra\nhelper:\njr $ra\n",
+            )),
+            Some("io.s") => Ok(String::from(
+                ".globl util\nutil:\njr $ra\nmain:\n.extern buf 4\n",
+            )),
+            _ => Err(format!("{}: not found", path.display())),
+        }
+    };
+    let project = Project::parse(
+        "entry = \"main.s\"\nsources = [\"util.s\", \"io.s\"]",
+        Path::new(""),
+    )
+    .unwrap();
+    let found: Vec<(String, usize, &str)> = project
+        .check_symbols(&read)
+        .unwrap()
+        .into_iter()
+        .map(|(file, d)| (file.display().to_string(), d.line, d.rule))
+        .collect();
+
+    assert_eq!(
+        found,
+        vec![
+            (String::from("io.s"), 2, "globals"),
+            (String::from("util.s"), 2, "globals"),
+            (String::from("main.s"), 4, "globals"),
+            (String::from("main.s"), 5, "globals"),
+            (String::from("io.s"), 4, "entry-point"),
+        ]
+    );
+}
@@ -0,0 +1,25 @@
+use crate::reorder::check;
+
+const OLD: &str = ".text\nmain:\nlw $t0, 0($a0)\naddi $t1, $t1, 1\nadd $t2, $t0, $t1\nsw $t2, 4($a0)\nbeq $t2, $zero, done\nnop\ndone:\njr $ra\n";
+
+#[test]
+fn safe_reordering() {
+    let new = ".text\nmain:\naddi $t1, $t1, 1\nlw $t0, 0($a0)\nadd $t2, $t0, $t1\nbeq $t2, $zero, done\nsw $t2, 4($a0)\ndone:\njr $ra\n";
+    assert_eq!(check(OLD, new, Some("main"), true), Ok(Vec::new()));
+}
+
+#[test]
+fn hazards() {
+    let new = ".text\nmain:\nlw $t0, 0($a0)\nadd $t2, $t0, $t1\naddi $t1, $t1, 1\nbeq $t2, $zero, done\nsw $t2, 4($a0)\ndone:\njr $ra\n";
+    assert_eq!(
+        check(OLD, new, None, false),
+        Ok(vec![
+            String::from("`sw $t2, 4($a0)` moved from line 6 to line 7, past a label or branch"),
+            String::from("`add $t2, $t0, $t1` (line 4) now runs before `addi $t1, $t1, 1` (line 5), which it reads $t1 from"),
+        ])
+    );
+
+    let changed = OLD.replace("addi $t1, $t1, 1", "addi $t1, $t1, 2");
+    assert_eq!(check(OLD, &changed, None, false).map(|p| p.len()), Ok(2));
+    assert!(check(OLD, OLD, Some("foo"), false).is_err());
+}
@@ -0,0 +1,57 @@
+use crate::json::Json;
+use crate::report::{file_report, is_structural, transformations};
+
+#[test]
+fn report_rules() {
+    let before = "main:\n  li $v0 ,1\n\n\n    syscall # go\nmove $a0, $t0";
+    let after = "main:\n\tli $v0, 1\n\n\tsyscall  # go\n\tmove $a0, $t0\n";
+    let report = file_report("a.s", before, after, &[("label", 1), ("code", 1)]);
+    assert_eq!(
+        report,
+        Json::object(vec![
+            ("file", "a.s".into()),
+            ("lines_touched", 4.into()),
+            (
+                "rules",
+                Json::object(vec![
+                    ("spacing", 1.into()),
+                    ("blank-lines", 1.into()),
+                    ("comment-alignment", 1.into()),
+                    ("indentation", 1.into()),
+                ])
+            ),
+            (
+                "transformations",
+                Json::object(vec![
+                    ("indent", 3.into()),
+                    ("comma-spacing", 1.into()),
+                    ("blank-lines", 1.into()),
+                    ("comment-align", 1.into()),
+                ])
+            ),
+            ("structural", false.into()),
+            (
+                "chunks",
+                Json::object(vec![("label", 1.into()), ("code", 1.into())])
+            ),
+        ])
+    );
+}
+
+#[test]
+fn report_transformations() {
+    let before = "main: li $v0 ,1\n  syscall # go\n";
+    let after = "main:\n\tli $v0, 1\n\tsyscall  # go\n";
+    assert_eq!(
+        transformations(before, after),
+        vec![
+            ("label-split", 1),
+            ("indent", 2),
+            ("comma-spacing", 1),
+            ("comment-align", 1),
+        ]
+    );
+
+    let cosmetic = transformations("  li $v0 ,1\n", "\tli $v0, 1\n");
+    assert!(!cosmetic.iter().any(|(t, _)| is_structural(t)));
+}
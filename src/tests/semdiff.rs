@@ -0,0 +1,25 @@
+use crate::semdiff::semantic_diff;
+
+#[test]
+fn ignores_formatting() {
+    let old = "main:\nli $v0,1 # one\n  syscall";
+    let new = "main:\n\tli $v0, 1    # first\n\tsyscall\n";
+    assert_eq!(semantic_diff(old, new), Vec::<String>::new());
+}
+
+#[test]
+fn instruction_and_label_changes() {
+    let old = "main:\nli $v0, 1\nsyscall\nnop\nfoo:\njr $ra\nbar:\njr $ra\nold:\njr $ra";
+    let new = "main:\nli $v0, 4\nsyscall\nbar:\njr $ra\nfoo:\njr $ra\nnew:\njr $ra";
+    assert_eq!(
+        semantic_diff(old, new),
+        vec![
+            "main:",
+            "  modified line 2 -> 2: `li $v0, 1` -> `li $v0, 4`",
+            "  removed line 4: `nop`",
+            "added label new (new line 8)",
+            "removed label old (old line 9)",
+            "moved label foo",
+        ]
+    );
+}
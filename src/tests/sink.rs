@@ -0,0 +1,108 @@
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use crate::sink::{self, FileSink, OutputSink, StreamSink};
+
+#[test]
+fn stream_sink() {
+    let mut sink = StreamSink::new("buffer", Vec::new());
+    sink.write(b"main:\n").unwrap();
+    sink.write(b"\tjr $ra\n").unwrap();
+    assert_eq!(sink.name(), "buffer");
+    assert_eq!(sink.stream, b"main:\n\tjr $ra\n");
+}
+
+#[test]
+fn file_sink_replaces_whole_file() {
+    let dir = std::env::temp_dir().join(format!("macmips-sink-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("prog.s");
+    fs::write(&path, "a much longer original file\n").unwrap();
+
+    let mut sink = FileSink::new(&path);
+    sink.write(b"main:\n").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "main:\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn file_sink_keeps_links_and_permissions() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let dir = std::env::temp_dir().join(format!("macmips-links-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("prog.s");
+    fs::write(&path, "old\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let link = dir.join("link.s");
+    symlink(&path, &link).unwrap();
+    let hard = dir.join("hard.s");
+    fs::write(&hard, "old\n").unwrap();
+    fs::hard_link(&hard, dir.join("other.s")).unwrap();
+
+    FileSink::new(&link).write(b"main:\n").unwrap();
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "main:\n");
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    FileSink::new(&hard).write(b"main:\n").unwrap();
+    assert_eq!(fs::read_to_string(dir.join("other.s")).unwrap(), "main:\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn retries_while_locked() {
+    let sharing_violation = io::Error::from_raw_os_error(32);
+    assert_eq!(sink::is_locked(&sharing_violation), cfg!(windows));
+
+    let locked = |e: &io::Error| e.kind() == io::ErrorKind::WouldBlock;
+
+    let mut attempts = 0;
+    let result = sink::retry_while_locked(
+        || {
+            attempts += 1;
+            Err(io::ErrorKind::WouldBlock.into())
+        },
+        locked,
+        Duration::ZERO,
+    );
+    assert!(result.as_ref().is_err_and(locked));
+    assert_eq!(attempts, 11);
+
+    let mut attempts = 0;
+    let result = sink::retry_while_locked(
+        || {
+            attempts += 1;
+            match attempts {
+                1..=3 => Err(io::ErrorKind::WouldBlock.into()),
+                _ => Ok(()),
+            }
+        },
+        locked,
+        Duration::ZERO,
+    );
+    assert!(result.is_ok());
+    assert_eq!(attempts, 4);
+
+    let mut attempts = 0;
+    let result = sink::retry_while_locked(
+        || {
+            attempts += 1;
+            Err(io::ErrorKind::PermissionDenied.into())
+        },
+        locked,
+        Duration::ZERO,
+    );
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(attempts, 1);
+}
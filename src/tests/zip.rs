@@ -0,0 +1,51 @@
+use crate::zip::{self, Entry};
+
+fn entry(name: &str, contents: &str) -> Entry {
+    Entry {
+        name: name.to_string(),
+        modified: 0x5350_9a00,
+        contents: contents.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn round_trip() {
+    let entries = vec![
+        entry("alice/", ""),
+        entry("alice/hw1.s", "main:\n\tli $v0, 10\n\tsyscall\n"),
+        entry("bob/notes.txt", "ünïcode"),
+    ];
+    let archive = zip::write(&entries);
+    assert_eq!(zip::read(&archive), Ok(entries));
+    assert_eq!(zip::read(&zip::write(&[])), Ok(vec![]));
+}
+
+#[test]
+fn unreadable_archives() {
+    let archive = zip::write(&[entry("a.s", "main:\n")]);
+
+    // The compression method, in the local and central headers
+    let mut deflated = archive.clone();
+    deflated[8] = 8;
+    let central = archive.len() - 22 - 46 - "a.s".len();
+    deflated[central + 10] = 8;
+    assert_eq!(
+        zip::read(&deflated),
+        Err(String::from(
+            "a.s is compressed, and only stored entries can be read (zip it with `zip -0`)"
+        ))
+    );
+
+    let mut corrupt = archive.clone();
+    corrupt[30 + "a.s".len()] = b'M';
+    assert_eq!(zip::read(&corrupt), Err(String::from("a.s is corrupt")));
+
+    assert_eq!(
+        zip::read(&archive[..(archive.len() - 30)]),
+        Err(String::from("not a zip archive"))
+    );
+    assert_eq!(
+        zip::read(b"main:\n"),
+        Err(String::from("not a zip archive"))
+    );
+}
@@ -0,0 +1,156 @@
+// Just enough of the zip format to format a zip of submissions: entries
+// stored without compression can be read, and archives are written that way.
+// Reading deflated entries would need a decompressor, which mac-mips doesn't
+// have without a dependency.
+
+static LOCAL_HEADER: u32 = 0x0403_4b50;
+static CENTRAL_HEADER: u32 = 0x0201_4b50;
+static END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+// Names are UTF-8
+static UTF8_FLAG: u16 = 0x0800;
+
+/// A file or directory (named with a trailing `/`) in an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    // The MS-DOS time, then date, it was last modified
+    pub modified: u32,
+    pub contents: Vec<u8>,
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn field(bytes: &[u8], at: usize, len: usize) -> Result<&[u8], String> {
+    bytes
+        .get(at..(at + len))
+        .ok_or(String::from("the archive is truncated"))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<u16, String> {
+    field(bytes, at, 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, String> {
+    field(bytes, at, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The entries of a zip archive, in the order its directory lists them.
+/// Fails on anything but stored entries, and on zip64 or encrypted archives.
+pub fn read(bytes: &[u8]) -> Result<Vec<Entry>, String> {
+    // The end record is last, followed only by a comment of up to 64KiB
+    let end = (0..=bytes.len().saturating_sub(22))
+        .rev()
+        .take(22 + u16::MAX as usize)
+        .find(|&at| u32_at(bytes, at) == Ok(END_OF_CENTRAL_DIRECTORY))
+        .ok_or(String::from("not a zip archive"))?;
+    let count = u16_at(bytes, end + 10)?;
+    let directory = u32_at(bytes, end + 16)?;
+    if count == u16::MAX || directory == u32::MAX {
+        return Err(String::from("zip64 archives aren't supported"));
+    }
+    let mut at = directory as usize;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if u32_at(bytes, at)? != CENTRAL_HEADER {
+            return Err(String::from("the archive's directory is corrupt"));
+        }
+        let flags = u16_at(bytes, at + 8)?;
+        let method = u16_at(bytes, at + 10)?;
+        let modified = u32_at(bytes, at + 12)?;
+        let crc = u32_at(bytes, at + 16)?;
+        let size = u32_at(bytes, at + 20)? as usize;
+        let name_len = u16_at(bytes, at + 28)? as usize;
+        let extra_len = u16_at(bytes, at + 30)? as usize;
+        let comment_len = u16_at(bytes, at + 32)? as usize;
+        let local = u32_at(bytes, at + 42)? as usize;
+        let name = String::from_utf8_lossy(field(bytes, at + 46, name_len)?).into_owned();
+
+        if flags & 1 != 0 {
+            return Err(format!("{} is encrypted", name));
+        }
+        if method != 0 {
+            return Err(format!(
+                "{} is compressed, and only stored entries can be read (zip it with `zip -0`)",
+                name
+            ));
+        }
+        if u32_at(bytes, local)? != LOCAL_HEADER {
+            return Err(format!("{} is corrupt", name));
+        }
+        let start = local + 30 + u16_at(bytes, local + 26)? as usize;
+        let start = start + u16_at(bytes, local + 28)? as usize;
+        let contents = field(bytes, start, size)?.to_vec();
+        if crc32(&contents) != crc {
+            return Err(format!("{} is corrupt", name));
+        }
+
+        entries.push(Entry {
+            name,
+            modified,
+            contents,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+/// A zip archive holding `entries`, each stored without compression.
+pub fn write(entries: &[Entry]) -> Vec<u8> {
+    let mut archive: Vec<u8> = Vec::new();
+    let mut directory: Vec<u8> = Vec::new();
+
+    for entry in entries {
+        let offset = archive.len() as u32;
+        let name = entry.name.as_bytes();
+        // Version 1.0 is enough to extract stored entries
+        let mut common = Vec::new();
+        common.extend(10u16.to_le_bytes());
+        common.extend(UTF8_FLAG.to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+        common.extend(entry.modified.to_le_bytes());
+        common.extend(crc32(&entry.contents).to_le_bytes());
+        common.extend((entry.contents.len() as u32).to_le_bytes());
+        common.extend((entry.contents.len() as u32).to_le_bytes());
+        common.extend((name.len() as u16).to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+
+        archive.extend(LOCAL_HEADER.to_le_bytes());
+        archive.extend(&common);
+        archive.extend(name);
+        archive.extend(&entry.contents);
+
+        directory.extend(CENTRAL_HEADER.to_le_bytes());
+        directory.extend(20u16.to_le_bytes());
+        directory.extend(&common);
+        // Comment length, disk, internal and external attributes
+        directory.extend([0; 10]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name);
+    }
+
+    let offset = archive.len() as u32;
+    archive.extend(&directory);
+    archive.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    archive.extend([0; 4]);
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(offset.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    archive
+}